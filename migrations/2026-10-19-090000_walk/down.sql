@@ -0,0 +1,2 @@
+DROP TABLE walk_step;
+DROP TABLE walk;
@@ -0,0 +1,16 @@
+CREATE TABLE walk (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES "user"(id),
+    created TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX ON walk(user_id, created);
+
+CREATE TABLE walk_step (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    walk_id UUID NOT NULL REFERENCES walk(id),
+    position INT NOT NULL,
+    note_id UUID NOT NULL REFERENCES note(id)
+);
+
+CREATE INDEX ON walk_step(walk_id, position);
@@ -0,0 +1,2 @@
+DROP TABLE note_link_click_day;
+DROP TABLE note_view_day;
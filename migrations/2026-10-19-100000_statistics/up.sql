@@ -0,0 +1,17 @@
+CREATE TABLE note_view_day (
+    note_id UUID NOT NULL REFERENCES note(id),
+    day DATE NOT NULL,
+    view_count INT NOT NULL DEFAULT (0),
+    PRIMARY KEY (note_id, day)
+);
+
+CREATE INDEX ON note_view_day(day);
+
+CREATE TABLE note_link_click_day (
+    link_id UUID NOT NULL REFERENCES note_link(id),
+    day DATE NOT NULL,
+    click_count INT NOT NULL DEFAULT (0),
+    PRIMARY KEY (link_id, day)
+);
+
+CREATE INDEX ON note_link_click_day(day);
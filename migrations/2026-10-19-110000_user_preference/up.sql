@@ -0,0 +1,7 @@
+CREATE TABLE user_preference (
+    user_id UUID NOT NULL PRIMARY KEY REFERENCES "user"(id),
+    index_sort TEXT NOT NULL,
+    index_orphans BOOL NOT NULL,
+    index_from DATE NULL,
+    index_to DATE NULL
+);
@@ -0,0 +1,30 @@
+ALTER TABLE note
+    ADD COLUMN created_at TIMESTAMPTZ NULL,
+    ADD COLUMN updated_at TIMESTAMPTZ NULL;
+
+UPDATE note
+SET
+    created_at = history.created_at,
+    updated_at = history.updated_at
+FROM (
+    SELECT
+        note_history.note_id,
+        MIN(note_history.created) AS created_at,
+        MAX(note_history.created) AS updated_at
+    FROM note_history
+    GROUP BY note_history.note_id
+) history
+WHERE history.note_id = note.id;
+
+UPDATE note
+SET
+    created_at = NOW(),
+    updated_at = NOW()
+WHERE created_at IS NULL;
+
+ALTER TABLE note
+    ALTER COLUMN created_at SET NOT NULL,
+    ALTER COLUMN updated_at SET NOT NULL;
+
+CREATE INDEX ON note(user_id, created_at);
+CREATE INDEX ON note(user_id, updated_at);
@@ -0,0 +1,2 @@
+DROP TABLE note_seo_alias;
+ALTER TABLE note DROP COLUMN seo_name_pinned;
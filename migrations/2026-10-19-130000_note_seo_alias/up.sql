@@ -0,0 +1,12 @@
+ALTER TABLE note ADD COLUMN seo_name_pinned BOOL NOT NULL DEFAULT (false);
+
+CREATE TABLE note_seo_alias (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    note_id UUID NOT NULL REFERENCES note(id),
+    user_id UUID NOT NULL REFERENCES "user"(id),
+    seo_name TEXT NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    UNIQUE (user_id, seo_name)
+);
+
+CREATE INDEX ON note_seo_alias(note_id);
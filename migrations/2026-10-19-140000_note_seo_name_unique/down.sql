@@ -0,0 +1 @@
+DROP INDEX note_user_id_seo_name;
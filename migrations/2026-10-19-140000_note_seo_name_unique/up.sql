@@ -0,0 +1,13 @@
+-- Rename notes that ended up with the same seo_name before this was enforced
+UPDATE note
+SET seo_name = note.seo_name || '-' || LEFT(note.id::TEXT, 8)
+FROM (
+    SELECT
+        id,
+        ROW_NUMBER() OVER (PARTITION BY user_id, seo_name ORDER BY created_at, id) AS position
+    FROM note
+) duplicate
+WHERE duplicate.id = note.id
+AND duplicate.position > 1;
+
+CREATE UNIQUE INDEX note_user_id_seo_name ON note(user_id, seo_name);
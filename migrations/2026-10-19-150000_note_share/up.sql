@@ -0,0 +1,12 @@
+CREATE TABLE note_share (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    note_id UUID NOT NULL REFERENCES note(id),
+    user_id UUID NOT NULL REFERENCES "user"(id),
+    token TEXT NOT NULL UNIQUE,
+    depth INT NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    expires TIMESTAMPTZ NULL,
+    revoked BOOL NOT NULL DEFAULT (false)
+);
+
+CREATE INDEX ON note_share(note_id);
@@ -0,0 +1,14 @@
+ALTER TABLE note_seo_alias ADD COLUMN user_id UUID NULL REFERENCES "user"(id);
+UPDATE note_seo_alias SET user_id = note.user_id FROM note WHERE note.id = note_seo_alias.note_id;
+ALTER TABLE note_seo_alias ALTER COLUMN user_id SET NOT NULL;
+ALTER TABLE note_seo_alias DROP COLUMN workspace_id;
+ALTER TABLE note_seo_alias ADD UNIQUE (user_id, seo_name);
+
+ALTER TABLE walk DROP COLUMN workspace_id;
+
+DROP INDEX note_workspace_id_seo_name;
+ALTER TABLE note DROP COLUMN workspace_id;
+CREATE UNIQUE INDEX note_user_id_seo_name ON note(user_id, seo_name);
+
+DROP TABLE workspace_member;
+DROP TABLE workspace;
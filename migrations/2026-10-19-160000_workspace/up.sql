@@ -0,0 +1,46 @@
+CREATE TABLE workspace (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    name TEXT NOT NULL,
+    created TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE workspace_member (
+    workspace_id UUID NOT NULL REFERENCES workspace(id),
+    user_id UUID NOT NULL REFERENCES "user"(id),
+    role TEXT NOT NULL,
+    PRIMARY KEY (workspace_id, user_id)
+);
+
+CREATE INDEX ON workspace_member(user_id);
+
+-- Every existing user gets a personal workspace, which takes over their notes
+ALTER TABLE workspace ADD COLUMN migrated_user_id UUID NULL;
+
+INSERT INTO workspace (name, created, migrated_user_id)
+SELECT "user".name, NOW(), "user".id
+FROM "user";
+
+INSERT INTO workspace_member (workspace_id, user_id, role)
+SELECT workspace.id, workspace.migrated_user_id, 'owner'
+FROM workspace;
+
+ALTER TABLE note ADD COLUMN workspace_id UUID NULL REFERENCES workspace(id);
+UPDATE note SET workspace_id = workspace.id FROM workspace WHERE workspace.migrated_user_id = note.user_id;
+ALTER TABLE note ALTER COLUMN workspace_id SET NOT NULL;
+
+DROP INDEX note_user_id_seo_name;
+CREATE UNIQUE INDEX note_workspace_id_seo_name ON note(workspace_id, seo_name);
+
+-- Seo names are unique per workspace now, so the old names of notes are too
+ALTER TABLE note_seo_alias ADD COLUMN workspace_id UUID NULL REFERENCES workspace(id);
+UPDATE note_seo_alias SET workspace_id = workspace.id FROM workspace WHERE workspace.migrated_user_id = note_seo_alias.user_id;
+ALTER TABLE note_seo_alias ALTER COLUMN workspace_id SET NOT NULL;
+ALTER TABLE note_seo_alias DROP COLUMN user_id;
+ALTER TABLE note_seo_alias ADD UNIQUE (workspace_id, seo_name);
+
+-- Walks go through the notes of a single workspace
+ALTER TABLE walk ADD COLUMN workspace_id UUID NULL REFERENCES workspace(id);
+UPDATE walk SET workspace_id = workspace.id FROM workspace WHERE workspace.migrated_user_id = walk.user_id;
+ALTER TABLE walk ALTER COLUMN workspace_id SET NOT NULL;
+
+ALTER TABLE workspace DROP COLUMN migrated_user_id;
@@ -0,0 +1,13 @@
+-- An entry grants a single user, or every member with a role in the workspace, access to a note.
+-- Notes without any entries can be read by all members of their workspace.
+CREATE TABLE note_acl (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    note_id UUID NOT NULL REFERENCES note(id),
+    user_id UUID NULL REFERENCES "user"(id),
+    role TEXT NULL,
+    can_write BOOL NOT NULL DEFAULT (false),
+    CHECK ((user_id IS NULL) <> (role IS NULL))
+);
+
+CREATE UNIQUE INDEX note_acl_note_id_user_id ON note_acl(note_id, user_id);
+CREATE UNIQUE INDEX note_acl_note_id_role ON note_acl(note_id, role);
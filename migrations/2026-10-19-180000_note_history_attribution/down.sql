@@ -0,0 +1,4 @@
+ALTER TABLE note_history
+    DROP COLUMN user_id,
+    DROP COLUMN token_id,
+    DROP COLUMN summary;
@@ -0,0 +1,5 @@
+-- Revisions made before this migration are not attributed, since it is not known who made them
+ALTER TABLE note_history
+    ADD COLUMN user_id UUID NULL REFERENCES "user"(id),
+    ADD COLUMN token_id UUID NULL REFERENCES user_token(id),
+    ADD COLUMN summary TEXT NULL;
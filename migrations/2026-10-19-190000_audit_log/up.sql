@@ -0,0 +1,14 @@
+ALTER TABLE "user" ADD COLUMN is_admin BOOL NOT NULL DEFAULT false;
+
+-- Security-relevant account events. The user is empty for events that can not be tied to an account, like a login with an unknown name.
+CREATE TABLE audit_log (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    created TIMESTAMPTZ NOT NULL,
+    user_id UUID NULL REFERENCES "user"(id),
+    event TEXT NOT NULL,
+    ip TEXT NULL,
+    details TEXT NULL
+);
+
+CREATE INDEX ON audit_log(user_id, created);
+CREATE INDEX ON audit_log(created);
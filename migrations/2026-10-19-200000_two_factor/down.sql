@@ -0,0 +1,5 @@
+DROP TABLE user_recovery_code;
+
+ALTER TABLE "user"
+    DROP COLUMN totp_secret,
+    DROP COLUMN totp_last_step;
@@ -0,0 +1,14 @@
+-- The base32 TOTP secret of users that enabled two-factor authentication,
+-- and the last time step a code was accepted for, so every code can only be used once.
+ALTER TABLE "user"
+    ADD COLUMN totp_secret TEXT NULL,
+    ADD COLUMN totp_last_step BIGINT NULL;
+
+-- Single-use codes to log in with when the authenticator is lost. Only a hash of every code is stored.
+CREATE TABLE user_recovery_code (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES "user"(id),
+    code_hash TEXT NOT NULL
+);
+
+CREATE INDEX ON user_recovery_code(user_id);
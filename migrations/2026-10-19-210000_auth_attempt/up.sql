@@ -0,0 +1,13 @@
+-- Recent failed logins and registrations, to slow down and lock out clients that make too many.
+-- Rows are removed once they are too old to matter, and failed logins of a user are removed when they log in.
+CREATE TABLE auth_attempt (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    created TIMESTAMPTZ NOT NULL,
+    kind TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    user_name TEXT NULL
+);
+
+CREATE INDEX ON auth_attempt(kind, ip, created);
+CREATE INDEX ON auth_attempt(kind, user_name, created);
+CREATE INDEX ON auth_attempt(created);
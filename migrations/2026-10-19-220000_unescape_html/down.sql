@@ -0,0 +1,24 @@
+CREATE FUNCTION pg_temp.escape_html(value TEXT) RETURNS TEXT AS $$
+    -- `&` goes first, so the entities that are added are not escaped again
+    SELECT replace(replace(replace(replace(replace(replace(replace(value,
+        '&', '&amp;'),
+        '<', '&lt;'),
+        '>', '&gt;'),
+        '"', '&quot;'),
+        '''', '&#x27;'),
+        '/', '&#x2F;'),
+        '`', '&#96;')
+$$ LANGUAGE SQL IMMUTABLE;
+
+UPDATE note SET
+    title = pg_temp.escape_html(title),
+    body = pg_temp.escape_html(body);
+
+UPDATE note_history SET
+    title = pg_temp.escape_html(title),
+    body = pg_temp.escape_html(body),
+    summary = pg_temp.escape_html(summary);
+
+UPDATE workspace SET name = pg_temp.escape_html(name);
+
+UPDATE "user" SET name = pg_temp.escape_html(name);
@@ -0,0 +1,27 @@
+-- Titles, bodies and names used to be stored html-escaped, the way rocket escapes form values.
+-- They are stored as they were entered now, and escaped when they are rendered.
+-- Seo names are left alone, so existing urls keep working.
+CREATE FUNCTION pg_temp.unescape_html(value TEXT) RETURNS TEXT AS $$
+    -- `&amp;` goes last, so `&amp;lt;` turns into `&lt;` and not into `<`
+    SELECT replace(replace(replace(replace(replace(replace(replace(value,
+        '&lt;', '<'),
+        '&gt;', '>'),
+        '&quot;', '"'),
+        '&#x27;', ''''),
+        '&#x2F;', '/'),
+        '&#96;', '`'),
+        '&amp;', '&')
+$$ LANGUAGE SQL IMMUTABLE;
+
+UPDATE note SET
+    title = pg_temp.unescape_html(title),
+    body = pg_temp.unescape_html(body);
+
+UPDATE note_history SET
+    title = pg_temp.unescape_html(title),
+    body = pg_temp.unescape_html(body),
+    summary = pg_temp.unescape_html(summary);
+
+UPDATE workspace SET name = pg_temp.unescape_html(name);
+
+UPDATE "user" SET name = pg_temp.unescape_html(name);
@@ -0,0 +1,10 @@
+CREATE TABLE attachment (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    note_id UUID NOT NULL REFERENCES note(id),
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    has_thumbnail BOOL NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    UNIQUE (note_id, file_name)
+);
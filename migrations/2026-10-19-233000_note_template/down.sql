@@ -0,0 +1,2 @@
+ALTER TABLE workspace DROP COLUMN default_template_id;
+ALTER TABLE note DROP COLUMN is_template;
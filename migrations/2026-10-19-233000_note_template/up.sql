@@ -0,0 +1,2 @@
+ALTER TABLE note ADD COLUMN is_template BOOL NOT NULL DEFAULT false;
+ALTER TABLE workspace ADD COLUMN default_template_id UUID NULL REFERENCES note(id);
@@ -0,0 +1,6 @@
+DROP TABLE journal_entry;
+
+ALTER TABLE workspace DROP COLUMN journal_template_id;
+ALTER TABLE workspace DROP COLUMN journal_title_format;
+
+ALTER TABLE "user" DROP COLUMN timezone;
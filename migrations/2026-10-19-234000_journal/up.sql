@@ -0,0 +1,14 @@
+ALTER TABLE "user" ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
+
+ALTER TABLE workspace ADD COLUMN journal_title_format TEXT NOT NULL DEFAULT '%Y-%m-%d';
+ALTER TABLE workspace ADD COLUMN journal_template_id UUID NULL REFERENCES note(id);
+
+CREATE TABLE journal_entry (
+    user_id UUID NOT NULL REFERENCES "user"(id),
+    workspace_id UUID NOT NULL REFERENCES workspace(id),
+    day DATE NOT NULL,
+    note_id UUID NOT NULL REFERENCES note(id),
+    PRIMARY KEY (user_id, workspace_id, day)
+);
+
+CREATE INDEX ON journal_entry(note_id);
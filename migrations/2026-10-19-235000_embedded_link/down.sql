@@ -0,0 +1 @@
+ALTER TABLE note_link DROP COLUMN embedded;
@@ -0,0 +1 @@
+ALTER TABLE note_link ADD COLUMN embedded BOOL NOT NULL DEFAULT false;
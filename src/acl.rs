@@ -0,0 +1,114 @@
+use crate::models::note::transaction;
+use crate::models::note_acl::{Accessor, NoteAcl, NoteAclEntry};
+use crate::models::user::DatabaseUser;
+use crate::models::workspace::Workspace as DatabaseWorkspace;
+use crate::note::Note;
+use crate::workspace::Role;
+use failure::{bail, format_err};
+use uuid::Uuid;
+
+/// An entry of the access control list of a note.
+///
+/// As long as a note has no entries, every member of its workspace can read it.
+/// Once it has entries, only the user that created it and the users and roles in the list can.
+#[derive(Serialize)]
+pub struct AclEntry {
+    pub id: Uuid,
+    /// The name of the user this entry is for, if it is for a single user
+    pub user_name: Option<String>,
+    /// The role this entry is for, if it is for every member with that role
+    pub role: Option<Role>,
+    pub can_write: bool,
+}
+
+impl AclEntry {
+    fn from_database(e: NoteAclEntry) -> Result<AclEntry, failure::Error> {
+        Ok(AclEntry {
+            id: e.id,
+            user_name: e.user_name,
+            role: e.role.map(|r| r.parse()).transpose()?,
+            can_write: e.can_write,
+        })
+    }
+}
+
+/// Who an entry of an access control list is for
+pub enum Grantee<'a> {
+    /// The user with the given name
+    User(&'a str),
+    /// Every member of the workspace with the given role
+    Role(Role),
+}
+
+impl AclEntry {
+    pub fn load_by_note(
+        conn: &diesel::PgConnection,
+        note: &Note,
+    ) -> Result<Vec<AclEntry>, failure::Error> {
+        NoteAcl::load_by_note(conn, note.id)?
+            .into_iter()
+            .map(AclEntry::from_database)
+            .collect()
+    }
+
+    /// Give the grantee access to the note, replacing the access they had before.
+    ///
+    /// Fails if the accessor is not allowed to change the note, or would not be allowed to anymore afterwards.
+    pub fn save(
+        conn: &diesel::PgConnection,
+        note: &Note,
+        accessor: &Accessor,
+        grantee: Grantee,
+        can_write: bool,
+    ) -> Result<(), failure::Error> {
+        if !note.can_write(conn, accessor)? {
+            bail!("You are not allowed to change this note");
+        }
+        transaction(conn, || {
+            match grantee {
+                Grantee::User(name) => {
+                    let user = DatabaseUser::load_by_name(conn, name)?
+                        .ok_or_else(|| format_err!("User {:?} not found", name))?;
+                    if DatabaseWorkspace::load_by_member(conn, note.workspace_id, user.id)?
+                        .is_none()
+                    {
+                        bail!("User {:?} is not a member of this workspace", name);
+                    }
+                    NoteAcl::save(conn, note.id, Some(user.id), None, can_write)?;
+                }
+                Grantee::Role(role) => {
+                    NoteAcl::save(conn, note.id, None, Some(role.as_str()), can_write)?;
+                }
+            }
+            ensure_can_write(conn, note, accessor)
+        })
+    }
+
+    pub fn remove(
+        conn: &diesel::PgConnection,
+        note: &Note,
+        accessor: &Accessor,
+        id: Uuid,
+    ) -> Result<(), failure::Error> {
+        if !note.can_write(conn, accessor)? {
+            bail!("You are not allowed to change this note");
+        }
+        transaction(conn, || {
+            NoteAcl::delete(conn, id, note.id)?;
+            ensure_can_write(conn, note, accessor)
+        })
+    }
+}
+
+/// Fail if the accessor can no longer change the note, so the surrounding transaction is rolled back.
+/// This keeps users from locking themselves out by changing an access control list.
+fn ensure_can_write(
+    conn: &diesel::PgConnection,
+    note: &Note,
+    accessor: &Accessor,
+) -> Result<(), failure::Error> {
+    if !note.can_write(conn, accessor)? {
+        bail!("You would not be allowed to change this note anymore");
+    }
+    Ok(())
+}
@@ -0,0 +1,464 @@
+use crate::csrf::CsrfToken;
+use crate::models::attachment::Attachment as DatabaseAttachment;
+use crate::models::note::transaction;
+use crate::models::note_acl::Accessor;
+use crate::note::Note;
+use chrono::{DateTime, Utc};
+use failure::{bail, format_err};
+use image::imageops::FilterType;
+use image::ImageOutputFormat;
+use multipart::server::Multipart;
+use rocket::data::{self, FromDataSimple};
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::{Data, Outcome, Request, State};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Images with more pixels than this do not get a thumbnail, decoding them would take too much memory
+const MAX_THUMBNAIL_PIXELS: u64 = 40_000_000;
+/// The width and height that thumbnails fit in
+const THUMBNAIL_SIZE: u32 = 200;
+const MAX_FILE_NAME_LENGTH: usize = 100;
+/// The room that the other fields of an upload form get, besides the file
+const FORM_OVERHEAD: u64 = 64 * 1024;
+
+/// The start of the files of every content type that is recognised, other than text
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+];
+/// The content types that browsers show on the page. Other files are downloaded.
+const INLINE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+/// The content types that thumbnails are made of
+const THUMBNAIL_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif"];
+
+/// Where the files of attachments are kept. Files are stored under keys that are made from the id of the attachment,
+/// never under the name that was uploaded.
+pub trait Storage: Send + Sync {
+    fn save(&self, key: &str, data: &[u8]) -> Result<(), failure::Error>;
+    fn load(&self, key: &str) -> Result<Vec<u8>, failure::Error>;
+    fn delete(&self, key: &str) -> Result<(), failure::Error>;
+}
+
+/// Stores the files in a directory on the local filesystem
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(root: impl Into<PathBuf>) -> FileStorage {
+        FileStorage { root: root.into() }
+    }
+}
+
+impl Storage for FileStorage {
+    fn save(&self, key: &str, data: &[u8]) -> Result<(), failure::Error> {
+        fs::create_dir_all(&self.root)?;
+        fs::write(self.root.join(key), data)?;
+        Ok(())
+    }
+
+    fn load(&self, key: &str) -> Result<Vec<u8>, failure::Error> {
+        Ok(fs::read(self.root.join(key))?)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), failure::Error> {
+        match fs::remove_file(self.root.join(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The files that are attached to notes, and the limit on their size.
+///
+/// Notes can refer to their attachments in markdown as `attachment:<file name>`, see `markdown::Context`.
+/// The storage and the limit are read from `Rocket.toml`, see `from_config`.
+pub struct Attachments {
+    storage: Box<dyn Storage>,
+    max_size: u64,
+}
+
+/// A file that is attached to a note
+#[derive(Serialize)]
+pub struct Attachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub has_thumbnail: bool,
+    /// Whether the file is shown on the page instead of downloaded
+    pub inline: bool,
+    pub created: DateTime<Utc>,
+}
+
+impl From<DatabaseAttachment> for Attachment {
+    fn from(a: DatabaseAttachment) -> Attachment {
+        Attachment {
+            inline: INLINE_TYPES.contains(&&*a.content_type),
+            file_name: a.file_name,
+            content_type: a.content_type,
+            size: a.size,
+            has_thumbnail: a.has_thumbnail,
+            created: a.created,
+        }
+    }
+}
+
+impl Attachments {
+    pub const DEFAULT_DIR: &'static str = "attachments";
+    pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+    pub fn new(storage: Box<dyn Storage>, max_size: u64) -> Attachments {
+        Attachments { storage, max_size }
+    }
+
+    /// Store the files in the `attachment_dir` directory, and limit them to `attachment_max_size` bytes,
+    /// falling back to the defaults for the settings that are missing
+    pub fn from_config(config: &rocket::Config) -> Result<Attachments, failure::Error> {
+        let dir = match config.get_str("attachment_dir") {
+            Ok(dir) => dir,
+            Err(rocket::config::ConfigError::Missing(_)) => Attachments::DEFAULT_DIR,
+            Err(e) => bail!("Invalid setting attachment_dir: {}", e),
+        };
+        let max_size = match config.get_int("attachment_max_size") {
+            Ok(value) if value > 0 => value as u64,
+            Ok(value) => bail!(
+                "Invalid setting attachment_max_size: {} is not positive",
+                value
+            ),
+            Err(rocket::config::ConfigError::Missing(_)) => Attachments::DEFAULT_MAX_SIZE,
+            Err(e) => bail!("Invalid setting attachment_max_size: {}", e),
+        };
+        Ok(Attachments::new(Box::new(FileStorage::new(dir)), max_size))
+    }
+
+    /// The largest file that can be attached, in bytes
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    pub fn load_by_note(
+        conn: &diesel::PgConnection,
+        note: &Note,
+    ) -> Result<Vec<Attachment>, failure::Error> {
+        Ok(DatabaseAttachment::load_by_note(conn, note.id)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Attach a file to the note, replacing the attachment with the same name.
+    ///
+    /// The name is cleaned up first, the attachment that is returned has the name it is stored under.
+    /// Fails if the accessor is not allowed to change the note, or the file is too large.
+    pub fn upload(
+        &self,
+        conn: &diesel::PgConnection,
+        note: &Note,
+        accessor: &Accessor,
+        file_name: &str,
+        data: &[u8],
+    ) -> Result<Attachment, failure::Error> {
+        if !note.can_write(conn, accessor)? {
+            bail!("You are not allowed to change this note");
+        }
+        let file_name = clean_file_name(file_name);
+        if file_name.is_empty() {
+            bail!("Choose a file to upload");
+        }
+        if data.len() as u64 > self.max_size {
+            bail!(
+                "The file is larger than the limit of {} KiB",
+                self.max_size / 1024
+            );
+        }
+        let content_type = sniff_content_type(data);
+        let thumbnail = if THUMBNAIL_TYPES.contains(&content_type) {
+            thumbnail(data)
+        } else {
+            None
+        };
+
+        let id = Uuid::new_v4();
+        self.storage.save(&id.to_string(), data)?;
+        if let Some(thumbnail) = &thumbnail {
+            self.storage.save(&thumbnail_key(id), thumbnail)?;
+        }
+        let result = transaction(conn, || {
+            let replaced = DatabaseAttachment::load_by_name(conn, note.id, &file_name)?;
+            if let Some(replaced) = &replaced {
+                DatabaseAttachment::delete(conn, replaced.id)?;
+            }
+            let attachment = DatabaseAttachment::create(
+                conn,
+                id,
+                note.id,
+                &file_name,
+                content_type,
+                data.len() as i64,
+                thumbnail.is_some(),
+            )?;
+            Ok((attachment, replaced))
+        });
+        match result {
+            Ok((attachment, replaced)) => {
+                if let Some(replaced) = replaced {
+                    self.remove_stored(replaced.id, replaced.has_thumbnail);
+                }
+                Ok(attachment.into())
+            }
+            Err(e) => {
+                self.remove_stored(id, thumbnail.is_some());
+                Err(e)
+            }
+        }
+    }
+
+    /// Remove the attachment with the given name from the note.
+    /// Fails if the accessor is not allowed to change the note.
+    pub fn delete(
+        &self,
+        conn: &diesel::PgConnection,
+        note: &Note,
+        accessor: &Accessor,
+        file_name: &str,
+    ) -> Result<(), failure::Error> {
+        if !note.can_write(conn, accessor)? {
+            bail!("You are not allowed to change this note");
+        }
+        let attachment = DatabaseAttachment::load_by_name(conn, note.id, file_name)?
+            .ok_or_else(|| format_err!("The note has no attachment {:?}", file_name))?;
+        DatabaseAttachment::delete(conn, attachment.id)?;
+        self.remove_stored(attachment.id, attachment.has_thumbnail);
+        Ok(())
+    }
+
+    /// Load the file of an attachment of the note, or its thumbnail.
+    /// Returns `None` if there is no such attachment, or it has no thumbnail.
+    pub fn load_file(
+        &self,
+        conn: &diesel::PgConnection,
+        note: &Note,
+        file_name: &str,
+        thumbnail: bool,
+    ) -> Result<Option<AttachmentFile>, failure::Error> {
+        let attachment = match DatabaseAttachment::load_by_name(conn, note.id, file_name)? {
+            Some(attachment) => attachment,
+            None => return Ok(None),
+        };
+        let (key, content_type) = match (thumbnail, attachment.has_thumbnail) {
+            (false, _) => (attachment.id.to_string(), attachment.content_type),
+            (true, true) => (thumbnail_key(attachment.id), "image/png".to_owned()),
+            (true, false) => return Ok(None),
+        };
+        Ok(Some(AttachmentFile {
+            data: self.storage.load(&key)?,
+            inline: INLINE_TYPES.contains(&&*content_type),
+            content_type,
+            file_name: attachment.file_name,
+        }))
+    }
+
+    /// Remove the stored files of attachments whose rows are deleted.
+    ///
+    /// This happens after the rows are gone, so a failure only leaves a file behind, which is logged.
+    pub fn remove_files(&self, attachments: &[DatabaseAttachment]) {
+        for attachment in attachments {
+            self.remove_stored(attachment.id, attachment.has_thumbnail);
+        }
+    }
+
+    fn remove_stored(&self, id: Uuid, has_thumbnail: bool) {
+        let mut keys = vec![id.to_string()];
+        if has_thumbnail {
+            keys.push(thumbnail_key(id));
+        }
+        for key in keys {
+            if let Err(e) = self.storage.delete(&key) {
+                eprintln!("Could not remove attachment file {}: {}", key, e);
+            }
+        }
+    }
+}
+
+fn thumbnail_key(id: Uuid) -> String {
+    format!("{}-thumbnail", id)
+}
+
+/// Keep the last part of a path, with only letters, digits, `.`, `-` and `_`, so the name can be put in urls and markdown as it is
+pub fn clean_file_name(name: &str) -> String {
+    let name = name.rsplit(|c| c == '/' || c == '\\').next().unwrap_or("");
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(MAX_FILE_NAME_LENGTH)
+        .collect();
+    cleaned.trim_start_matches('.').to_owned()
+}
+
+/// Whether the name is one that attachments can be stored under, see `clean_file_name`
+pub fn is_valid_file_name(name: &str) -> bool {
+    !name.is_empty() && clean_file_name(name) == name
+}
+
+/// Recognise the type of a file by its content, instead of trusting the name or the type the browser sent
+fn sniff_content_type(data: &[u8]) -> &'static str {
+    if let Some((_, content_type)) = SIGNATURES.iter().find(|(s, _)| data.starts_with(s)) {
+        return content_type;
+    }
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.contains('\0') => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A PNG of the image that fits in `THUMBNAIL_SIZE`, or `None` if the image can not be decoded or is too large
+fn thumbnail(data: &[u8]) -> Option<Vec<u8>> {
+    let reader = image::io::Reader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?;
+    let (width, height) = reader.into_dimensions().ok()?;
+    if u64::from(width) * u64::from(height) > MAX_THUMBNAIL_PIXELS {
+        return None;
+    }
+    let image = image::load_from_memory(data).ok()?;
+    let thumbnail = if width > THUMBNAIL_SIZE || height > THUMBNAIL_SIZE {
+        image.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle)
+    } else {
+        image
+    };
+    let mut png = Vec::new();
+    thumbnail.write_to(&mut png, ImageOutputFormat::Png).ok()?;
+    Some(png)
+}
+
+/// The file of an attachment, as it is sent to the browser
+pub struct AttachmentFile {
+    pub file_name: String,
+    pub content_type: String,
+    /// Whether the browser shows the file, instead of downloading it
+    pub inline: bool,
+    pub data: Vec<u8>,
+}
+
+impl<'r> Responder<'r> for AttachmentFile {
+    fn respond_to(self, _: &Request) -> response::Result<'r> {
+        let content_type =
+            ContentType::parse_flexible(&self.content_type).unwrap_or(ContentType::Binary);
+        let disposition = if self.inline { "inline" } else { "attachment" };
+        Response::build()
+            .header(content_type)
+            .raw_header("X-Content-Type-Options", "nosniff")
+            .raw_header(
+                "Content-Disposition",
+                format!("{}; filename=\"{}\"", disposition, self.file_name),
+            )
+            .sized_body(Cursor::new(self.data))
+            .ok()
+    }
+}
+
+/// A file that was uploaded with a `multipart/form-data` form, in a `file` field.
+///
+/// Like `CsrfForm`, the form has to submit the token of the browser in a `csrf_token` field, before the file.
+/// Files that are larger than the limit are cut off after the limit, so `Attachments::upload` refuses them.
+/// The rest of the form is not read then, so a file that is too large without a token before it fails with 413.
+pub struct Upload {
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+impl FromDataSimple for Upload {
+    type Error = &'static str;
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Upload, &'static str> {
+        let boundary = match request.content_type() {
+            Some(ct) if ct.top() == "multipart" && ct.sub() == "form-data" => ct
+                .params()
+                .find(|(key, _)| *key == "boundary")
+                .map(|(_, value)| value.to_owned()),
+            _ => return Outcome::Forward(data),
+        };
+        let boundary = match boundary {
+            Some(boundary) => boundary,
+            None => return Outcome::Failure((Status::BadRequest, "The form has no boundary")),
+        };
+        let max_size = match request.guard::<State<Attachments>>() {
+            Outcome::Success(attachments) => attachments.max_size,
+            _ => {
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    "Attachments are not configured",
+                ))
+            }
+        };
+
+        let mut multipart =
+            Multipart::with_body(data.open().take(max_size + 1 + FORM_OVERHEAD), boundary);
+        let mut csrf_token = None;
+        let mut upload = Upload {
+            file_name: String::new(),
+            data: Vec::new(),
+        };
+        loop {
+            let mut field = match multipart.read_entry() {
+                Ok(Some(field)) => field,
+                Ok(None) => break,
+                // The rest of a file that is too large is not read
+                Err(_) if upload.data.len() as u64 > max_size => break,
+                Err(_) => return Outcome::Failure((Status::BadRequest, "The form is malformed")),
+            };
+            let read = match &*field.headers.name {
+                "csrf_token" => {
+                    let mut token = String::new();
+                    let read = field.data.by_ref().take(1024).read_to_string(&mut token);
+                    csrf_token = Some(token);
+                    read.map(|_| ())
+                }
+                "file" => {
+                    upload.file_name = field.headers.filename.clone().unwrap_or_default();
+                    let read = field
+                        .data
+                        .by_ref()
+                        .take(max_size + 1)
+                        .read_to_end(&mut upload.data);
+                    read.map(|_| ())
+                }
+                _ => Ok(()),
+            };
+            if read.is_err() {
+                return Outcome::Failure((Status::BadRequest, "The form is malformed"));
+            }
+            if upload.data.len() as u64 > max_size {
+                break;
+            }
+        }
+
+        let too_large = upload.data.len() as u64 > max_size;
+        match csrf_token {
+            Some(token) if CsrfToken::of(request).matches(&token) => Outcome::Success(upload),
+            Some(_) => Outcome::Failure((Status::Forbidden, "The CSRF token is invalid")),
+            // The token may have come after the part of the form that was not read
+            None if too_large => {
+                Outcome::Failure((Status::PayloadTooLarge, "The file is too large"))
+            }
+            None => Outcome::Failure((Status::Forbidden, "The form has no CSRF token")),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::models::audit_log::{AuditLog, AuditLogEntry};
+use chrono::{DateTime, Utc};
+use failure::bail;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// The amount of audit log entries that are shown per page
+pub const PAGE_SIZE: u64 = 50;
+
+/// A security-relevant account event
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    LoginSucceeded,
+    /// A login with a wrong password, or with the name of a user that does not exist
+    LoginFailed,
+    /// A login that was refused because there were too many failed logins for the user or from the address
+    LoginThrottled,
+    Registered,
+    /// A session token was handed out to a browser
+    SessionCreated,
+    /// A session token was deactivated by logging out
+    SessionRevoked,
+    /// A read-only link to a note was created
+    ShareCreated,
+    ShareRevoked,
+    /// Notes were published to a static website
+    Exported,
+    /// The user was made an administrator
+    AdminGranted,
+    /// The password was changed, which also ends all other sessions
+    PasswordChanged,
+    /// The account was deleted, together with its notes, workspaces and sessions
+    AccountDeleted,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+}
+
+impl AuditEvent {
+    pub const ALL: [AuditEvent; 14] = [
+        AuditEvent::LoginSucceeded,
+        AuditEvent::LoginFailed,
+        AuditEvent::LoginThrottled,
+        AuditEvent::Registered,
+        AuditEvent::SessionCreated,
+        AuditEvent::SessionRevoked,
+        AuditEvent::ShareCreated,
+        AuditEvent::ShareRevoked,
+        AuditEvent::Exported,
+        AuditEvent::AdminGranted,
+        AuditEvent::PasswordChanged,
+        AuditEvent::AccountDeleted,
+        AuditEvent::TwoFactorEnabled,
+        AuditEvent::TwoFactorDisabled,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditEvent::LoginSucceeded => "login_succeeded",
+            AuditEvent::LoginFailed => "login_failed",
+            AuditEvent::LoginThrottled => "login_throttled",
+            AuditEvent::Registered => "registered",
+            AuditEvent::SessionCreated => "session_created",
+            AuditEvent::SessionRevoked => "session_revoked",
+            AuditEvent::ShareCreated => "share_created",
+            AuditEvent::ShareRevoked => "share_revoked",
+            AuditEvent::Exported => "exported",
+            AuditEvent::AdminGranted => "admin_granted",
+            AuditEvent::PasswordChanged => "password_changed",
+            AuditEvent::AccountDeleted => "account_deleted",
+            AuditEvent::TwoFactorEnabled => "two_factor_enabled",
+            AuditEvent::TwoFactorDisabled => "two_factor_disabled",
+        }
+    }
+
+    /// Write this event to the audit log.
+    ///
+    /// `user_id` is the user the event is about, if it can be tied to one. `ip` is the address the request came from, if there was a request.
+    pub fn record(
+        self,
+        conn: &diesel::PgConnection,
+        user_id: Option<Uuid>,
+        ip: Option<&str>,
+        details: Option<&str>,
+    ) -> Result<(), failure::Error> {
+        AuditLog::create(conn, user_id, self.as_str(), ip, details)
+    }
+}
+
+impl FromStr for AuditEvent {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<AuditEvent, failure::Error> {
+        match AuditEvent::ALL.iter().find(|e| e.as_str() == s) {
+            Some(event) => Ok(*event),
+            None => bail!("Unknown audit event {:?}", s),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub created: DateTime<Utc>,
+    /// The name of the user this event is about, if any
+    pub user_name: Option<String>,
+    pub event: AuditEvent,
+    pub ip: Option<String>,
+    pub details: Option<String>,
+}
+
+impl AuditEntry {
+    fn from_database(e: AuditLogEntry) -> Result<AuditEntry, failure::Error> {
+        Ok(AuditEntry {
+            id: e.id,
+            created: e.created,
+            user_name: e.user_name,
+            event: e.event.parse()?,
+            ip: e.ip,
+            details: e.details,
+        })
+    }
+
+    /// Load a page of the audit log, newest first.
+    ///
+    /// If `user_id` is given, only the events about that user are loaded. Otherwise the events of every user are.
+    pub fn load(
+        conn: &diesel::PgConnection,
+        user_id: Option<Uuid>,
+        event: Option<AuditEvent>,
+        page: u64,
+    ) -> Result<Vec<AuditEntry>, failure::Error> {
+        // Pages past the end of what the database can count are empty, like the ones past the last entry
+        let offset = i64::try_from((page.max(1) - 1).saturating_mul(PAGE_SIZE)).unwrap_or(i64::MAX);
+        AuditLog::load(
+            conn,
+            user_id,
+            event.map(AuditEvent::as_str),
+            offset,
+            PAGE_SIZE as i64,
+        )?
+        .into_iter()
+        .map(AuditEntry::from_database)
+        .collect()
+    }
+}
@@ -0,0 +1,162 @@
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rocket::data::{self, FromData, Transform, Transformed};
+use rocket::http::{Cookie, Status};
+use rocket::request::{self, FormItem, FormItems, FromForm, FromRequest};
+use rocket::{Data, Outcome, Request};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+use std::io::Read;
+use std::ops::Deref;
+
+/// The private cookie that holds the token of the browser
+const COOKIE: &str = "CSRF";
+/// The hidden field that every form submits the token in
+const FIELD: &str = "csrf_token";
+const TOKEN_LENGTH: usize = 32;
+/// The size limit of forms when `limits.forms` is not configured, the same as rocket uses
+const DEFAULT_FORM_LIMIT: u64 = 32 * 1024;
+
+/// The token that forms have to submit, so requests that other websites make on behalf of a user are refused.
+///
+/// Every browser gets a random token in a private cookie, which `load` reads before the request is handled.
+/// Pages render the token into a hidden `csrf_token` field of their forms with `render`,
+/// and `CsrfForm` only accepts forms that submit the same token.
+#[derive(Clone)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    /// Load the token from the cookie of the browser, or give the browser a new one
+    pub fn load(request: &Request) {
+        let mut cookies = request.cookies();
+        let token = match cookies.get_private(COOKIE) {
+            Some(cookie) => cookie.value().to_owned(),
+            None => {
+                let token: String = rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(TOKEN_LENGTH)
+                    .collect();
+                cookies.add_private(Cookie::new(COOKIE, token.clone()));
+                token
+            }
+        };
+        drop(cookies);
+        request.local_cache(|| CsrfToken(token));
+    }
+
+    /// The token of the current request. It is empty if it was not loaded, which makes every form fail.
+    pub(crate) fn of(request: &Request) -> CsrfToken {
+        request.local_cache(|| CsrfToken(String::new())).clone()
+    }
+
+    pub(crate) fn matches(&self, submitted: &str) -> bool {
+        // Compare every byte, so the time this takes does not tell how much of the token is right
+        !self.0.is_empty()
+            && self.0.len() == submitted.len()
+            && self
+                .0
+                .bytes()
+                .zip(submitted.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    /// Render a template with the token available as `csrf_token`
+    pub fn render<C: Serialize>(&self, name: &'static str, context: C) -> Template {
+        Template::render(
+            name,
+            CsrfContext {
+                context,
+                csrf_token: &self.0,
+            },
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct CsrfContext<'a, C> {
+    #[serde(flatten)]
+    context: C,
+    csrf_token: &'a str,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for CsrfToken {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<CsrfToken, ()> {
+        Outcome::Success(CsrfToken::of(request))
+    }
+}
+
+/// A form that was submitted with the token of the browser. Other forms are refused with `403 Forbidden`.
+pub struct CsrfForm<T>(T);
+
+impl<T> CsrfForm<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for CsrfForm<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<'f, T: for<'a> FromForm<'a>> FromData<'f> for CsrfForm<T> {
+    type Error = &'static str;
+    type Owned = String;
+    type Borrowed = str;
+
+    fn transform(request: &Request, data: Data) -> Transform<data::Outcome<String, &'static str>> {
+        if !request.content_type().map_or(false, |ct| ct.is_form()) {
+            return Transform::Borrowed(Outcome::Forward(data));
+        }
+        let limit = request.limits().get("forms").unwrap_or(DEFAULT_FORM_LIMIT);
+        let mut form = String::new();
+        let outcome = match data.open().take(limit).read_to_string(&mut form) {
+            Ok(_) => Outcome::Success(form),
+            Err(_) => Outcome::Failure((Status::InternalServerError, "Could not read the form")),
+        };
+        Transform::Borrowed(outcome)
+    }
+
+    fn from_data(
+        request: &Request,
+        outcome: Transformed<'f, Self>,
+    ) -> data::Outcome<Self, &'static str> {
+        let form = outcome.borrowed()?;
+        let mut raw_items = FormItems::from(form);
+        let mut items: Vec<FormItem> = raw_items.by_ref().collect();
+        if !raw_items.completed() {
+            return Outcome::Failure((Status::BadRequest, "The form is malformed"));
+        }
+        let submitted = match items.iter().position(|item| item.key == FIELD) {
+            Some(index) => items.remove(index).value,
+            None => return Outcome::Failure((Status::Forbidden, "The form has no CSRF token")),
+        };
+        if !CsrfToken::of(request).matches(submitted) {
+            return Outcome::Failure((Status::Forbidden, "The CSRF token is invalid"));
+        }
+        let value = T::from_form(&mut FormItems::from(&items[..]), true);
+        match value {
+            Ok(value) => Outcome::Success(CsrfForm(value)),
+            Err(_) => Outcome::Failure((Status::UnprocessableEntity, "The form is invalid")),
+        }
+    }
+}
+
+/// The fields of a form that only submits the token, for buttons that do not send anything else
+pub struct NoFields;
+
+impl<'f> FromForm<'f> for NoFields {
+    type Error = ();
+
+    fn from_form(items: &mut FormItems<'f>, strict: bool) -> Result<NoFields, ()> {
+        if strict && items.next().is_some() {
+            return Err(());
+        }
+        Ok(NoFields)
+    }
+}
@@ -0,0 +1,388 @@
+use pulldown_cmark::escape::escape_html;
+
+/// The syntax of a language, as far as it is needed to color keywords, strings, comments and numbers
+struct Language {
+    /// The names that fenced code blocks use for the language, like ```` ```rust ````
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    /// Whether `SELECT` and `select` are both keywords
+    case_sensitive: bool,
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        names: &["rust", "rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+            "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+            "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        // Not `'`, that also starts lifetimes
+        quotes: &['"'],
+    },
+    Language {
+        names: &["c", "h", "cpp", "c++", "hpp", "cs", "csharp", "java"],
+        keywords: &[
+            "abstract",
+            "auto",
+            "bool",
+            "break",
+            "case",
+            "catch",
+            "char",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "double",
+            "else",
+            "enum",
+            "extends",
+            "extern",
+            "false",
+            "final",
+            "float",
+            "for",
+            "goto",
+            "if",
+            "implements",
+            "import",
+            "int",
+            "interface",
+            "long",
+            "namespace",
+            "new",
+            "null",
+            "nullptr",
+            "package",
+            "private",
+            "protected",
+            "public",
+            "return",
+            "short",
+            "signed",
+            "sizeof",
+            "static",
+            "struct",
+            "switch",
+            "template",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "typedef",
+            "union",
+            "unsigned",
+            "using",
+            "var",
+            "virtual",
+            "void",
+            "volatile",
+            "while",
+        ],
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Language {
+        names: &["javascript", "js", "typescript", "ts", "json"],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "null",
+            "return",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "type",
+            "typeof",
+            "undefined",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Language {
+        names: &["go"],
+        keywords: &[
+            "break",
+            "case",
+            "chan",
+            "const",
+            "continue",
+            "default",
+            "defer",
+            "else",
+            "false",
+            "for",
+            "func",
+            "go",
+            "if",
+            "import",
+            "interface",
+            "map",
+            "nil",
+            "package",
+            "range",
+            "return",
+            "select",
+            "struct",
+            "switch",
+            "true",
+            "type",
+            "var",
+        ],
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Language {
+        names: &["python", "py"],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+            "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+            "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return",
+            "True", "try", "while", "with", "yield",
+        ],
+        case_sensitive: true,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Language {
+        names: &["sh", "bash", "shell", "zsh"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "until", "while",
+        ],
+        case_sensitive: true,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Language {
+        names: &["sql", "psql"],
+        keywords: &[
+            "alter",
+            "and",
+            "as",
+            "asc",
+            "by",
+            "create",
+            "delete",
+            "desc",
+            "distinct",
+            "drop",
+            "exists",
+            "from",
+            "group",
+            "having",
+            "in",
+            "index",
+            "insert",
+            "into",
+            "is",
+            "join",
+            "left",
+            "limit",
+            "not",
+            "null",
+            "on",
+            "or",
+            "order",
+            "primary",
+            "references",
+            "select",
+            "set",
+            "table",
+            "union",
+            "update",
+            "values",
+            "where",
+        ],
+        case_sensitive: false,
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['\''],
+    },
+];
+
+/// Color the code of a fenced code block, by wrapping keywords, strings, comments and numbers in
+/// `<span class="hl-...">` elements. Returns `None` if the language is not known.
+///
+/// This is a simple tokenizer instead of a full parser, it is only meant to make code easier to read.
+pub fn highlight(language: &str, code: &str) -> Option<String> {
+    let language = LANGUAGES
+        .iter()
+        .find(|l| l.names.contains(&&*language.to_lowercase()))?;
+    let mut html = String::new();
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let (class, length) = if let Some(length) = comment_length(language, rest) {
+            (Some("hl-comment"), length)
+        } else if language.quotes.contains(&c) {
+            (Some("hl-string"), string_length(rest, c))
+        } else if c.is_ascii_digit() {
+            (Some("hl-number"), number_length(rest))
+        } else if c.is_alphabetic() || c == '_' {
+            let length = word_length(rest);
+            let word = &rest[..length];
+            let is_keyword = if language.case_sensitive {
+                language.keywords.contains(&word)
+            } else {
+                language.keywords.contains(&&*word.to_lowercase())
+            };
+            (if is_keyword { Some("hl-keyword") } else { None }, length)
+        } else {
+            (None, c.len_utf8())
+        };
+        let (token, remainder) = rest.split_at(length);
+        match class {
+            Some(class) => {
+                html.push_str("<span class=\"");
+                html.push_str(class);
+                html.push_str("\">");
+                escape_html(&mut html, token).unwrap();
+                html.push_str("</span>");
+            }
+            None => escape_html(&mut html, token).unwrap(),
+        }
+        rest = remainder;
+    }
+    Some(html)
+}
+
+/// The length of the comment that `code` starts with, if it starts with one
+fn comment_length(language: &Language, code: &str) -> Option<usize> {
+    if language.line_comments.iter().any(|c| code.starts_with(c)) {
+        return Some(code.find('\n').unwrap_or_else(|| code.len()));
+    }
+    let (start, end) = language.block_comment?;
+    if !code.starts_with(start) {
+        return None;
+    }
+    Some(
+        code[start.len()..]
+            .find(end)
+            .map_or(code.len(), |i| start.len() + i + end.len()),
+    )
+}
+
+/// The length of the string that starts with the `quote` at the start of `code`, up to and including the closing quote
+fn string_length(code: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in code.char_indices().skip(1) {
+        if c == quote && !escaped {
+            return i + c.len_utf8();
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    code.len()
+}
+
+fn word_length(code: &str) -> usize {
+    code.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or_else(|| code.len())
+}
+
+/// Numbers are words that start with a digit, and can have a decimal point like `1.5`
+fn number_length(code: &str) -> usize {
+    code.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or_else(|| code.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_strings_numbers_and_comments_are_colored() {
+        let html = highlight("rust", "fn main() { let x = \"a\\\"b\"; } // 1.5").unwrap();
+        assert_eq!(
+            html,
+            "<span class=\"hl-keyword\">fn</span> main() { <span class=\"hl-keyword\">let</span> x = \
+             <span class=\"hl-string\">&quot;a\\&quot;b&quot;</span>; } <span class=\"hl-comment\">// 1.5</span>"
+        );
+        assert_eq!(
+            highlight("c", "x = 1.5; /* a */ y").unwrap(),
+            "x = <span class=\"hl-number\">1.5</span>; <span class=\"hl-comment\">/* a */</span> y"
+        );
+    }
+
+    #[test]
+    fn keywords_are_whole_words() {
+        assert_eq!(
+            highlight("rust", "format r#fn").unwrap(),
+            "format r#<span class=\"hl-keyword\">fn</span>"
+        );
+        assert_eq!(highlight("rust", "fns").unwrap(), "fns");
+    }
+
+    #[test]
+    fn case_insensitive_languages_match_keywords_in_any_case() {
+        assert_eq!(
+            highlight("SQL", "Select 'it''s'").unwrap(),
+            "<span class=\"hl-keyword\">Select</span> <span class=\"hl-string\">'it'</span><span class=\"hl-string\">'s'</span>"
+        );
+        assert_eq!(highlight("rust", "Fn").unwrap(), "Fn");
+    }
+
+    #[test]
+    fn unterminated_strings_and_comments_run_to_the_end() {
+        assert_eq!(
+            highlight("js", "'abc").unwrap(),
+            "<span class=\"hl-string\">'abc</span>"
+        );
+        assert_eq!(
+            highlight("js", "/* <b>").unwrap(),
+            "<span class=\"hl-comment\">/* &lt;b&gt;</span>"
+        );
+    }
+
+    #[test]
+    fn unknown_languages_are_not_highlighted() {
+        assert_eq!(highlight("brainfuck", "+++"), None);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::models::note::Note as DatabaseNote;
+use crate::models::note_acl::Accessor;
+use crate::models::note_link::NoteLink as DatabaseNoteLink;
+use crate::note::Note;
+use std::collections::{HashMap, HashSet, VecDeque};
+use uuid::Uuid;
+
+/// The amount of hubs and under-linked notes that are shown
+const TOP_COUNT: usize = 10;
+/// The amount of notes that are shown for every cluster
+const CLUSTER_PREVIEW_COUNT: usize = 5;
+/// Notes with this many links or less are considered under-linked
+const UNDERLINKED_MAX_LINKS: usize = 1;
+
+#[derive(Serialize)]
+pub struct Insights {
+    pub total_notes: usize,
+    pub total_links: usize,
+    pub orphans: Vec<Note>,
+    pub hubs: Vec<RankedNote>,
+    pub clusters: Vec<Cluster>,
+    pub underlinked: Vec<RankedNote>,
+}
+
+#[derive(Serialize)]
+pub struct RankedNote {
+    pub note: Note,
+    pub link_count: usize,
+    pub view_count: i32,
+}
+
+#[derive(Serialize)]
+pub struct Cluster {
+    pub size: usize,
+    pub notes: Vec<Note>,
+}
+
+impl Insights {
+    pub fn load(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<Insights, failure::Error> {
+        // Links to notes that cannot be read are skipped below, so private notes do not show up at all
+        let notes = DatabaseNote::load_all_readable(conn, accessor)?;
+        let edges = DatabaseNoteLink::load_edges_by_workspace(conn, accessor.workspace_id)?;
+
+        let view_counts: HashMap<Uuid, i32> = notes.iter().map(|n| (n.id, n.view_count)).collect();
+        let notes: HashMap<Uuid, Note> = notes.into_iter().map(|n| (n.id, n.into())).collect();
+
+        let mut neighbours: HashMap<Uuid, Vec<Uuid>> =
+            notes.keys().map(|id| (*id, Vec::new())).collect();
+        let mut total_links = 0;
+        for edge in &edges {
+            if !notes.contains_key(&edge.left) || !notes.contains_key(&edge.right) {
+                continue;
+            }
+            total_links += 1;
+            neighbours.get_mut(&edge.left).unwrap().push(edge.right);
+            neighbours.get_mut(&edge.right).unwrap().push(edge.left);
+        }
+
+        let ranked = |id: &Uuid| RankedNote {
+            note: notes[id].clone(),
+            link_count: neighbours[id].len(),
+            view_count: view_counts[id],
+        };
+
+        let mut orphans: Vec<Note> = neighbours
+            .iter()
+            .filter(|(_, n)| n.is_empty())
+            .map(|(id, _)| notes[id].clone())
+            .collect();
+        orphans.sort_by(|a, b| a.title.cmp(&b.title));
+
+        let mut hubs: Vec<RankedNote> = neighbours
+            .iter()
+            .filter(|(_, n)| !n.is_empty())
+            .map(|(id, _)| ranked(id))
+            .collect();
+        hubs.sort_by_key(|h| std::cmp::Reverse(h.link_count));
+        hubs.truncate(TOP_COUNT);
+
+        let mut underlinked: Vec<RankedNote> = neighbours
+            .iter()
+            .filter(|(id, n)| n.len() <= UNDERLINKED_MAX_LINKS && view_counts[*id] > 0)
+            .map(|(id, _)| ranked(id))
+            .collect();
+        underlinked.sort_by_key(|h| std::cmp::Reverse(h.view_count));
+        underlinked.truncate(TOP_COUNT);
+
+        let mut clusters = Vec::new();
+        let mut visited = HashSet::new();
+        for id in notes.keys() {
+            if visited.contains(id) || neighbours[id].is_empty() {
+                continue;
+            }
+            let mut members = Vec::new();
+            let mut queue = VecDeque::new();
+            visited.insert(*id);
+            queue.push_back(*id);
+            while let Some(current) = queue.pop_front() {
+                members.push(current);
+                for next in &neighbours[&current] {
+                    if visited.insert(*next) {
+                        queue.push_back(*next);
+                    }
+                }
+            }
+            members.sort_by_key(|id| std::cmp::Reverse(neighbours[id].len()));
+            clusters.push(Cluster {
+                size: members.len(),
+                notes: members
+                    .iter()
+                    .take(CLUSTER_PREVIEW_COUNT)
+                    .map(|id| notes[id].clone())
+                    .collect(),
+            });
+        }
+        clusters.sort_by_key(|c| std::cmp::Reverse(c.size));
+
+        Ok(Insights {
+            total_notes: notes.len(),
+            total_links,
+            orphans,
+            hubs,
+            clusters,
+            underlinked,
+        })
+    }
+}
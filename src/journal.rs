@@ -0,0 +1,325 @@
+use crate::models::journal::JournalEntry;
+use crate::models::note::transaction;
+use crate::note::Note;
+use crate::user::User;
+use crate::workspace::Workspace;
+use chrono::{Datelike, NaiveDate};
+use failure::bail;
+use std::fmt::Write;
+
+/// The title format of daily notes, unless the workspace is configured to use another one
+pub const DEFAULT_TITLE_FORMAT: &str = "%Y-%m-%d";
+
+/// Whether the strftime format can be used to make the titles of daily notes.
+/// Formats with fields that a date does not have, like `%H` or `%Z`, can not.
+pub fn is_valid_title_format(format: &str) -> bool {
+    let sample = NaiveDate::from_ymd(2000, 1, 1);
+    !format.trim().is_empty() && write!(String::new(), "{}", sample.format(format)).is_ok()
+}
+
+/// Find the daily note of today in the journal that the user keeps in the workspace.
+/// Returns `None` if the user has not started it yet.
+pub fn find_today(
+    conn: &diesel::PgConnection,
+    user: &User,
+    workspace: &Workspace,
+) -> Result<Option<Note>, failure::Error> {
+    let day = user.local_now().date();
+    match JournalEntry::load_by_day(conn, user.id, workspace.id, day)? {
+        Some(entry) => Note::load_by_id(conn, entry.note_id, &workspace.accessor()),
+        None => Ok(None),
+    }
+}
+
+/// Find the daily note of today, or create it if there is none yet.
+///
+/// Today is the current date in the timezone of the user. A new note is titled after the date with
+/// the title format of the workspace, starts from its journal template if it has one, and links to
+/// the daily note of the last day the user wrote one.
+pub fn today(
+    conn: &diesel::PgConnection,
+    user: &User,
+    workspace: &Workspace,
+) -> Result<Note, failure::Error> {
+    match find_or_create_today(conn, user, workspace) {
+        Ok(note) => Ok(note),
+        // Another request may have started it at the same time, and saved its entry first
+        Err(e) => match find_today(conn, user, workspace)? {
+            Some(note) => Ok(note),
+            None => Err(e),
+        },
+    }
+}
+
+/// Find or create the daily note of today, see `today`.
+/// Fails without creating anything if another request started it in the meantime.
+fn find_or_create_today(
+    conn: &diesel::PgConnection,
+    user: &User,
+    workspace: &Workspace,
+) -> Result<Note, failure::Error> {
+    let accessor = workspace.accessor();
+    let now = user.local_now();
+    let day = now.date();
+    transaction(conn, || {
+        if let Some(note) = find_today(conn, user, workspace)? {
+            return Ok(note);
+        }
+
+        let format = if is_valid_title_format(&workspace.journal_title_format) {
+            workspace.journal_title_format.as_str()
+        } else {
+            DEFAULT_TITLE_FORMAT
+        };
+        let title = day.format(format).to_string();
+        let template = match workspace.journal_template_id {
+            Some(id) => Note::load_template(conn, id, &accessor)?,
+            None => None,
+        };
+        let revision = user.revision(None);
+        let note = match template {
+            Some(template) => {
+                Note::create_from_template(conn, &title, &template, now, &accessor, &revision)?
+            }
+            None => Note::create(conn, &title, "", &accessor, &revision)?,
+        };
+
+        if let Some(previous) = JournalEntry::load_previous(conn, user.id, workspace.id, day)? {
+            if let Some(previous) = Note::load_by_id(conn, previous.note_id, &accessor)? {
+                note.create_link_to(conn, &previous, &accessor)?;
+            }
+        }
+        let entry = JournalEntry {
+            user_id: user.id,
+            workspace_id: workspace.id,
+            day,
+            note_id: note.id,
+        };
+        if !entry.save(conn)? {
+            bail!("The daily note of today was started at the same time");
+        }
+        Ok(note)
+    })
+}
+
+/// A month of the journal, laid out in weeks that start on monday
+#[derive(Serialize)]
+pub struct Calendar {
+    /// The month as "2026-10", as it is passed in the url
+    pub month: String,
+    /// The month as it is shown, like "October 2026"
+    pub title: String,
+    pub previous_month: String,
+    pub next_month: String,
+    /// Every week has seven days, which are `None` if they fall outside of the month
+    pub weeks: Vec<Vec<Option<CalendarDay>>>,
+    /// The number of days in this month that have a daily note
+    pub entries: usize,
+}
+
+#[derive(Serialize)]
+pub struct CalendarDay {
+    pub day: u32,
+    pub is_today: bool,
+    /// The daily note of this day, if the user wrote one
+    pub note: Option<Note>,
+}
+
+impl Calendar {
+    /// Load the month that starts on the given day, or the current month of the user if it is `None`
+    pub fn load(
+        conn: &diesel::PgConnection,
+        user: &User,
+        workspace: &Workspace,
+        month: Option<NaiveDate>,
+    ) -> Result<Calendar, failure::Error> {
+        let today = user.local_now().date();
+        let (previous, first, next) = months_around(month.unwrap_or(today))?;
+
+        let accessor = workspace.accessor();
+        let mut notes = Vec::new();
+        for entry in JournalEntry::load_between(conn, user.id, workspace.id, first, next)? {
+            if let Some(note) = Note::load_by_id(conn, entry.note_id, &accessor)? {
+                notes.push((entry.day, note));
+            }
+        }
+        let entries = notes.len();
+        let weeks = weeks(first, next, today, notes);
+
+        Ok(Calendar {
+            month: first.format("%Y-%m").to_string(),
+            title: first.format("%B %Y").to_string(),
+            previous_month: previous.format("%Y-%m").to_string(),
+            next_month: next.format("%Y-%m").to_string(),
+            weeks,
+            entries,
+        })
+    }
+}
+
+/// The first days of the month before the one that `day` is in, of that month itself, and of the month after it
+fn months_around(day: NaiveDate) -> Result<(NaiveDate, NaiveDate, NaiveDate), failure::Error> {
+    let first = day.with_day(1).expect("Every month has a first day");
+    let next = if first.month() == 12 {
+        NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)
+    };
+    match (first.pred_opt(), next) {
+        (Some(previous), Some(next)) => Ok((previous.with_day(1).unwrap(), first, next)),
+        _ => bail!("The journal does not go back or ahead that far"),
+    }
+}
+
+/// Lay out the days from `first` up to `next` in weeks that start on monday,
+/// with the notes of the days that have one. The notes have to be sorted by day.
+fn weeks(
+    first: NaiveDate,
+    next: NaiveDate,
+    today: NaiveDate,
+    notes: Vec<(NaiveDate, Note)>,
+) -> Vec<Vec<Option<CalendarDay>>> {
+    let mut days: Vec<Option<CalendarDay>> = (0..first.weekday().num_days_from_monday())
+        .map(|_| None)
+        .collect();
+    let mut notes = notes.into_iter().peekable();
+    let mut day = first;
+    while day < next {
+        let note = match notes.peek() {
+            Some((d, _)) if *d == day => notes.next().map(|(_, note)| note),
+            _ => None,
+        };
+        days.push(Some(CalendarDay {
+            day: day.day(),
+            is_today: day == today,
+            note,
+        }));
+        day = day.succ();
+    }
+    while days.len() % 7 != 0 {
+        days.push(None);
+    }
+    let mut weeks = Vec::new();
+    while !days.is_empty() {
+        weeks.push(days.drain(..7).collect());
+    }
+    weeks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::{self, Fixture};
+    use diesel::Connection;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(year, month, day)
+    }
+
+    /// The days of the weeks, with 0 for the days outside of the month
+    fn layout(month: NaiveDate) -> Vec<Vec<u32>> {
+        let (_, first, next) = months_around(month).unwrap();
+        weeks(first, next, month, Vec::new())
+            .iter()
+            .map(|week| {
+                week.iter()
+                    .map(|day| day.as_ref().map_or(0, |day| day.day))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn title_formats_only_use_date_fields() {
+        assert!(is_valid_title_format(DEFAULT_TITLE_FORMAT));
+        assert!(is_valid_title_format("%A %e %B %Y"));
+        assert!(is_valid_title_format("Journal %d-%m"));
+        assert!(!is_valid_title_format(""));
+        assert!(!is_valid_title_format("  "));
+        assert!(!is_valid_title_format("%Y-%m-%d %H:%M"));
+        assert!(!is_valid_title_format("%Y %Z"));
+        assert!(!is_valid_title_format("%Q"));
+    }
+
+    #[test]
+    fn months_that_start_on_monday_have_no_days_before_them() {
+        assert_eq!(
+            layout(date(2026, 6, 15)),
+            vec![
+                vec![1, 2, 3, 4, 5, 6, 7],
+                vec![8, 9, 10, 11, 12, 13, 14],
+                vec![15, 16, 17, 18, 19, 20, 21],
+                vec![22, 23, 24, 25, 26, 27, 28],
+                vec![29, 30, 0, 0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn months_that_start_on_sunday_start_at_the_end_of_the_week() {
+        assert_eq!(
+            layout(date(2026, 2, 1)),
+            vec![
+                vec![0, 0, 0, 0, 0, 0, 1],
+                vec![2, 3, 4, 5, 6, 7, 8],
+                vec![9, 10, 11, 12, 13, 14, 15],
+                vec![16, 17, 18, 19, 20, 21, 22],
+                vec![23, 24, 25, 26, 27, 28, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn months_around_the_new_year() {
+        assert_eq!(
+            months_around(date(2026, 12, 31)).unwrap(),
+            (date(2026, 11, 1), date(2026, 12, 1), date(2027, 1, 1))
+        );
+        assert_eq!(
+            months_around(date(2027, 1, 1)).unwrap(),
+            (date(2026, 12, 1), date(2027, 1, 1), date(2027, 2, 1))
+        );
+        assert_eq!(layout(date(2026, 12, 1)).concat().iter().max(), Some(&31));
+    }
+
+    #[test]
+    #[ignore]
+    fn the_daily_note_links_to_the_last_day_with_one() {
+        let conn = test_db::connect();
+        conn.begin_test_transaction().unwrap();
+        let fixture = Fixture::create(&conn);
+        let accessor = fixture.accessor();
+        let revision = fixture.user.revision(None);
+        let today = fixture.user.local_now().date();
+
+        // The days in between have no daily note
+        let mut earlier = Vec::new();
+        for days_ago in &[5, 3] {
+            let day = today - chrono::Duration::days(*days_ago);
+            let title = day.format(DEFAULT_TITLE_FORMAT).to_string();
+            let note = Note::create(&conn, &title, "", &accessor, &revision).unwrap();
+            let entry = JournalEntry {
+                user_id: fixture.user.id,
+                workspace_id: fixture.workspace.id,
+                day,
+                note_id: note.id,
+            };
+            assert!(entry.save(&conn).unwrap());
+            earlier.push(note);
+        }
+
+        let note = super::today(&conn, &fixture.user, &fixture.workspace).unwrap();
+        assert_eq!(note.title, today.format(DEFAULT_TITLE_FORMAT).to_string());
+        let linked: Vec<_> = note
+            .load_links(&conn, &accessor)
+            .unwrap()
+            .into_iter()
+            .filter_map(|link| link.note.map(|note| note.id))
+            .collect();
+        assert_eq!(linked, vec![earlier[1].id]);
+
+        // Starting it again finds the same note
+        let again = super::today(&conn, &fixture.user, &fixture.workspace).unwrap();
+        assert_eq!(again.id, note.id);
+    }
+}
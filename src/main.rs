@@ -10,16 +10,35 @@ extern crate rocket;
 #[macro_use]
 extern crate diesel;
 
+pub mod acl;
+pub mod attachment;
+pub mod audit;
+pub mod csrf;
 pub mod either;
+pub mod highlight;
+pub mod insights;
+pub mod journal;
+pub mod markdown;
 pub mod models;
 pub mod note;
+pub mod password;
+pub mod publish;
 pub mod routes;
 pub mod schema;
+pub mod share;
+pub mod statistics;
 pub mod tera_utils;
+#[cfg(test)]
+mod test_db;
+pub mod throttle;
+pub mod totp;
 pub mod user;
+pub mod walk;
+pub mod workspace;
 
-use rocket::http::RawStr;
-use rocket::request::FromFormValue;
+use diesel::Connection;
+use rocket::fairing::AdHoc;
+use rocket_contrib::databases::database_config;
 use rocket_contrib::databases::diesel::PgConnection;
 use rocket_contrib::serve::StaticFiles;
 use rocket_contrib::templates::Template;
@@ -29,33 +48,61 @@ use rocket_contrib::templates::Template;
 #[database("mindmap_db")]
 pub struct MindmapDB(PgConnection);
 
-#[derive(Debug)]
-pub struct HtmlSafeString(String);
-
-impl HtmlSafeString {
-    pub fn get(self) -> String {
-        self.0
-    }
-}
-
-impl std::ops::Deref for HtmlSafeString {
-    type Target = str;
-    fn deref(&self) -> &str {
-        self.0.as_str()
-    }
-}
-
-impl<'v> FromFormValue<'v> for HtmlSafeString {
-    type Error = &'v RawStr;
-
-    fn from_form_value(form_value: &'v RawStr) -> Result<HtmlSafeString, &'v RawStr> {
-        Ok(HtmlSafeString(form_value.html_escape().to_string()))
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let result = match args.get(1).map(String::as_str) {
+        Some("publish") => Some(publish(&args[2..])),
+        Some("admin") => Some(admin(&args[2..])),
+        _ => None,
+    };
+    if let Some(result) = result {
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
     }
-}
 
-fn main() {
     rocket::ignite()
         .attach(MindmapDB::fairing())
+        .attach(AdHoc::on_attach("Password hashing", |rocket| {
+            match crate::password::PasswordHasher::from_config(rocket.config()) {
+                Ok(hasher) => Ok(rocket.manage(hasher)),
+                Err(e) => {
+                    eprintln!("Could not configure password hashing: {}", e);
+                    Err(rocket)
+                }
+            }
+        }))
+        .attach(AdHoc::on_attach("Login throttling", |rocket| {
+            match crate::throttle::Throttle::from_config(rocket.config()) {
+                Ok(throttle) => Ok(rocket.manage(throttle)),
+                Err(e) => {
+                    eprintln!("Could not configure login throttling: {}", e);
+                    Err(rocket)
+                }
+            }
+        }))
+        .attach(AdHoc::on_attach("Attachments", |rocket| {
+            match crate::attachment::Attachments::from_config(rocket.config()) {
+                Ok(attachments) => Ok(rocket.manage(attachments)),
+                Err(e) => {
+                    eprintln!("Could not configure attachments: {}", e);
+                    Err(rocket)
+                }
+            }
+        }))
+        .attach(AdHoc::on_launch(
+            "Statistics compaction",
+            |rocket| match database_config("mindmap_db", rocket.config()) {
+                Ok(config) => crate::statistics::spawn_compaction_job(config.url.to_owned()),
+                Err(e) => eprintln!("Could not start statistics compaction: {:?}", e),
+            },
+        ))
+        .attach(AdHoc::on_request("CSRF token", |request, _| {
+            crate::csrf::CsrfToken::load(request)
+        }))
+        .manage(crate::totp::Clock::System)
         .attach(Template::custom(|engine| {
             crate::tera_utils::register(&mut engine.tera);
         }))
@@ -63,3 +110,53 @@ fn main() {
         .mount("/", StaticFiles::from("static"))
         .launch();
 }
+
+/// `mindmap_server publish <user> <workspace> <seo name> <depth> <output directory>`
+fn publish(args: &[String]) -> Result<(), failure::Error> {
+    let (user, workspace, seo_name, depth, output) = match args {
+        [user, workspace, seo_name, depth, output] => {
+            (user, workspace, seo_name, depth.parse()?, output)
+        }
+        _ => failure::bail!(
+            "Usage: mindmap_server publish <user> <workspace> <seo name> <depth> <output directory>\n\n\
+             Publishes the note <seo name> in <workspace>, given by name or id, and every note up to <depth> links away from it.\n\
+             Notes are only selected by the links around a note, selecting them by tag is not supported."
+        ),
+    };
+    let rocket = rocket::ignite();
+    let conn = establish_connection(rocket.config())?;
+    let attachments = crate::attachment::Attachments::from_config(rocket.config())?;
+    let count = crate::publish::publish(
+        &conn,
+        user,
+        workspace,
+        seo_name,
+        depth,
+        std::path::Path::new(output),
+        &attachments,
+    )?;
+    println!("Published {} notes to {}", count, output);
+    Ok(())
+}
+
+/// `mindmap_server admin <user>`
+fn admin(args: &[String]) -> Result<(), failure::Error> {
+    let name = match args {
+        [name] => name,
+        _ => failure::bail!("Usage: mindmap_server admin <user>"),
+    };
+    let conn = establish_connection(rocket::ignite().config())?;
+    let user = crate::models::user::DatabaseUser::load_by_name(&conn, name)?
+        .ok_or_else(|| failure::format_err!("User {:?} not found", name))?;
+    crate::models::user::DatabaseUser::set_admin(&conn, user.id, true)?;
+    crate::audit::AuditEvent::AdminGranted.record(&conn, Some(user.id), None, None)?;
+    println!("{} is now an administrator", name);
+    Ok(())
+}
+
+/// Connect to the database that is configured for rocket, for the commands that run without a server
+fn establish_connection(config: &rocket::Config) -> Result<PgConnection, failure::Error> {
+    let config = database_config("mindmap_db", config)
+        .map_err(|e| failure::format_err!("Could not load the database config: {:?}", e))?;
+    Ok(PgConnection::establish(config.url)?)
+}
@@ -0,0 +1,995 @@
+use ammonia::Builder;
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// The elements that rendered markdown may contain, everything else is removed from the html
+const TAGS: &[&str] = &[
+    "a",
+    "blockquote",
+    "br",
+    "code",
+    "del",
+    "div",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "img",
+    "input",
+    "li",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "span",
+    "strong",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+/// The attributes that are kept on every allowed element
+const GENERIC_ATTRIBUTES: &[&str] = &["title"];
+/// The attributes that are kept on specific elements
+const TAG_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href"]),
+    ("div", &["id"]),
+    ("h1", &["id"]),
+    ("h2", &["id"]),
+    ("h3", &["id"]),
+    ("h4", &["id"]),
+    ("h5", &["id"]),
+    ("h6", &["id"]),
+    ("img", &["src", "alt"]),
+    ("input", &["checked", "disabled"]),
+    ("ol", &["start"]),
+    ("td", &["align"]),
+    ("th", &["align"]),
+];
+/// The classes that are kept on specific elements, for the table of contents, footnotes, highlighting, math and embedded notes
+const CLASSES: &[(&str, &[&str])] = &[
+    ("a", &["heading-anchor"]),
+    (
+        "div",
+        &["embed", "embed-error", "embed-title", "footnote-definition"],
+    ),
+    (
+        "li",
+        &["toc-1", "toc-2", "toc-3", "toc-4", "toc-5", "toc-6"],
+    ),
+    ("nav", &["toc"]),
+    (
+        "span",
+        &[
+            "hl-comment",
+            "hl-keyword",
+            "hl-number",
+            "hl-string",
+            "math",
+            "math-display",
+            "math-inline",
+        ],
+    ),
+    ("sup", &["footnote-definition-label", "footnote-reference"]),
+];
+/// Links and images with other schemes, like `javascript:`, lose their url. Relative urls are kept.
+const URL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Notes with at least this many headings get a table of contents
+const TOC_MIN_HEADINGS: usize = 3;
+/// How deep notes can be embedded in notes that are embedded themselves
+pub const MAX_EMBED_DEPTH: usize = 3;
+/// Stands in for a formula or an embedded note in the markdown, so the parser leaves it alone
+const PLACEHOLDER_MARKER: char = '\u{E000}';
+/// Stands in for a task list checkbox in the rendered html, until it is replaced by a form
+const TASK_MARKER: char = '\u{E001}';
+/// Links and images to `attachment:<file name>` point to a file that is attached to the note
+const ATTACHMENT_SCHEME: &str = "attachment:";
+
+fn options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+}
+
+/// What markdown is rendered with, besides the markdown itself
+#[derive(Default)]
+pub struct Context<'a> {
+    /// The seo name of the note that is rendered, so it does not embed itself
+    pub note: Option<&'a str>,
+    /// Where the checkboxes of task lists are submitted to. Without it, they can not be clicked.
+    pub tasks: Option<TaskForm<'a>>,
+    /// The notes that `![[seo-name]]` can embed, by the seo name that is used for them.
+    /// Notes that are not in here are not embedded, so it should only contain notes that the reader is allowed to see.
+    pub embeds: HashMap<String, Embed>,
+    /// The url that the `attachment:` urls of the note are resolved against, ending with a `/`.
+    /// Without it, those urls are removed.
+    pub attachments: Option<&'a str>,
+    /// Where links to other notes (`/n/<seo-name>`) point to instead, by seo name, like the pages of a published website.
+    /// Links to notes that are not in here are kept as they are.
+    pub note_urls: HashMap<String, String>,
+}
+
+/// Where the checkboxes of task lists are submitted to, so they can be ticked off on the page.
+/// The form submits the index of the task in a `task` field.
+pub struct TaskForm<'a> {
+    pub action: &'a str,
+    pub csrf_token: &'a str,
+}
+
+/// A note that can be embedded in other notes
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Embed {
+    pub seo_name: String,
+    pub title: String,
+    /// Where the title of the embedded note links to
+    pub url: String,
+    pub body: String,
+    /// The url that the `attachment:` urls of the embedded note are resolved against, see `Context::attachments`
+    #[serde(default)]
+    pub attachments: Option<String>,
+}
+
+/// A part of the markdown that is taken out before parsing, and put back in the rendered html
+enum Placeholder {
+    Math {
+        display: bool,
+        tex: String,
+    },
+    Embed {
+        name: String,
+        heading: Option<String>,
+    },
+}
+
+/// The embedded notes that are being rendered, and the ones that were rendered before
+#[derive(Default)]
+struct Embedding {
+    /// The seo names of the embedded notes that are being rendered, from the outermost one to the current one
+    stack: Vec<String>,
+    /// For every embedded note that is being rendered, the seo names that were looked for on the stack while rendering it
+    checked: Vec<HashSet<String>>,
+    /// The html of the embeds that were rendered before, by seo name, heading and depth,
+    /// so notes that are embedded in several places are only rendered once
+    rendered: HashMap<(String, Option<String>, usize), RenderedEmbed>,
+}
+
+struct RenderedEmbed {
+    html: String,
+    /// The seo names that were looked for on the stack while rendering it
+    checked: HashSet<String>,
+    /// The stack it was rendered with. The html can be used again if the checked names are on the stack
+    /// the same way as they are now, because whether they are is all that rendering depends on.
+    stack: Vec<String>,
+}
+
+impl Embedding {
+    /// Whether the note is being rendered already, which is remembered by the embeds that are being rendered
+    fn is_rendering(&mut self, context: &Context, seo_name: &str) -> bool {
+        if let Some(checked) = self.checked.last_mut() {
+            checked.insert(seo_name.to_owned());
+        }
+        context.note == Some(seo_name) || self.stack.iter().any(|name| name == seo_name)
+    }
+
+    /// The html that the embed was rendered with before, if it can be used with the current stack
+    fn rendered(&mut self, key: &(String, Option<String>, usize)) -> Option<String> {
+        let rendered = self.rendered.get(key)?;
+        let same_stack = rendered
+            .checked
+            .iter()
+            .all(|name| rendered.stack.contains(name) == self.stack.contains(name));
+        if !same_stack {
+            return None;
+        }
+        let html = rendered.html.clone();
+        let checked = rendered.checked.clone();
+        if let Some(parent) = self.checked.last_mut() {
+            parent.extend(checked);
+        }
+        Some(html)
+    }
+}
+
+struct Heading {
+    level: u32,
+    id: String,
+    text: String,
+}
+
+/// Render markdown to html that is safe to put on a page.
+///
+/// Besides commonmark this supports tables, footnotes, strikethrough and task lists. Fenced code blocks are highlighted,
+/// headings get an id to link to, and notes with a few headings start with a table of contents.
+/// Formulas between `$` or `$$` are left as they are in `<span class="math">` elements, for KaTeX to render in the browser.
+/// `![[seo-name]]` embeds the note from `context.embeds`, and `![[seo-name#heading]]` only the section under that heading.
+///
+/// Markdown may contain html, so the rendered html is sanitised with an allow-list of elements, attributes and url schemes.
+pub fn to_html(markdown: &str, context: &Context) -> String {
+    let (rendered, checked_tasks) = render(
+        markdown,
+        context,
+        context.attachments,
+        &mut Embedding::default(),
+        "",
+    );
+    let mut sanitizer = sanitizer();
+    if !context.note_urls.is_empty() {
+        // Links in html are rewritten here, the ones in markdown already were while rendering
+        let note_urls = context.note_urls.clone();
+        sanitizer.attribute_filter(move |element, attribute, value| {
+            match (element, attribute, note_url(value, &note_urls)) {
+                ("a", "href", Some(url)) => Some(url.into()),
+                _ => Some(value.into()),
+            }
+        });
+    }
+    let cleaned = sanitizer.clean(&rendered).to_string();
+
+    // The task forms are added after sanitising, the sanitiser would remove them
+    let mut parts = cleaned.split(TASK_MARKER);
+    let mut result = parts.next().unwrap_or_default().to_owned();
+    for (index, part) in parts.enumerate() {
+        if let (Some(tasks), Some(checked)) = (&context.tasks, checked_tasks.get(index)) {
+            result.push_str(&task_form(tasks, index, *checked));
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+/// Render markdown to html that still has to be sanitised, and return the state of its tasks.
+///
+/// `embedding` has the embedded notes that are being rendered, from the outermost one to this one.
+/// Embedded notes do not get a table of contents or clickable tasks,
+/// and the ids of their headings and footnotes start with `id_prefix` so they do not clash with the outer note.
+/// `attachments` is the url that the `attachment:` urls of this note are resolved against.
+fn render(
+    markdown: &str,
+    context: &Context,
+    attachments: Option<&str>,
+    embedding: &mut Embedding,
+    id_prefix: &str,
+) -> (String, Vec<bool>) {
+    let outer = id_prefix.is_empty();
+    let (source, placeholders, _) = prepare(markdown);
+    let mut events = merge_text(Parser::new_ext(&source, options()));
+    unwrap_embeds(&mut events, &placeholders);
+    let mut events = events.into_iter();
+
+    let mut output = Vec::new();
+    let mut headings = Vec::new();
+    let mut footnotes = HashMap::new();
+    let mut checked_tasks = Vec::new();
+    while let Some(event) = events.next() {
+        match event {
+            Event::Text(text) if text.contains(PLACEHOLDER_MARKER) => {
+                for (i, part) in text.split(PLACEHOLDER_MARKER).enumerate() {
+                    let placeholder = if i % 2 == 1 {
+                        part.parse::<usize>().ok().and_then(|i| placeholders.get(i))
+                    } else {
+                        None
+                    };
+                    match placeholder {
+                        Some(placeholder) => output.push(Event::Html(
+                            render_placeholder(placeholder, context, embedding).into(),
+                        )),
+                        None if !part.is_empty() => {
+                            output.push(Event::Text(part.to_owned().into()))
+                        }
+                        None => {}
+                    }
+                }
+            }
+            Event::Start(Tag::Heading(level)) => {
+                let inner: Vec<Event> = events
+                    .by_ref()
+                    .take_while(|e| !matches!(e, Event::End(Tag::Heading(_))))
+                    .collect();
+                let text: String = inner
+                    .iter()
+                    .filter_map(|e| match e {
+                        Event::Text(text) | Event::Code(text) => Some(&**text),
+                        _ => None,
+                    })
+                    .collect();
+                let text = text.replace(PLACEHOLDER_MARKER, "");
+                let id = unique_id(id_prefix, &text, &headings);
+                output.push(Event::Html(format!("<h{} id=\"{}\">", level, id).into()));
+                output.extend(inner);
+                output.push(Event::Html(
+                    format!(
+                        "<a class=\"heading-anchor\" href=\"#{}\">#</a></h{}>\n",
+                        id, level
+                    )
+                    .into(),
+                ));
+                headings.push(Heading { level, id, text });
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                let inner: Vec<Event> = events
+                    .by_ref()
+                    .take_while(|e| !matches!(e, Event::End(Tag::CodeBlock(_))))
+                    .collect();
+                let code: String = inner
+                    .iter()
+                    .filter_map(|e| match e {
+                        Event::Text(text) => Some(&**text),
+                        _ => None,
+                    })
+                    .collect();
+                let language = info.split_whitespace().next().unwrap_or("");
+                match crate::highlight::highlight(language, &code) {
+                    Some(html) => output.push(Event::Html(
+                        format!("<pre><code>{}</code></pre>\n", html).into(),
+                    )),
+                    None => {
+                        output.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
+                            info.clone(),
+                        ))));
+                        output.extend(inner);
+                        output.push(Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(info))));
+                    }
+                }
+            }
+            Event::FootnoteReference(name) => {
+                let number = footnote_number(&mut footnotes, &name);
+                output.push(Event::Html(
+                    format!(
+                        "<sup class=\"footnote-reference\"><a href=\"#{}fn-{}\">{}</a></sup>",
+                        id_prefix,
+                        slug::slugify(&*name),
+                        number
+                    )
+                    .into(),
+                ));
+            }
+            Event::Start(Tag::FootnoteDefinition(name)) => {
+                let number = footnote_number(&mut footnotes, &name);
+                output.push(Event::Html(
+                    format!(
+                        "<div class=\"footnote-definition\" id=\"{}fn-{}\"><sup class=\"footnote-definition-label\">{}</sup>",
+                        id_prefix,
+                        slug::slugify(&*name),
+                        number
+                    )
+                    .into(),
+                ));
+            }
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                output.push(Event::Html("</div>\n".into()));
+            }
+            Event::Start(Tag::Link(kind, url, title)) => {
+                let url = match note_url(&url, &context.note_urls) {
+                    Some(url) => url.into(),
+                    None => resolve_attachment(url, attachments),
+                };
+                output.push(Event::Start(Tag::Link(kind, url, title)))
+            }
+            Event::Start(Tag::Image(kind, url, title)) => output.push(Event::Start(Tag::Image(
+                kind,
+                resolve_attachment(url, attachments),
+                title,
+            ))),
+            Event::TaskListMarker(checked) if outer && context.tasks.is_some() => {
+                checked_tasks.push(checked);
+                output.push(Event::Text(TASK_MARKER.to_string().into()));
+            }
+            event => output.push(event),
+        }
+    }
+
+    let mut rendered = String::new();
+    if outer && headings.len() >= TOC_MIN_HEADINGS {
+        rendered.push_str(&table_of_contents(&headings));
+    }
+    html::push_html(&mut rendered, output.into_iter());
+    (rendered, checked_tasks)
+}
+
+/// Tick off or reopen the task with the given index, counting from the start of the note like `to_html` does.
+/// Returns the changed markdown, or `None` if there is no such task.
+pub fn toggle_task(markdown: &str, index: usize) -> Option<String> {
+    // Find the task in the markdown that is rendered, and map it back to where it came from
+    let (source, _, origins) = prepare(markdown);
+    let (checked, range) = Parser::new_ext(&source, options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::TaskListMarker(checked) => Some((checked, range)),
+            _ => None,
+        })
+        .nth(index)?;
+    let range = origins[range.start]..origins[range.end - 1] + 1;
+    let marker = if checked { "[ ]" } else { "[x]" };
+    Some(format!(
+        "{}{}{}",
+        &markdown[..range.start],
+        marker,
+        &markdown[range.end..]
+    ))
+}
+
+/// The seo names of the notes that the markdown embeds with `![[seo-name]]`
+pub fn embedded_notes(markdown: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for placeholder in prepare(markdown).1 {
+        if let Placeholder::Embed { name, .. } = placeholder {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// The markdown as it is parsed: without marker characters, and with placeholders for its formulas and embedded notes.
+/// Also returns the position in `markdown` that every byte of the parsed markdown comes from.
+fn prepare(markdown: &str) -> (String, Vec<Placeholder>, Vec<usize>) {
+    let mut stripped = String::new();
+    let mut stripped_origins = Vec::new();
+    for (i, c) in markdown.char_indices() {
+        if c != PLACEHOLDER_MARKER && c != TASK_MARKER {
+            stripped.push(c);
+            stripped_origins.extend(i..i + c.len_utf8());
+        }
+    }
+    let (source, placeholders, origins) = extract_placeholders(&stripped);
+    let origins = origins.into_iter().map(|i| stripped_origins[i]).collect();
+    (source, placeholders, origins)
+}
+
+/// Replace the formulas and embedded notes in the markdown with a marker and their index, so the parser leaves them alone.
+/// Formulas and embedded notes in code and html are not replaced.
+/// Also returns the position in `markdown` that every byte of the result comes from, placeholders come from where they start.
+fn extract_placeholders(markdown: &str) -> (String, Vec<Placeholder>, Vec<usize>) {
+    let skipped: Vec<Range<usize>> = Parser::new_ext(markdown, options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Html(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect();
+
+    let mut source = String::new();
+    let mut origins = Vec::new();
+    let mut placeholders = Vec::new();
+    let mut copied = 0;
+    let mut i = 0;
+    while i < markdown.len() {
+        if let Some(range) = skipped.iter().find(|r| r.contains(&i)) {
+            i = range.end;
+            continue;
+        }
+        let rest = &markdown[i..];
+        if rest.starts_with('\\') {
+            // Skip the escaped character, so `\$` is not the start of a formula
+            i += rest.chars().nth(1).map_or(1, |c| 1 + c.len_utf8());
+            continue;
+        }
+        let placeholder = if let Some(display) = rest.strip_prefix("$$") {
+            display
+                .find("$$")
+                .filter(|end| !display[..*end].trim().is_empty())
+                .map(|end| {
+                    let tex = display[..end].to_owned();
+                    (Placeholder::Math { display: true, tex }, end + 4)
+                })
+        } else if rest.starts_with('$') {
+            inline_formula(rest).map(|end| {
+                let tex = rest[1..end].to_owned();
+                (
+                    Placeholder::Math {
+                        display: false,
+                        tex,
+                    },
+                    end + 1,
+                )
+            })
+        } else if rest.starts_with("![[") {
+            embed(rest)
+        } else {
+            None
+        };
+        match placeholder {
+            // A placeholder can not run into code or html
+            Some((placeholder, length))
+                if !skipped.iter().any(|r| r.start > i && r.start < i + length) =>
+            {
+                source.push_str(&markdown[copied..i]);
+                origins.extend(copied..i);
+                source.push(PLACEHOLDER_MARKER);
+                source.push_str(&placeholders.len().to_string());
+                source.push(PLACEHOLDER_MARKER);
+                origins.resize(source.len(), i);
+                placeholders.push(placeholder);
+                i += length;
+                copied = i;
+            }
+            _ => i += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    source.push_str(&markdown[copied..]);
+    origins.extend(copied..markdown.len());
+    (source, placeholders, origins)
+}
+
+/// The position of the `$` that closes the inline formula at the start of `text`.
+/// Like in pandoc, the formula has to be on one line, may not start or end with a space,
+/// and may not be followed by a digit, so amounts like `$5 and $10` are not formulas.
+fn inline_formula(text: &str) -> Option<usize> {
+    if text[1..].starts_with(char::is_whitespace) {
+        return None;
+    }
+    let line_end = text.find('\n').unwrap_or_else(|| text.len());
+    let mut previous = '$';
+    for (i, c) in text[..line_end].char_indices().skip(1) {
+        if c == '$' && i > 1 && previous != '\\' && !previous.is_whitespace() {
+            let next = text[i + 1..].chars().next();
+            return if next.map_or(false, |c| c.is_ascii_digit()) {
+                None
+            } else {
+                Some(i)
+            };
+        }
+        previous = c;
+    }
+    None
+}
+
+/// The `![[seo-name]]` or `![[seo-name#heading]]` at the start of `text`, and its length
+fn embed(text: &str) -> Option<(Placeholder, usize)> {
+    let end = text[3..].find("]]")?;
+    let target = &text[3..3 + end];
+    if target.contains(|c| c == '\n' || c == '[' || c == ']') {
+        return None;
+    }
+    let (name, heading) = match target.find('#') {
+        Some(i) => (&target[..i], Some(target[i + 1..].trim())),
+        None => (target, None),
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let heading = heading.filter(|h| !h.is_empty()).map(ToOwned::to_owned);
+    let placeholder = Placeholder::Embed {
+        name: name.to_owned(),
+        heading,
+    };
+    Some((placeholder, end + 5))
+}
+
+/// The parser can split text in several events, join them so the placeholder markers are not split up
+fn merge_text<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut merged: Vec<Event> = Vec::new();
+    for event in events {
+        if let (Some(Event::Text(previous)), Event::Text(text)) = (merged.last_mut(), &event) {
+            *previous = CowStr::from(format!("{}{}", &**previous, &**text));
+        } else {
+            merged.push(event);
+        }
+    }
+    merged
+}
+
+/// Embedded notes are blocks, so leave out the paragraph around an embedded note that is on a line of its own
+fn unwrap_embeds(events: &mut Vec<Event>, placeholders: &[Placeholder]) {
+    let mut i = 0;
+    while i + 2 < events.len() {
+        let only_embed = match &events[i..i + 3] {
+            [Event::Start(Tag::Paragraph), Event::Text(text), Event::End(Tag::Paragraph)] => text
+                .strip_prefix(PLACEHOLDER_MARKER)
+                .and_then(|t| t.strip_suffix(PLACEHOLDER_MARKER))
+                .and_then(|index| index.parse::<usize>().ok())
+                .and_then(|index| placeholders.get(index))
+                .map_or(false, |p| matches!(p, Placeholder::Embed { .. })),
+            _ => false,
+        };
+        if only_embed {
+            events.remove(i + 2);
+            events.remove(i);
+        }
+        i += 1;
+    }
+}
+
+/// The html that takes the place of a placeholder: a formula in an element that KaTeX renders, or an embedded note
+fn render_placeholder(
+    placeholder: &Placeholder,
+    context: &Context,
+    embedding: &mut Embedding,
+) -> String {
+    let (name, heading) = match placeholder {
+        Placeholder::Math { display, tex } => {
+            let class = if *display {
+                "math math-display"
+            } else {
+                "math math-inline"
+            };
+            return format!("<span class=\"{}\">{}</span>", class, escape(tex));
+        }
+        Placeholder::Embed { name, heading } => (name, heading.as_deref()),
+    };
+    // Notes deeper than this are not in `context.embeds`, so check the depth first
+    let depth = embedding.stack.len();
+    if depth >= MAX_EMBED_DEPTH {
+        return embed_error(name, "is embedded too deep");
+    }
+    let embed = match context.embeds.get(name) {
+        Some(embed) => embed,
+        None => return embed_error(name, "can not be embedded"),
+    };
+    if embedding.is_rendering(context, &embed.seo_name) {
+        return embed_error(name, "is already shown here");
+    }
+    let key = (
+        embed.seo_name.clone(),
+        heading.map(ToOwned::to_owned),
+        depth,
+    );
+    if let Some(html) = embedding.rendered(&key) {
+        return html;
+    }
+    let (body, title) = match heading {
+        Some(heading) => match section(&embed.body, heading) {
+            Some(body) => (body, format!("{} › {}", embed.title, heading)),
+            None => return embed_error(name, "has no such heading"),
+        },
+        None => (&*embed.body, embed.title.clone()),
+    };
+    let id_prefix = format!("{}--", embed.seo_name);
+    embedding.stack.push(embed.seo_name.clone());
+    embedding.checked.push(HashSet::new());
+    let (html, _) = render(
+        body,
+        context,
+        embed.attachments.as_deref(),
+        embedding,
+        &id_prefix,
+    );
+    embedding.stack.pop();
+    let checked = embedding.checked.pop().unwrap_or_default();
+    if let Some(parent) = embedding.checked.last_mut() {
+        parent.extend(checked.iter().cloned());
+    }
+    let html = format!(
+        "<div class=\"embed\"><div class=\"embed-title\"><a href=\"{}\">{}</a></div>\n{}</div>\n",
+        escape(&embed.url),
+        escape(&title),
+        html
+    );
+    embedding.rendered.insert(
+        key,
+        RenderedEmbed {
+            html: html.clone(),
+            checked,
+            stack: embedding.stack.clone(),
+        },
+    );
+    html
+}
+
+/// Point `attachment:<file name>` to the file under `attachments`. Other urls are returned as they are,
+/// and so are attachment urls that can not be resolved, which the sanitiser removes.
+fn resolve_attachment<'a>(url: CowStr<'a>, attachments: Option<&str>) -> CowStr<'a> {
+    let resolved = match (url.strip_prefix(ATTACHMENT_SCHEME), attachments) {
+        (Some(name), Some(base)) if crate::attachment::is_valid_file_name(name) => {
+            format!("{}{}", base, name)
+        }
+        _ => return url,
+    };
+    resolved.into()
+}
+
+/// The url in `note_urls` of the note that `url` links to, if it is a link to a note (`/n/<seo-name>`) in there.
+/// A `#heading` at the end of the link is kept.
+fn note_url(url: &str, note_urls: &HashMap<String, String>) -> Option<String> {
+    let (path, fragment) = match url.find('#') {
+        Some(i) => url.split_at(i),
+        None => (url, ""),
+    };
+    let seo_name = path.strip_prefix("/n/")?;
+    note_urls
+        .get(seo_name)
+        .map(|target| format!("{}{}", target, fragment))
+}
+
+fn embed_error(name: &str, problem: &str) -> String {
+    format!(
+        "<div class=\"embed embed-error\">Note {} {}</div>\n",
+        escape(name),
+        problem
+    )
+}
+
+/// The markdown under the heading with the given text or id, up to the next heading that is not below it
+fn section<'a>(markdown: &'a str, heading: &str) -> Option<&'a str> {
+    let wanted = slug::slugify(heading);
+    // The level and end of the heading that was found
+    let mut found: Option<(u32, usize)> = None;
+    // The level, range and text of the heading that is being parsed
+    let mut current: Option<(u32, Range<usize>, String)> = None;
+    for (event, range) in Parser::new_ext(markdown, options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading(level)) => {
+                if let Some((found_level, start)) = found {
+                    if level <= found_level {
+                        return Some(&markdown[start..range.start]);
+                    }
+                }
+                current = Some((level, range, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, current_text)) = &mut current {
+                    current_text.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(_)) => {
+                if let Some((level, range, text)) = current.take() {
+                    if found.is_none() && slug::slugify(&text) == wanted {
+                        found = Some((level, range.end));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    found.map(|(_, start)| &markdown[start..])
+}
+
+/// An id for a heading with the given text, that is not used by the headings before it
+fn unique_id(prefix: &str, text: &str, headings: &[Heading]) -> String {
+    let mut base = slug::slugify(text);
+    if base.is_empty() {
+        base = String::from("section");
+    }
+    base.insert_str(0, prefix);
+    let mut id = base.clone();
+    let mut counter = 1;
+    while headings.iter().any(|h| h.id == id) {
+        id = format!("{}-{}", base, counter);
+        counter += 1;
+    }
+    id
+}
+
+/// Footnotes are numbered in the order they are first used or defined
+fn footnote_number(footnotes: &mut HashMap<String, usize>, name: &str) -> usize {
+    let next = footnotes.len() + 1;
+    *footnotes.entry(name.to_owned()).or_insert(next)
+}
+
+fn table_of_contents(headings: &[Heading]) -> String {
+    let mut html = String::from("<nav class=\"toc\"><ul>");
+    for heading in headings {
+        html.push_str(&format!(
+            "<li class=\"toc-{}\"><a href=\"#{}\">{}</a></li>",
+            heading.level,
+            heading.id,
+            escape(&heading.text)
+        ));
+    }
+    html.push_str("</ul></nav>\n");
+    html
+}
+
+fn task_form(tasks: &TaskForm, index: usize, checked: bool) -> String {
+    let (icon, title) = if checked {
+        ("far fa-check-square", "Mark as not done")
+    } else {
+        ("far fa-square", "Mark as done")
+    };
+    format!(
+        "<form method=\"POST\" action=\"{}\" class=\"d-inline task-form\">\
+            <input type=\"hidden\" name=\"csrf_token\" value=\"{}\" />\
+            <input type=\"hidden\" name=\"task\" value=\"{}\" />\
+            <button type=\"submit\" class=\"btn btn-link p-0 {}\" title=\"{}\"></button>\
+        </form> ",
+        escape(tasks.action),
+        escape(tasks.csrf_token),
+        index,
+        icon,
+        title
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, text).unwrap();
+    escaped
+}
+
+fn sanitizer() -> Builder<'static> {
+    let mut builder = Builder::default();
+    builder
+        .tags(TAGS.iter().copied().collect())
+        .generic_attributes(GENERIC_ATTRIBUTES.iter().copied().collect())
+        .tag_attributes(
+            TAG_ATTRIBUTES
+                .iter()
+                .map(|(tag, attributes)| (*tag, attributes.iter().copied().collect()))
+                .collect::<HashMap<_, HashSet<_>>>(),
+        )
+        .add_tag_attribute_values("input", "type", &["checkbox"])
+        .allowed_classes(
+            CLASSES
+                .iter()
+                .map(|(tag, classes)| (*tag, classes.iter().copied().collect()))
+                .collect::<HashMap<_, HashSet<_>>>(),
+        )
+        .url_schemes(URL_SCHEMES.iter().copied().collect())
+        .link_rel(Some("noopener noreferrer"));
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn published() -> Context<'static> {
+        let mut note_urls = HashMap::new();
+        note_urls.insert(String::from("other"), String::from("other.html"));
+        Context {
+            note_urls,
+            ..Context::default()
+        }
+    }
+
+    #[test]
+    fn links_to_published_notes_are_rewritten() {
+        let context = published();
+        let html = to_html(
+            "[inline](/n/other) and [reference][ref]\n\n[ref]: /n/other#part",
+            &context,
+        );
+        assert!(html.contains(r#"<a href="other.html" rel="noopener noreferrer">inline</a>"#));
+        assert!(
+            html.contains(r#"<a href="other.html#part" rel="noopener noreferrer">reference</a>"#)
+        );
+
+        let html = to_html(r#"<a href="/n/other">html</a>"#, &context);
+        assert!(html.contains(r#"<a href="other.html" rel="noopener noreferrer">html</a>"#));
+    }
+
+    #[test]
+    fn links_to_other_notes_are_kept() {
+        let html = to_html(
+            "[unpublished](/n/secret) [not a note](/other)",
+            &published(),
+        );
+        assert!(html.contains(r#"href="/n/secret""#));
+        assert!(html.contains(r#"href="/other""#));
+    }
+
+    fn embedding(notes: &[(&str, &str)]) -> Context<'static> {
+        let embeds = notes
+            .iter()
+            .map(|(name, body)| {
+                let embed = Embed {
+                    seo_name: name.to_string(),
+                    title: name.to_uppercase(),
+                    url: format!("/n/{}", name),
+                    body: body.to_string(),
+                    attachments: None,
+                };
+                (name.to_string(), embed)
+            })
+            .collect();
+        Context {
+            note: Some("a"),
+            embeds,
+            ..Context::default()
+        }
+    }
+
+    #[test]
+    fn embeds_that_are_too_deep_are_not_rendered() {
+        let context = embedding(&[("b", "![[c]]"), ("c", "![[d]]"), ("d", "![[e]]")]);
+        let html = to_html("![[b]]", &context);
+        assert!(html.contains(">D</a>"));
+        assert!(html.contains("Note e is embedded too deep"));
+    }
+
+    #[test]
+    fn embeds_are_rendered_again_where_other_notes_are_being_rendered() {
+        // d shows b when it is embedded in c, but not when it is embedded in b itself
+        let context = embedding(&[
+            ("a", "![[b]]\n\n![[c]]"),
+            ("b", "![[d]]"),
+            ("c", "![[d]]"),
+            ("d", "![[b]] ![[a]]"),
+        ]);
+        let html = to_html("![[b]]\n\n![[c]]", &context);
+        assert_eq!(html.matches("Note b is already shown here").count(), 1);
+        assert_eq!(html.matches("Note a is already shown here").count(), 2);
+        assert_eq!(html.matches("Note d is embedded too deep").count(), 1);
+        assert_eq!(html.matches(">B</a>").count(), 2);
+        assert_eq!(html.matches(">D</a>").count(), 2);
+    }
+
+    #[test]
+    fn scripts_and_javascript_urls_are_removed() {
+        let html = to_html(
+            "<script>alert(1)</script>\n\n[link](javascript:alert(1)) <a href=\"javascript:alert(1)\" onclick=\"alert(1)\">html</a>",
+            &Context::default(),
+        );
+        assert!(!html.contains("script"));
+        assert!(!html.contains("javascript:"));
+        assert!(!html.contains("onclick"));
+        assert!(html.contains("link</a>"));
+        assert!(html.contains("html</a>"));
+    }
+
+    #[test]
+    fn formulas_and_embeds_are_extracted_outside_of_code() {
+        let (source, placeholders, origins) =
+            extract_placeholders("$a$ costs $5 and `$b$` ![[other#part]]");
+        assert_eq!(
+            source,
+            "\u{E000}0\u{E000} costs $5 and `$b$` \u{E000}1\u{E000}"
+        );
+        assert!(matches!(
+            &placeholders[0],
+            Placeholder::Math { display: false, tex } if tex == "a"
+        ));
+        assert!(matches!(
+            &placeholders[1],
+            Placeholder::Embed { name, heading: Some(heading) } if name == "other" && heading == "part"
+        ));
+        assert_eq!(origins.len(), source.len());
+        assert_eq!(origins[source.find(" costs").unwrap()], 3);
+    }
+
+    #[test]
+    fn placeholders_survive_rendering() {
+        let html = to_html("$$x < y$$ and *$a_1$*", &Context::default());
+        assert!(html.contains(r#"<span class="math math-display">x &lt; y</span>"#));
+        assert!(html.contains(r#"<em><span class="math math-inline">a_1</span></em>"#));
+        assert!(!html.contains(PLACEHOLDER_MARKER));
+
+        // Marker characters in the note itself can not be used to inject placeholders
+        let html = to_html("\u{E000}0\u{E000} $a$ \u{E001}", &Context::default());
+        assert!(!html.contains(PLACEHOLDER_MARKER));
+        assert!(!html.contains(TASK_MARKER));
+    }
+
+    #[test]
+    fn tasks_are_toggled_by_the_index_they_are_rendered_with() {
+        let markdown = "- [ ] first\n- [x] second $[ ]$\n\n`- [ ] code`\n\n- \u{E000}[ ] third";
+        let context = Context {
+            tasks: Some(TaskForm {
+                action: "/toggle_task/note",
+                csrf_token: "token",
+            }),
+            ..Context::default()
+        };
+        let html = to_html(markdown, &context);
+        assert_eq!(html.matches("name=\"task\"").count(), 3);
+        assert!(html.contains(r#"name="task" value="2""#));
+
+        assert_eq!(
+            toggle_task(markdown, 0).unwrap(),
+            "- [x] first\n- [x] second $[ ]$\n\n`- [ ] code`\n\n- \u{E000}[ ] third"
+        );
+        assert_eq!(
+            toggle_task(markdown, 1).unwrap(),
+            "- [ ] first\n- [ ] second $[ ]$\n\n`- [ ] code`\n\n- \u{E000}[ ] third"
+        );
+        assert_eq!(
+            toggle_task(markdown, 2).unwrap(),
+            "- [ ] first\n- [x] second $[ ]$\n\n`- [ ] code`\n\n- \u{E000}[x] third"
+        );
+        assert_eq!(toggle_task(markdown, 3), None);
+    }
+}
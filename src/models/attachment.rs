@@ -0,0 +1,93 @@
+use crate::schema::attachment;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Queryable)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub has_thumbnail: bool,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[table_name = "attachment"]
+struct InsertAttachment<'a> {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub file_name: &'a str,
+    pub content_type: &'a str,
+    pub size: i64,
+    pub has_thumbnail: bool,
+    pub created: DateTime<Utc>,
+}
+
+impl Attachment {
+    /// Insert an attachment. The id is chosen by the caller, because the file is stored under it before the row exists.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        note_id: Uuid,
+        file_name: &str,
+        content_type: &str,
+        size: i64,
+        has_thumbnail: bool,
+    ) -> Result<Attachment, failure::Error> {
+        diesel::insert_into(attachment::table)
+            .values(InsertAttachment {
+                id,
+                note_id,
+                file_name,
+                content_type,
+                size,
+                has_thumbnail,
+                created: Utc::now(),
+            })
+            .get_result(conn)
+            .map_err(Into::into)
+    }
+
+    pub fn load_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<Vec<Attachment>, failure::Error> {
+        attachment::table
+            .filter(attachment::dsl::note_id.eq(note_id))
+            .order(attachment::dsl::file_name.asc())
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    pub fn load_by_name(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+        file_name: &str,
+    ) -> Result<Option<Attachment>, failure::Error> {
+        attachment::table
+            .filter(attachment::dsl::note_id.eq(note_id))
+            .filter(attachment::dsl::file_name.eq(file_name))
+            .get_result(conn)
+            .optional()
+            .map_err(Into::into)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, id: Uuid) -> Result<(), failure::Error> {
+        diesel::delete(attachment::table.filter(attachment::dsl::id.eq(id))).execute(conn)?;
+        Ok(())
+    }
+
+    /// Delete the attachments of the note, and return them so their files can be removed
+    pub fn delete_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<Vec<Attachment>, failure::Error> {
+        diesel::delete(attachment::table.filter(attachment::dsl::note_id.eq(note_id)))
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+}
@@ -0,0 +1,91 @@
+use crate::schema::{audit_log, user};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+/// An entry of the audit log, together with the name of the user it is about
+#[derive(Queryable)]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub created: DateTime<Utc>,
+    pub user_id: Option<Uuid>,
+    pub user_name: Option<String>,
+    pub event: String,
+    pub ip: Option<String>,
+    pub details: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "audit_log"]
+struct InsertAuditLog<'a> {
+    pub created: DateTime<Utc>,
+    pub user_id: Option<Uuid>,
+    pub event: &'a str,
+    pub ip: Option<&'a str>,
+    pub details: Option<&'a str>,
+}
+
+pub struct AuditLog;
+
+impl AuditLog {
+    pub fn create(
+        conn: &diesel::PgConnection,
+        user_id: Option<Uuid>,
+        event: &str,
+        ip: Option<&str>,
+        details: Option<&str>,
+    ) -> Result<(), failure::Error> {
+        diesel::insert_into(audit_log::table)
+            .values(InsertAuditLog {
+                created: Utc::now(),
+                user_id,
+                event,
+                ip,
+                details,
+            })
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Keep the entries about the user, but no longer tie them to it, so the user can be deleted
+    pub fn detach_user(conn: &diesel::PgConnection, user_id: Uuid) -> Result<(), failure::Error> {
+        diesel::update(audit_log::table.filter(audit_log::dsl::user_id.eq(user_id)))
+            .set(audit_log::dsl::user_id.eq(None::<Uuid>))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Load the newest entries, optionally only the ones about `user_id` and with the given event
+    pub fn load(
+        conn: &diesel::PgConnection,
+        user_id: Option<Uuid>,
+        event: Option<&str>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<AuditLogEntry>, failure::Error> {
+        let mut query = audit_log::table
+            .left_join(user::table)
+            .select((
+                audit_log::dsl::id,
+                audit_log::dsl::created,
+                audit_log::dsl::user_id,
+                user::dsl::name.nullable(),
+                audit_log::dsl::event,
+                audit_log::dsl::ip,
+                audit_log::dsl::details,
+            ))
+            .into_boxed();
+        if let Some(user_id) = user_id {
+            query = query.filter(audit_log::dsl::user_id.eq(user_id));
+        }
+        if let Some(event) = event {
+            query = query.filter(audit_log::dsl::event.eq(event));
+        }
+        query
+            .order(audit_log::dsl::created.desc())
+            .offset(offset)
+            .limit(limit)
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+}
@@ -0,0 +1,88 @@
+use crate::schema::auth_attempt;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+#[derive(Insertable)]
+#[table_name = "auth_attempt"]
+struct InsertAuthAttempt<'a> {
+    pub created: DateTime<Utc>,
+    pub kind: &'a str,
+    pub ip: &'a str,
+    pub user_name: Option<&'a str>,
+}
+
+pub struct AuthAttempt;
+
+impl AuthAttempt {
+    pub fn create(
+        conn: &diesel::PgConnection,
+        created: DateTime<Utc>,
+        kind: &str,
+        ip: &str,
+        user_name: Option<&str>,
+    ) -> Result<(), failure::Error> {
+        diesel::insert_into(auth_attempt::table)
+            .values(InsertAuthAttempt {
+                created,
+                kind,
+                ip,
+                user_name,
+            })
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Count the attempts of the given kind that were made after `since`, from `ip` or for `user_name`.
+    /// Returns the amount, and when the last of them was made.
+    pub fn count_since(
+        conn: &diesel::PgConnection,
+        kind: &str,
+        ip: Option<&str>,
+        user_name: Option<&str>,
+        since: DateTime<Utc>,
+    ) -> Result<(i64, Option<DateTime<Utc>>), failure::Error> {
+        let filtered = || {
+            let mut query = auth_attempt::table
+                .filter(auth_attempt::dsl::kind.eq(kind))
+                .filter(auth_attempt::dsl::created.gt(since))
+                .into_boxed();
+            if let Some(ip) = ip {
+                query = query.filter(auth_attempt::dsl::ip.eq(ip));
+            }
+            if let Some(user_name) = user_name {
+                query = query.filter(auth_attempt::dsl::user_name.eq(user_name));
+            }
+            query
+        };
+        let count = filtered().count().get_result(conn)?;
+        let last = filtered()
+            .select(auth_attempt::dsl::created)
+            .order(auth_attempt::dsl::created.desc())
+            .first(conn)
+            .optional()?;
+        Ok((count, last))
+    }
+
+    pub fn delete_by_user_name(
+        conn: &diesel::PgConnection,
+        kind: &str,
+        user_name: &str,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(
+            auth_attempt::table
+                .filter(auth_attempt::dsl::kind.eq(kind))
+                .filter(auth_attempt::dsl::user_name.eq(user_name)),
+        )
+        .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete_before(
+        conn: &diesel::PgConnection,
+        before: DateTime<Utc>,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(auth_attempt::table.filter(auth_attempt::dsl::created.lt(before)))
+            .execute(conn)?;
+        Ok(())
+    }
+}
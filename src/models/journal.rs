@@ -0,0 +1,91 @@
+use crate::schema::journal_entry;
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+/// The note of a day in the journal that a user keeps in a workspace
+#[derive(Queryable, Insertable)]
+#[table_name = "journal_entry"]
+pub struct JournalEntry {
+    pub user_id: Uuid,
+    pub workspace_id: Uuid,
+    pub day: NaiveDate,
+    pub note_id: Uuid,
+}
+
+impl JournalEntry {
+    /// Save the entry, unless the day already has one. Returns whether it was saved.
+    pub fn save(&self, conn: &diesel::PgConnection) -> Result<bool, failure::Error> {
+        let inserted = diesel::insert_into(journal_entry::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        Ok(inserted > 0)
+    }
+
+    pub fn load_by_day(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        workspace_id: Uuid,
+        day: NaiveDate,
+    ) -> Result<Option<JournalEntry>, failure::Error> {
+        journal_entry::table
+            .find((user_id, workspace_id, day))
+            .get_result(conn)
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// Load the latest entry before the given day
+    pub fn load_previous(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        workspace_id: Uuid,
+        before: NaiveDate,
+    ) -> Result<Option<JournalEntry>, failure::Error> {
+        journal_entry::table
+            .filter(journal_entry::dsl::user_id.eq(user_id))
+            .filter(journal_entry::dsl::workspace_id.eq(workspace_id))
+            .filter(journal_entry::dsl::day.lt(before))
+            .order(journal_entry::dsl::day.desc())
+            .first(conn)
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// Load the entries from `from` up to, but not including, `until`
+    pub fn load_between(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        workspace_id: Uuid,
+        from: NaiveDate,
+        until: NaiveDate,
+    ) -> Result<Vec<JournalEntry>, failure::Error> {
+        journal_entry::table
+            .filter(journal_entry::dsl::user_id.eq(user_id))
+            .filter(journal_entry::dsl::workspace_id.eq(workspace_id))
+            .filter(journal_entry::dsl::day.ge(from))
+            .filter(journal_entry::dsl::day.lt(until))
+            .order(journal_entry::dsl::day.asc())
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    pub fn delete_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(journal_entry::table.filter(journal_entry::dsl::note_id.eq(note_id)))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(journal_entry::table.filter(journal_entry::dsl::user_id.eq(user_id)))
+            .execute(conn)?;
+        Ok(())
+    }
+}
@@ -1,4 +1,15 @@
+pub mod attachment;
+pub mod audit_log;
+pub mod auth_attempt;
+pub mod journal;
 pub mod note;
+pub mod note_acl;
 pub mod note_link;
+pub mod note_share;
+pub mod statistics;
 pub mod user;
+pub mod user_preference;
+pub mod user_recovery_code;
 pub mod user_token;
+pub mod walk;
+pub mod workspace;
@@ -1,9 +1,55 @@
+use super::note_acl::{readable_notes_sql, Accessor};
+use crate::note::{NoteFilter, NoteSort};
 use crate::routes::SearchQuery;
-use crate::schema::{note, note_history};
+use crate::schema::{note, note_history, note_seo_alias, user};
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use failure::bail;
 use uuid::Uuid;
 
+no_arg_sql_function!(random, diesel::sql_types::Double);
+
+/// Run `f` inside a database transaction, rolling back everything it did if it returns an error.
+///
+/// Transactions can be nested; an inner transaction becomes a savepoint of the outer one.
+/// Use this to combine several model operations (e.g. merging, importing or restoring notes) into a single atomic operation.
+pub fn transaction<T, F>(conn: &diesel::PgConnection, f: F) -> Result<T, failure::Error>
+where
+    F: FnOnce() -> Result<T, failure::Error>,
+{
+    conn.transaction(f)
+}
+
+/// The FROM and WHERE clauses of the queries that load notes by a `NoteFilter`.
+///
+/// The parameters are: $1 the workspace id, $2 only orphans, $3 created since and $4 created until.
+/// Queries that use this also need to check `readable_notes_sql(5, 6)`.
+const FILTERED_NOTES: &str = r#"
+FROM note
+LEFT JOIN (
+    SELECT
+        link.note_id,
+        COUNT(*) AS link_count
+    FROM (
+        SELECT note_link."left" AS note_id FROM note_link
+        UNION ALL
+        SELECT note_link."right" AS note_id FROM note_link
+    ) link
+    GROUP BY link.note_id
+) links ON links.note_id = note.id
+WHERE note.workspace_id = $1
+AND note.deleted = false
+AND ($2 = false OR links.link_count IS NULL)
+AND ($3::DATE IS NULL OR note.created_at >= $3::DATE)
+AND ($4::DATE IS NULL OR note.created_at < $4::DATE + 1)"#;
+
+#[derive(QueryableByName)]
+struct NoteCount {
+    #[sql_type = "diesel::sql_types::BigInt"]
+    count: i64,
+}
+
 #[derive(Queryable, QueryableByName)]
 #[table_name = "note"]
 pub struct Note {
@@ -14,6 +60,11 @@ pub struct Note {
     pub title: String,
     pub body: String,
     pub deleted: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub seo_name_pinned: bool,
+    pub workspace_id: Uuid,
+    pub is_template: bool,
 }
 
 #[derive(Insertable)]
@@ -25,6 +76,11 @@ pub struct InsertNote<'a> {
     pub title: &'a str,
     pub body: &'a str,
     pub deleted: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub seo_name_pinned: bool,
+    pub workspace_id: Uuid,
+    pub is_template: bool,
 }
 
 #[derive(Queryable)]
@@ -34,6 +90,16 @@ pub struct NoteHistory {
     pub created: DateTime<Utc>,
     pub title: String,
     pub body: String,
+    pub user_id: Option<Uuid>,
+    pub token_id: Option<Uuid>,
+    pub summary: Option<String>,
+}
+
+/// Who made a change to a note, from which session, and why
+pub struct Revision<'a> {
+    pub user_id: Uuid,
+    pub token_id: Option<Uuid>,
+    pub summary: Option<&'a str>,
 }
 
 impl NoteHistory {
@@ -45,6 +111,20 @@ impl NoteHistory {
             .execute(conn)?;
         Ok(())
     }
+
+    /// Forget which user and session made the revisions of the given user, so the user can be deleted
+    pub fn unattribute_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::update(note_history::table.filter(note_history::dsl::user_id.eq(user_id)))
+            .set((
+                note_history::dsl::user_id.eq(None::<Uuid>),
+                note_history::dsl::token_id.eq(None::<Uuid>),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
 }
 
 #[derive(Insertable)]
@@ -54,16 +134,100 @@ pub struct InsertNoteHistory<'a> {
     pub created: DateTime<Utc>,
     pub title: &'a str,
     pub body: &'a str,
+    pub user_id: Option<Uuid>,
+    pub token_id: Option<Uuid>,
+    pub summary: Option<&'a str>,
+}
+
+/// A previous seo name of a note, which is kept so old urls keep working after a note is renamed
+#[derive(Queryable)]
+pub struct NoteSeoAlias {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub seo_name: String,
+    pub created: DateTime<Utc>,
+    pub workspace_id: Uuid,
+}
+
+#[derive(Insertable)]
+#[table_name = "note_seo_alias"]
+struct InsertNoteSeoAlias<'a> {
+    pub note_id: Uuid,
+    pub workspace_id: Uuid,
+    pub seo_name: &'a str,
+    pub created: DateTime<Utc>,
+}
+
+impl NoteSeoAlias {
+    pub fn create(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+        workspace_id: Uuid,
+        seo_name: &str,
+    ) -> Result<(), failure::Error> {
+        let created = Utc::now();
+        diesel::insert_into(note_seo_alias::table)
+            .values(InsertNoteSeoAlias {
+                note_id,
+                workspace_id,
+                seo_name,
+                created,
+            })
+            .on_conflict((
+                note_seo_alias::dsl::workspace_id,
+                note_seo_alias::dsl::seo_name,
+            ))
+            .do_update()
+            .set((
+                note_seo_alias::dsl::note_id.eq(note_id),
+                note_seo_alias::dsl::created.eq(created),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Remove the alias with the given name, because a note is now using it as its actual seo name
+    pub fn delete_by_seo_name(
+        conn: &diesel::PgConnection,
+        seo_name: &str,
+        workspace_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(
+            note_seo_alias::table.filter(
+                note_seo_alias::dsl::workspace_id
+                    .eq(workspace_id)
+                    .and(note_seo_alias::dsl::seo_name.eq(seo_name)),
+            ),
+        )
+        .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(note_seo_alias::table.filter(note_seo_alias::dsl::note_id.eq(note_id)))
+            .execute(conn)?;
+        Ok(())
+    }
 }
 
 impl<'a> InsertNoteHistory<'a> {
-    fn create(conn: &diesel::PgConnection, note: &Note) -> Result<(), failure::Error> {
+    fn create(
+        conn: &diesel::PgConnection,
+        note: &Note,
+        revision: &Revision,
+    ) -> Result<(), failure::Error> {
         diesel::insert_into(note_history::table)
             .values(InsertNoteHistory {
                 note_id: note.id,
-                created: Utc::now(),
+                created: note.updated_at,
                 title: note.title.as_str(),
                 body: note.body.as_str(),
+                user_id: Some(revision.user_id),
+                token_id: revision.token_id,
+                summary: revision.summary,
             })
             .execute(conn)?;
         Ok(())
@@ -71,47 +235,189 @@ impl<'a> InsertNoteHistory<'a> {
 }
 
 impl Note {
-    pub fn load_paged(
+    pub fn load_filtered(
         conn: &diesel::PgConnection,
-        user_id: Uuid,
+        accessor: &Accessor,
+        filter: &NoteFilter,
         start: i64,
         count: i64,
+    ) -> Result<Vec<Note>, failure::Error> {
+        let order = match filter.sort {
+            NoteSort::Title => "note.title ASC",
+            NoteSort::MostViewed => "note.view_count DESC",
+            NoteSort::LeastViewed => "note.view_count ASC",
+            NoteSort::RecentlyCreated => "note.created_at DESC",
+            NoteSort::RecentlyEdited => "note.updated_at DESC",
+            NoteSort::MostLinks => "COALESCE(links.link_count, 0) DESC",
+        };
+        let query = format!(
+            "SELECT note.*{}\nAND {}\nORDER BY {}, note.title ASC, note.id\nOFFSET $7\nLIMIT $8",
+            FILTERED_NOTES,
+            readable_notes_sql(5, 6),
+            order
+        );
+        diesel::sql_query(query)
+            .bind::<diesel::sql_types::Uuid, _>(accessor.workspace_id)
+            .bind::<diesel::sql_types::Bool, _>(filter.orphans)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Date>, _>(filter.from)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Date>, _>(filter.to)
+            .bind::<diesel::sql_types::Uuid, _>(accessor.user_id)
+            .bind::<diesel::sql_types::Text, _>(&accessor.role)
+            .bind::<diesel::sql_types::BigInt, _>(start)
+            .bind::<diesel::sql_types::BigInt, _>(count)
+            .load(conn)
+            .map_err(Into::into)
+    }
+
+    pub fn count_filtered(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        filter: &NoteFilter,
+    ) -> Result<i64, failure::Error> {
+        let query = format!(
+            "SELECT COUNT(*) AS count{}\nAND {}",
+            FILTERED_NOTES,
+            readable_notes_sql(5, 6)
+        );
+        let result: NoteCount = diesel::sql_query(query)
+            .bind::<diesel::sql_types::Uuid, _>(accessor.workspace_id)
+            .bind::<diesel::sql_types::Bool, _>(filter.orphans)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Date>, _>(filter.from)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Date>, _>(filter.to)
+            .bind::<diesel::sql_types::Uuid, _>(accessor.user_id)
+            .bind::<diesel::sql_types::Text, _>(&accessor.role)
+            .get_result(conn)?;
+        Ok(result.count)
+    }
+
+    pub fn load_all_readable(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
     ) -> Result<Vec<Note>, failure::Error> {
         note::table
-            .filter(
-                note::dsl::user_id
-                    .eq(user_id)
-                    .and(note::dsl::deleted.eq(false)),
-            )
-            .order(note::dsl::view_count.desc())
-            .offset(start)
-            .limit(count)
+            .filter(note::dsl::id.eq_any(accessor.readable_notes()))
+            .filter(note::dsl::deleted.eq(false))
             .get_results(conn)
             .map_err(Into::into)
     }
 
-    pub fn count_by_user(
+    pub fn load_random(
         conn: &diesel::PgConnection,
-        user_id: Uuid,
+        accessor: &Accessor,
+    ) -> Result<Option<Note>, failure::Error> {
+        note::table
+            .filter(note::dsl::id.eq_any(accessor.readable_notes()))
+            .filter(note::dsl::deleted.eq(false))
+            .order(random)
+            .first(conn)
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// Load the notes of the workspace that are marked as templates, ordered by title
+    pub fn load_templates(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<Vec<Note>, failure::Error> {
+        note::table
+            .filter(note::dsl::id.eq_any(accessor.readable_notes()))
+            .filter(note::dsl::is_template.eq(true))
+            .order((note::dsl::title.asc(), note::dsl::id))
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    /// Mark the note as a template, or not. Returns `false` if the accessor is not allowed to change the note.
+    pub fn set_template(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        accessor: &Accessor,
+        is_template: bool,
+    ) -> Result<bool, failure::Error> {
+        let updated = diesel::update(
+            note::table
+                .filter(note::dsl::id.eq(id))
+                .filter(note::dsl::id.eq_any(accessor.writable_notes())),
+        )
+        .set(note::dsl::is_template.eq(is_template))
+        .execute(conn)?;
+        Ok(updated > 0)
+    }
+
+    pub fn count_by_workspace(
+        conn: &diesel::PgConnection,
+        workspace_id: Uuid,
     ) -> Result<i64, failure::Error> {
         note::table
-            .filter(note::dsl::user_id.eq(user_id))
+            .filter(note::dsl::workspace_id.eq(workspace_id))
             .count()
             .get_result(conn)
             .map_err(Into::into)
     }
 
-    pub fn delete(conn: &diesel::PgConnection, id: Uuid) -> Result<(), failure::Error> {
+    pub fn delete(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        accessor: &Accessor,
+    ) -> Result<(), failure::Error> {
+        let deleted = diesel::delete(
+            note::table
+                .filter(note::dsl::id.eq(id))
+                .filter(note::dsl::id.eq_any(accessor.writable_notes())),
+        )
+        .execute(conn)?;
+        if deleted == 0 {
+            bail!("You are not allowed to delete this note");
+        }
+        Ok(())
+    }
+
+    /// Delete a note without checking whether anyone is allowed to
+    pub fn delete_by_id(conn: &diesel::PgConnection, id: Uuid) -> Result<(), failure::Error> {
         diesel::delete(note::table.find(id)).execute(conn)?;
         Ok(())
     }
 
+    /// Load the ids of the notes that the user created, and of all notes in the given workspaces
+    pub fn load_ids_by_user_or_workspaces(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        workspace_ids: &[Uuid],
+    ) -> Result<Vec<Uuid>, failure::Error> {
+        note::table
+            .filter(
+                note::dsl::user_id
+                    .eq(user_id)
+                    .or(note::dsl::workspace_id.eq_any(workspace_ids)),
+            )
+            .select(note::dsl::id)
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    /// Whether the accessor is allowed to change the note, according to its access control list
+    pub fn is_writable(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        accessor: &Accessor,
+    ) -> Result<bool, failure::Error> {
+        diesel::select(diesel::dsl::exists(
+            note::table
+                .filter(note::dsl::id.eq(id))
+                .filter(note::dsl::id.eq_any(accessor.writable_notes())),
+        ))
+        .get_result(conn)
+        .map_err(Into::into)
+    }
+
     pub fn load_by_id(
         conn: &diesel::PgConnection,
         id: Uuid,
+        accessor: &Accessor,
     ) -> Result<Option<Note>, failure::Error> {
         note::table
-            .find(id)
+            .filter(note::dsl::id.eq(id))
+            .filter(note::dsl::id.eq_any(accessor.readable_notes()))
             .get_result(conn)
             .optional()
             .map_err(Into::into)
@@ -120,14 +426,34 @@ impl Note {
     pub fn load_by_seo_name(
         conn: &diesel::PgConnection,
         name: &str,
-        user_id: Uuid,
+        accessor: &Accessor,
     ) -> Result<Option<Note>, failure::Error> {
         note::table
             .filter(
-                note::dsl::user_id
-                    .eq(user_id)
+                note::dsl::workspace_id
+                    .eq(accessor.workspace_id)
                     .and(note::dsl::seo_name.eq(name)),
             )
+            .filter(note::dsl::id.eq_any(accessor.readable_notes()))
+            .get_result(conn)
+            .optional()
+            .map_err(Into::into)
+    }
+
+    pub fn load_by_alias(
+        conn: &diesel::PgConnection,
+        name: &str,
+        accessor: &Accessor,
+    ) -> Result<Option<Note>, failure::Error> {
+        note_seo_alias::table
+            .filter(
+                note_seo_alias::dsl::workspace_id
+                    .eq(accessor.workspace_id)
+                    .and(note_seo_alias::dsl::seo_name.eq(name)),
+            )
+            .inner_join(note::table)
+            .filter(note::dsl::id.eq_any(accessor.readable_notes()))
+            .select(note::all_columns)
             .get_result(conn)
             .optional()
             .map_err(Into::into)
@@ -136,7 +462,7 @@ impl Note {
     pub fn search(
         conn: &diesel::PgConnection,
         search_query: SearchQuery,
-        user_id: Uuid,
+        accessor: &Accessor,
     ) -> Result<Vec<Note>, failure::Error> {
         fn sanitize(s: &str) -> String {
             let mut result = String::with_capacity(s.len());
@@ -161,7 +487,7 @@ impl Note {
                         format!("{} & {}", acc, s)
                     }
                 });
-        let mut query = String::from(
+        let mut query = format!(
             r#"
 SELECT
     note_search.id,
@@ -170,7 +496,12 @@ SELECT
     note_search.seo_name,
     note_search.title,
     note_search.body,
-    note_search.deleted
+    note_search.deleted,
+    note_search.created_at,
+    note_search.updated_at,
+    note_search.seo_name_pinned,
+    note_search.workspace_id,
+    note_search.is_template
 FROM (
     SELECT
         note.id,
@@ -180,11 +511,18 @@ FROM (
         note.title,
         note.body,
         note.deleted,
+        note.created_at,
+        note.updated_at,
+        note.seo_name_pinned,
+        note.workspace_id,
+        note.is_template,
         Setweight(To_tsvector('english', note.title), 'A') || Setweight(To_tsvector('english', note.body), 'B') AS document
     FROM note
-    WHERE note.user_id = $1
+    WHERE note.workspace_id = $1
+    AND {}
 ) note_search
 WHERE note_search.document @@ to_tsquery('english', $2)"#,
+            readable_notes_sql(3, 4)
         );
 
         for exclude in search_query.excludes {
@@ -202,19 +540,26 @@ WHERE note_search.document @@ to_tsquery('english', $2)"#,
 ORDER BY ts_rank(note_search.document, to_tsquery('english', $2)) DESC"#;
 
         diesel::sql_query(query)
-            .bind::<diesel::sql_types::Uuid, _>(user_id)
+            .bind::<diesel::sql_types::Uuid, _>(accessor.workspace_id)
             .bind::<diesel::sql_types::Text, _>(&search_words)
+            .bind::<diesel::sql_types::Uuid, _>(accessor.user_id)
+            .bind::<diesel::sql_types::Text, _>(&accessor.role)
             .load(conn)
             .map_err(Into::into)
     }
 
+    /// Load the revisions of the note, newest first, together with the name of the user that made them
     pub fn load_history(
         conn: &diesel::PgConnection,
         note_id: Uuid,
-    ) -> Result<Vec<NoteHistory>, failure::Error> {
+        accessor: &Accessor,
+    ) -> Result<Vec<(NoteHistory, Option<String>)>, failure::Error> {
         note_history::table
+            .left_join(user::table)
             .filter(note_history::dsl::note_id.eq(note_id))
+            .filter(note_history::dsl::note_id.eq_any(accessor.readable_notes()))
             .order(note_history::dsl::created.desc())
+            .select((note_history::all_columns, user::dsl::name.nullable()))
             .get_results(conn)
             .map_err(Into::into)
     }
@@ -230,45 +575,116 @@ ORDER BY ts_rank(note_search.document, to_tsquery('english', $2)) DESC"#;
             .map_err(Into::into)
     }
 
+    /// Load the seo names in the workspace that are equal to `base`, or start with `base` followed by an underscore.
+    ///
+    /// The note with id `except` is ignored, so that a note does not collide with itself when it is updated.
+    pub fn load_seo_names_like(
+        conn: &diesel::PgConnection,
+        base: &str,
+        workspace_id: Uuid,
+        except: Option<Uuid>,
+    ) -> Result<Vec<String>, failure::Error> {
+        let pattern = format!(
+            "{}\\_%",
+            base.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        note::table
+            .filter(note::dsl::workspace_id.eq(workspace_id))
+            .filter(
+                note::dsl::seo_name
+                    .eq(base)
+                    .or(note::dsl::seo_name.like(pattern)),
+            )
+            .filter(note::dsl::id.ne(except.unwrap_or_else(Uuid::nil)))
+            .select(note::dsl::seo_name)
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    /// Update a note. Returns `None` if the seo name is already in use by another note in the same workspace.
+    ///
+    /// Fails if the accessor is not allowed to change the note.
+    #[allow(clippy::too_many_arguments)]
     pub fn update(
         conn: &diesel::PgConnection,
         id: Uuid,
+        accessor: &Accessor,
+        revision: &Revision,
         seo_name: &str,
+        seo_name_pinned: bool,
         title: &str,
         body: &str,
-    ) -> Result<Note, failure::Error> {
-        let note: Note = diesel::update(note::table.find(id))
-            .set((
-                note::dsl::seo_name.eq(seo_name),
-                note::dsl::title.eq(title),
-                note::dsl::body.eq(body),
-            ))
-            .get_result(conn)?;
-        InsertNoteHistory::create(conn, &note)?;
-        Ok(note)
+    ) -> Result<Option<Note>, failure::Error> {
+        transaction(conn, || {
+            // Run the update in a nested transaction, so that a unique violation only rolls back this statement
+            let result = conn.transaction(|| {
+                diesel::update(
+                    note::table
+                        .filter(note::dsl::id.eq(id))
+                        .filter(note::dsl::id.eq_any(accessor.writable_notes())),
+                )
+                .set((
+                    note::dsl::seo_name.eq(seo_name),
+                    note::dsl::seo_name_pinned.eq(seo_name_pinned),
+                    note::dsl::title.eq(title),
+                    note::dsl::body.eq(body),
+                    note::dsl::updated_at.eq(Utc::now()),
+                ))
+                .get_result::<Note>(conn)
+            });
+            let note = match result {
+                Ok(note) => note,
+                Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                    return Ok(None)
+                }
+                Err(DieselError::NotFound) => bail!("You are not allowed to change this note"),
+                Err(e) => return Err(e.into()),
+            };
+            InsertNoteHistory::create(conn, &note, revision)?;
+            Ok(Some(note))
+        })
     }
 
+    /// Create a new note. Returns `None` if the seo name is already in use by another note in the same workspace.
     pub fn create(
         conn: &diesel::PgConnection,
         seo_name: &str,
         title: &str,
         body: &str,
-        user_id: Uuid,
-    ) -> Result<Note, failure::Error> {
+        workspace_id: Uuid,
+        revision: &Revision,
+    ) -> Result<Option<Note>, failure::Error> {
+        let now = Utc::now();
         let note = InsertNote {
-            user_id,
+            user_id: revision.user_id,
             view_count: 0,
             seo_name,
             title,
             body,
             deleted: false,
+            created_at: now,
+            updated_at: now,
+            seo_name_pinned: false,
+            workspace_id,
+            is_template: false,
         };
-        let note: Note = diesel::insert_into(note::table)
-            .values(note)
-            .get_result(conn)?;
+        transaction(conn, || {
+            let note: Note = match diesel::insert_into(note::table)
+                .values(note)
+                .on_conflict((note::dsl::workspace_id, note::dsl::seo_name))
+                .do_nothing()
+                .get_result(conn)
+                .optional()?
+            {
+                Some(note) => note,
+                None => return Ok(None),
+            };
 
-        InsertNoteHistory::create(conn, &note)?;
+            InsertNoteHistory::create(conn, &note, revision)?;
 
-        Ok(note)
+            Ok(Some(note))
+        })
     }
 }
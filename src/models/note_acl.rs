@@ -0,0 +1,211 @@
+use super::note::transaction;
+use crate::schema::{note, note_acl, user, workspace_member};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_builder::BoxedSelectStatement;
+use uuid::Uuid;
+
+/// A member of a workspace, as far as the access control lists of its notes are concerned
+pub struct Accessor {
+    pub workspace_id: Uuid,
+    pub user_id: Uuid,
+    pub role: String,
+}
+
+/// A query that selects the ids of notes
+pub type NoteIds<'a> = BoxedSelectStatement<'a, diesel::sql_types::Uuid, note::table, Pg>;
+
+#[derive(Queryable)]
+pub struct NoteAcl {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub role: Option<String>,
+    pub can_write: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "note_acl"]
+struct InsertNoteAcl<'a> {
+    pub note_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub role: Option<&'a str>,
+    pub can_write: bool,
+}
+
+/// An entry of an access control list, together with the name of the user it is for
+#[derive(Queryable)]
+pub struct NoteAclEntry {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub user_name: Option<String>,
+    pub role: Option<String>,
+    pub can_write: bool,
+}
+
+impl Accessor {
+    /// Load the accessor for the given user in the workspace of the note, if the user is a member of that workspace
+    pub fn load_for_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<Accessor>, failure::Error> {
+        let result: Option<(Uuid, String)> = note::table
+            .inner_join(
+                workspace_member::table
+                    .on(workspace_member::dsl::workspace_id.eq(note::dsl::workspace_id)),
+            )
+            .filter(note::dsl::id.eq(note_id))
+            .filter(workspace_member::dsl::user_id.eq(user_id))
+            .select((note::dsl::workspace_id, workspace_member::dsl::role))
+            .get_result(conn)
+            .optional()?;
+        Ok(result.map(|(workspace_id, role)| Accessor {
+            workspace_id,
+            user_id,
+            role,
+        }))
+    }
+
+    /// The notes in the workspace that this accessor can read.
+    ///
+    /// A note can be read by the user that created it, and by everyone if it has no access control list.
+    /// Otherwise the list needs an entry for the user or their role.
+    pub fn readable_notes<'a>(&self) -> NoteIds<'a> {
+        self.granted_notes(false)
+    }
+
+    /// The notes in the workspace that this accessor can change.
+    ///
+    /// This is the same as `readable_notes`, except that the entries in the access control list need to allow writing.
+    /// Whether the role of the accessor allows editing at all is not checked here.
+    pub fn writable_notes<'a>(&self) -> NoteIds<'a> {
+        self.granted_notes(true)
+    }
+
+    fn granted_notes<'a>(&self, write: bool) -> NoteIds<'a> {
+        let restricted = note_acl::table.select(note_acl::dsl::note_id);
+        let mut entries = note_acl::table
+            .filter(
+                note_acl::dsl::user_id
+                    .eq(self.user_id)
+                    .or(note_acl::dsl::role.eq(self.role.clone())),
+            )
+            .into_boxed();
+        if write {
+            entries = entries.filter(note_acl::dsl::can_write.eq(true));
+        }
+        note::table
+            .filter(note::dsl::workspace_id.eq(self.workspace_id))
+            .filter(
+                note::dsl::user_id
+                    .eq(self.user_id)
+                    .or(note::dsl::id.ne_all(restricted))
+                    .or(note::dsl::id.eq_any(entries.select(note_acl::dsl::note_id))),
+            )
+            .select(note::dsl::id)
+            .into_boxed()
+    }
+}
+
+/// The SQL condition of `Accessor::readable_notes`, for queries that are written by hand.
+///
+/// The workspace itself is not checked. `user_param` and `role_param` are the positions of the parameters that the user id and role are bound to.
+pub fn readable_notes_sql(user_param: usize, role_param: usize) -> String {
+    format!(
+        r#"(
+    note.user_id = ${user}
+    OR note.id NOT IN (SELECT note_acl.note_id FROM note_acl)
+    OR note.id IN (
+        SELECT note_acl.note_id
+        FROM note_acl
+        WHERE note_acl.user_id = ${user}
+        OR note_acl.role = ${role}
+    )
+)"#,
+        user = user_param,
+        role = role_param
+    )
+}
+
+impl NoteAcl {
+    pub fn load_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<Vec<NoteAclEntry>, failure::Error> {
+        note_acl::table
+            .left_join(user::table)
+            .filter(note_acl::dsl::note_id.eq(note_id))
+            .order((note_acl::dsl::role.asc(), user::dsl::name.asc()))
+            .select((
+                note_acl::dsl::id,
+                note_acl::dsl::user_id,
+                user::dsl::name.nullable(),
+                note_acl::dsl::role,
+                note_acl::dsl::can_write,
+            ))
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    /// Give a user (if `user_id` is set) or a role (if `role` is set) access to the note, replacing the access they had before
+    pub fn save(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+        user_id: Option<Uuid>,
+        role: Option<&str>,
+        can_write: bool,
+    ) -> Result<(), failure::Error> {
+        transaction(conn, || {
+            diesel::delete(
+                note_acl::table
+                    .filter(note_acl::dsl::note_id.eq(note_id))
+                    .filter(
+                        note_acl::dsl::user_id
+                            .eq(user_id)
+                            .or(note_acl::dsl::role.eq(role)),
+                    ),
+            )
+            .execute(conn)?;
+            diesel::insert_into(note_acl::table)
+                .values(InsertNoteAcl {
+                    note_id,
+                    user_id,
+                    role,
+                    can_write,
+                })
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    pub fn delete(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(
+            note_acl::table
+                .filter(note_acl::dsl::id.eq(id))
+                .filter(note_acl::dsl::note_id.eq(note_id)),
+        )
+        .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(note_acl::table.filter(note_acl::dsl::note_id.eq(note_id))).execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(note_acl::table.filter(note_acl::dsl::user_id.eq(user_id))).execute(conn)?;
+        Ok(())
+    }
+}
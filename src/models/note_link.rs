@@ -1,12 +1,29 @@
 use super::note::Note;
-use crate::schema::{note, note_link};
+use super::note_acl::Accessor;
+use crate::schema::{note, note_link, note_link_click_day};
 use diesel::prelude::*;
+use std::collections::HashSet;
 use uuid::Uuid;
 
 #[derive(Queryable)]
+struct NoteLinkRow {
+    id: Uuid,
+    other: Note,
+    click_count: i32,
+}
+
 pub struct NoteLink {
     pub id: Uuid,
-    pub other: Note,
+    /// The note on the other side of the link, or `None` if the accessor is not allowed to read it
+    pub other: Option<Note>,
+    pub click_count: i32,
+}
+
+#[derive(Queryable)]
+pub struct NoteLinkEdge {
+    pub id: Uuid,
+    pub left: Uuid,
+    pub right: Uuid,
     pub click_count: i32,
 }
 
@@ -16,14 +33,16 @@ pub struct InsertNoteLink {
     left: Uuid,
     right: Uuid,
     click_count: i32,
+    embedded: bool,
 }
 
 impl NoteLink {
     pub fn load_by_note(
         conn: &diesel::PgConnection,
         note_id: Uuid,
+        accessor: &Accessor,
     ) -> Result<Vec<NoteLink>, failure::Error> {
-        let mut first: Vec<NoteLink> = note_link::table
+        let mut first: Vec<NoteLinkRow> = note_link::table
             .filter(note_link::dsl::left.eq(note_id))
             .inner_join(note::table.on(note::dsl::id.eq(note_link::dsl::right)))
             .select((
@@ -36,11 +55,16 @@ impl NoteLink {
                     note::dsl::title,
                     note::dsl::body,
                     note::dsl::deleted,
+                    note::dsl::created_at,
+                    note::dsl::updated_at,
+                    note::dsl::seo_name_pinned,
+                    note::dsl::workspace_id,
+                    note::dsl::is_template,
                 ),
                 note_link::dsl::click_count,
             ))
             .get_results(conn)?;
-        let second: Vec<NoteLink> = note_link::table
+        let second: Vec<NoteLinkRow> = note_link::table
             .filter(note_link::dsl::right.eq(note_id))
             .inner_join(note::table.on(note::dsl::id.eq(note_link::dsl::left)))
             .select((
@@ -53,6 +77,11 @@ impl NoteLink {
                     note::dsl::title,
                     note::dsl::body,
                     note::dsl::deleted,
+                    note::dsl::created_at,
+                    note::dsl::updated_at,
+                    note::dsl::seo_name_pinned,
+                    note::dsl::workspace_id,
+                    note::dsl::is_template,
                 ),
                 note_link::dsl::click_count,
             ))
@@ -60,7 +89,44 @@ impl NoteLink {
 
         first.extend(second.into_iter());
         first.sort_by_key(|f| -f.click_count);
-        Ok(first)
+
+        let others: Vec<Uuid> = first.iter().map(|l| l.other.id).collect();
+        let readable: HashSet<Uuid> = note::table
+            .filter(note::dsl::id.eq_any(others))
+            .filter(note::dsl::id.eq_any(accessor.readable_notes()))
+            .select(note::dsl::id)
+            .get_results::<Uuid>(conn)?
+            .into_iter()
+            .collect();
+        Ok(first
+            .into_iter()
+            .map(|l| NoteLink {
+                id: l.id,
+                other: if readable.contains(&l.other.id) {
+                    Some(l.other)
+                } else {
+                    None
+                },
+                click_count: l.click_count,
+            })
+            .collect())
+    }
+
+    pub fn load_edges_by_workspace(
+        conn: &diesel::PgConnection,
+        workspace_id: Uuid,
+    ) -> Result<Vec<NoteLinkEdge>, failure::Error> {
+        note_link::table
+            .inner_join(note::table.on(note::dsl::id.eq(note_link::dsl::left)))
+            .filter(note::dsl::workspace_id.eq(workspace_id))
+            .select((
+                note_link::dsl::id,
+                note_link::dsl::left,
+                note_link::dsl::right,
+                note_link::dsl::click_count,
+            ))
+            .get_results(conn)
+            .map_err(Into::into)
     }
 
     pub fn delete_by_note(
@@ -78,28 +144,81 @@ impl NoteLink {
         Ok(())
     }
 
+    /// Link two notes. `embedded` is whether the link is there because the left note embeds the right one.
     pub fn create(
         conn: &diesel::PgConnection,
         left: Uuid,
         right: Uuid,
+        embedded: bool,
     ) -> Result<(), failure::Error> {
         diesel::insert_into(note_link::table)
             .values(InsertNoteLink {
                 left,
                 right,
                 click_count: 0,
+                embedded,
             })
             .execute(conn)?;
         Ok(())
     }
 
-    pub fn increase_click_count(
+    /// Delete the links that the note got for embedding notes that are not in `embedded`, with their statistics
+    pub fn delete_embedded_except(
         conn: &diesel::PgConnection,
-        id: Uuid,
+        note_id: Uuid,
+        embedded: &[Uuid],
     ) -> Result<(), failure::Error> {
-        diesel::update(note_link::table.find(id))
-            .set(note_link::dsl::click_count.eq(note_link::dsl::click_count + 1))
-            .execute(conn)?;
+        let stale: Vec<Uuid> = note_link::table
+            .filter(note_link::dsl::left.eq(note_id))
+            .filter(note_link::dsl::embedded.eq(true))
+            .filter(note_link::dsl::right.ne_all(embedded))
+            .select(note_link::dsl::id)
+            .get_results(conn)?;
+        diesel::delete(
+            note_link_click_day::table.filter(note_link_click_day::dsl::link_id.eq_any(&stale)),
+        )
+        .execute(conn)?;
+        diesel::delete(note_link::table.filter(note_link::dsl::id.eq_any(&stale))).execute(conn)?;
         Ok(())
     }
+
+    /// The ids of the notes that are linked to the given note, in either direction
+    pub fn load_linked_ids(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<HashSet<Uuid>, failure::Error> {
+        let mut ids: HashSet<Uuid> = note_link::table
+            .filter(note_link::dsl::left.eq(note_id))
+            .select(note_link::dsl::right)
+            .get_results::<Uuid>(conn)?
+            .into_iter()
+            .collect();
+        ids.extend(
+            note_link::table
+                .filter(note_link::dsl::right.eq(note_id))
+                .select(note_link::dsl::left)
+                .get_results::<Uuid>(conn)?,
+        );
+        Ok(ids)
+    }
+
+    /// Increase the click count of a link between notes of the given workspace.
+    /// Returns `false` if there is no such link in the workspace.
+    pub fn increase_click_count(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        workspace_id: Uuid,
+    ) -> Result<bool, failure::Error> {
+        let workspace_notes = note::table
+            .filter(note::dsl::workspace_id.eq(workspace_id))
+            .select(note::dsl::id);
+        let updated = diesel::update(
+            note_link::table
+                .filter(note_link::dsl::id.eq(id))
+                .filter(note_link::dsl::left.eq_any(workspace_notes)),
+        )
+        .set(note_link::dsl::click_count.eq(note_link::dsl::click_count + 1))
+        .execute(conn)?;
+        Ok(updated > 0)
+    }
 }
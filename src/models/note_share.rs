@@ -0,0 +1,116 @@
+use super::note_acl::Accessor;
+use crate::schema::note_share;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Queryable)]
+pub struct NoteShare {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub user_id: Uuid,
+    pub token: String,
+    pub depth: i32,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub revoked: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "note_share"]
+struct InsertNoteShare<'a> {
+    pub note_id: Uuid,
+    pub user_id: Uuid,
+    pub token: &'a str,
+    pub depth: i32,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl NoteShare {
+    pub fn create(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+        user_id: Uuid,
+        token: &str,
+        depth: i32,
+        expires: Option<DateTime<Utc>>,
+    ) -> Result<NoteShare, failure::Error> {
+        diesel::insert_into(note_share::table)
+            .values(InsertNoteShare {
+                note_id,
+                user_id,
+                token,
+                depth,
+                created: Utc::now(),
+                expires,
+            })
+            .get_result(conn)
+            .map_err(Into::into)
+    }
+
+    /// Load the share with the given token, if it is not revoked and not expired
+    pub fn load_active_by_token(
+        conn: &diesel::PgConnection,
+        token: &str,
+    ) -> Result<Option<NoteShare>, failure::Error> {
+        note_share::table
+            .filter(note_share::dsl::token.eq(token))
+            .filter(note_share::dsl::revoked.eq(false))
+            .filter(
+                note_share::dsl::expires
+                    .is_null()
+                    .or(note_share::dsl::expires.gt(Utc::now())),
+            )
+            .get_result(conn)
+            .optional()
+            .map_err(Into::into)
+    }
+
+    pub fn load_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<Vec<NoteShare>, failure::Error> {
+        note_share::table
+            .filter(note_share::dsl::note_id.eq(note_id))
+            .order(note_share::dsl::created.desc())
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    /// Revoke the share. Returns `None` if the share does not exist or is of a note in another workspace.
+    /// Revoke the share, if it is of a note that the accessor can change
+    pub fn revoke(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        accessor: &Accessor,
+    ) -> Result<Option<NoteShare>, failure::Error> {
+        diesel::update(
+            note_share::table
+                .filter(note_share::dsl::id.eq(id))
+                .filter(note_share::dsl::note_id.eq_any(accessor.writable_notes())),
+        )
+        .set(note_share::dsl::revoked.eq(true))
+        .get_result(conn)
+        .optional()
+        .map_err(Into::into)
+    }
+
+    pub fn delete_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(note_share::table.filter(note_share::dsl::note_id.eq(note_id)))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(note_share::table.filter(note_share::dsl::user_id.eq(user_id)))
+            .execute(conn)?;
+        Ok(())
+    }
+}
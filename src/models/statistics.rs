@@ -0,0 +1,357 @@
+use super::note::Note;
+use super::note_acl::{readable_notes_sql, Accessor};
+use crate::schema::{note_link_click_day, note_view_day};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Date, Nullable, Text};
+use uuid::Uuid;
+
+#[derive(Queryable)]
+pub struct NoteViewDay {
+    pub note_id: Uuid,
+    pub day: NaiveDate,
+    pub view_count: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "note_view_day"]
+struct InsertNoteViewDay {
+    pub note_id: Uuid,
+    pub day: NaiveDate,
+    pub view_count: i32,
+}
+
+#[derive(Queryable)]
+pub struct NoteLinkClickDay {
+    pub link_id: Uuid,
+    pub day: NaiveDate,
+    pub click_count: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "note_link_click_day"]
+struct InsertNoteLinkClickDay {
+    pub link_id: Uuid,
+    pub day: NaiveDate,
+    pub click_count: i32,
+}
+
+#[derive(QueryableByName)]
+pub struct DayTotal {
+    #[sql_type = "Date"]
+    pub day: NaiveDate,
+    #[sql_type = "BigInt"]
+    pub total: i64,
+}
+
+#[derive(QueryableByName)]
+pub struct NoteTrend {
+    #[diesel(embed)]
+    pub note: Note,
+    #[sql_type = "BigInt"]
+    pub recent: i64,
+    #[sql_type = "BigInt"]
+    pub previous: i64,
+}
+
+#[derive(QueryableByName)]
+pub struct NoteLastViewed {
+    #[diesel(embed)]
+    pub note: Note,
+    #[sql_type = "Nullable<Date>"]
+    pub last_viewed: Option<NaiveDate>,
+}
+
+impl NoteViewDay {
+    pub fn record(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+        day: NaiveDate,
+    ) -> Result<(), failure::Error> {
+        diesel::insert_into(note_view_day::table)
+            .values(InsertNoteViewDay {
+                note_id,
+                day,
+                view_count: 1,
+            })
+            .on_conflict((note_view_day::dsl::note_id, note_view_day::dsl::day))
+            .do_update()
+            .set(note_view_day::dsl::view_count.eq(note_view_day::dsl::view_count + 1))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// The views of the notes in the workspace of the accessor per day, leaving out the notes it can not read
+    pub fn load_totals_by_workspace(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        since: NaiveDate,
+    ) -> Result<Vec<DayTotal>, failure::Error> {
+        diesel::sql_query(format!(
+            r#"
+SELECT
+    note_view_day.day,
+    SUM(note_view_day.view_count)::BIGINT AS total
+FROM note_view_day
+INNER JOIN note ON note.id = note_view_day.note_id
+WHERE note.workspace_id = $1
+AND note_view_day.day >= $2
+AND {}
+GROUP BY note_view_day.day
+ORDER BY note_view_day.day"#,
+            readable_notes_sql(3, 4)
+        ))
+        .bind::<diesel::sql_types::Uuid, _>(accessor.workspace_id)
+        .bind::<Date, _>(since)
+        .bind::<diesel::sql_types::Uuid, _>(accessor.user_id)
+        .bind::<Text, _>(&accessor.role)
+        .load(conn)
+        .map_err(Into::into)
+    }
+
+    pub fn load_totals_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+        since: NaiveDate,
+    ) -> Result<Vec<DayTotal>, failure::Error> {
+        diesel::sql_query(
+            r#"
+SELECT
+    note_view_day.day,
+    note_view_day.view_count::BIGINT AS total
+FROM note_view_day
+WHERE note_view_day.note_id = $1
+AND note_view_day.day >= $2
+ORDER BY note_view_day.day"#,
+        )
+        .bind::<diesel::sql_types::Uuid, _>(note_id)
+        .bind::<Date, _>(since)
+        .load(conn)
+        .map_err(Into::into)
+    }
+
+    /// Load the notes of which the views since `split` increased the most, compared to the views between `since` and `split`
+    pub fn load_trending(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        since: NaiveDate,
+        split: NaiveDate,
+        count: i64,
+    ) -> Result<Vec<NoteTrend>, failure::Error> {
+        diesel::sql_query(format!(
+            r#"
+SELECT
+    note.*,
+    trend.recent,
+    trend.previous
+FROM (
+    SELECT
+        note_view_day.note_id,
+        SUM(CASE WHEN note_view_day.day >= $3 THEN note_view_day.view_count ELSE 0 END)::BIGINT AS recent,
+        SUM(CASE WHEN note_view_day.day < $3 THEN note_view_day.view_count ELSE 0 END)::BIGINT AS previous
+    FROM note_view_day
+    WHERE note_view_day.day >= $2
+    GROUP BY note_view_day.note_id
+) trend
+INNER JOIN note ON note.id = trend.note_id
+WHERE note.workspace_id = $1
+AND note.deleted = false
+AND {}
+AND trend.recent > trend.previous
+ORDER BY trend.recent - trend.previous DESC
+LIMIT $4"#,
+            readable_notes_sql(5, 6)
+        ))
+        .bind::<diesel::sql_types::Uuid, _>(accessor.workspace_id)
+        .bind::<Date, _>(since)
+        .bind::<Date, _>(split)
+        .bind::<BigInt, _>(count)
+        .bind::<diesel::sql_types::Uuid, _>(accessor.user_id)
+        .bind::<Text, _>(&accessor.role)
+        .load(conn)
+        .map_err(Into::into)
+    }
+
+    /// Load the most viewed notes that have not been viewed since `since`
+    pub fn load_forgotten(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        since: NaiveDate,
+        count: i64,
+    ) -> Result<Vec<NoteLastViewed>, failure::Error> {
+        diesel::sql_query(format!(
+            r#"
+SELECT
+    note.*,
+    views.last_viewed
+FROM note
+LEFT JOIN (
+    SELECT
+        note_view_day.note_id,
+        MAX(note_view_day.day) AS last_viewed
+    FROM note_view_day
+    GROUP BY note_view_day.note_id
+) views ON views.note_id = note.id
+WHERE note.workspace_id = $1
+AND note.deleted = false
+AND {}
+AND (views.last_viewed IS NULL OR views.last_viewed < $2)
+ORDER BY note.view_count DESC
+LIMIT $3"#,
+            readable_notes_sql(4, 5)
+        ))
+        .bind::<diesel::sql_types::Uuid, _>(accessor.workspace_id)
+        .bind::<Date, _>(since)
+        .bind::<BigInt, _>(count)
+        .bind::<diesel::sql_types::Uuid, _>(accessor.user_id)
+        .bind::<Text, _>(&accessor.role)
+        .load(conn)
+        .map_err(Into::into)
+    }
+
+    pub fn delete_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(note_view_day::table.filter(note_view_day::dsl::note_id.eq(note_id)))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Roll the daily rows from before `before` up into a single row per month, stored on the first day of that month
+    pub fn compact(conn: &diesel::PgConnection, before: NaiveDate) -> Result<(), failure::Error> {
+        diesel::sql_query(
+            r#"
+INSERT INTO note_view_day (note_id, day, view_count)
+SELECT note_id, date_trunc('month', day)::DATE, SUM(view_count)
+FROM note_view_day
+WHERE day < $1 AND day <> date_trunc('month', day)::DATE
+GROUP BY 1, 2
+ON CONFLICT (note_id, day) DO UPDATE SET view_count = note_view_day.view_count + EXCLUDED.view_count"#,
+        )
+        .bind::<Date, _>(before)
+        .execute(conn)?;
+        diesel::sql_query(
+            "DELETE FROM note_view_day WHERE day < $1 AND day <> date_trunc('month', day)::DATE",
+        )
+        .bind::<Date, _>(before)
+        .execute(conn)?;
+        Ok(())
+    }
+}
+
+impl NoteLinkClickDay {
+    pub fn record(
+        conn: &diesel::PgConnection,
+        link_id: Uuid,
+        day: NaiveDate,
+    ) -> Result<(), failure::Error> {
+        diesel::insert_into(note_link_click_day::table)
+            .values(InsertNoteLinkClickDay {
+                link_id,
+                day,
+                click_count: 1,
+            })
+            .on_conflict((
+                note_link_click_day::dsl::link_id,
+                note_link_click_day::dsl::day,
+            ))
+            .do_update()
+            .set(
+                note_link_click_day::dsl::click_count.eq(note_link_click_day::dsl::click_count + 1),
+            )
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// The clicks on links in the workspace of the accessor per day, leaving out links to or from notes it can not read
+    pub fn load_totals_by_workspace(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        since: NaiveDate,
+    ) -> Result<Vec<DayTotal>, failure::Error> {
+        let readable = readable_notes_sql(3, 4);
+        diesel::sql_query(format!(
+            r#"
+SELECT
+    note_link_click_day.day,
+    SUM(note_link_click_day.click_count)::BIGINT AS total
+FROM note_link_click_day
+INNER JOIN note_link ON note_link.id = note_link_click_day.link_id
+INNER JOIN note ON note.id = note_link."left"
+WHERE note.workspace_id = $1
+AND note_link_click_day.day >= $2
+AND {}
+AND note_link."right" IN (SELECT note.id FROM note WHERE {})
+GROUP BY note_link_click_day.day
+ORDER BY note_link_click_day.day"#,
+            readable, readable
+        ))
+        .bind::<diesel::sql_types::Uuid, _>(accessor.workspace_id)
+        .bind::<Date, _>(since)
+        .bind::<diesel::sql_types::Uuid, _>(accessor.user_id)
+        .bind::<Text, _>(&accessor.role)
+        .load(conn)
+        .map_err(Into::into)
+    }
+
+    pub fn load_totals_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+        since: NaiveDate,
+    ) -> Result<Vec<DayTotal>, failure::Error> {
+        diesel::sql_query(
+            r#"
+SELECT
+    note_link_click_day.day,
+    SUM(note_link_click_day.click_count)::BIGINT AS total
+FROM note_link_click_day
+INNER JOIN note_link ON note_link.id = note_link_click_day.link_id
+WHERE (note_link."left" = $1 OR note_link."right" = $1)
+AND note_link_click_day.day >= $2
+GROUP BY note_link_click_day.day
+ORDER BY note_link_click_day.day"#,
+        )
+        .bind::<diesel::sql_types::Uuid, _>(note_id)
+        .bind::<Date, _>(since)
+        .load(conn)
+        .map_err(Into::into)
+    }
+
+    pub fn delete_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::sql_query(
+            r#"
+DELETE FROM note_link_click_day
+WHERE note_link_click_day.link_id IN (
+    SELECT note_link.id FROM note_link WHERE note_link."left" = $1 OR note_link."right" = $1
+)"#,
+        )
+        .bind::<diesel::sql_types::Uuid, _>(note_id)
+        .execute(conn)?;
+        Ok(())
+    }
+
+    /// Roll the daily rows from before `before` up into a single row per month, stored on the first day of that month
+    pub fn compact(conn: &diesel::PgConnection, before: NaiveDate) -> Result<(), failure::Error> {
+        diesel::sql_query(
+            r#"
+INSERT INTO note_link_click_day (link_id, day, click_count)
+SELECT link_id, date_trunc('month', day)::DATE, SUM(click_count)
+FROM note_link_click_day
+WHERE day < $1 AND day <> date_trunc('month', day)::DATE
+GROUP BY 1, 2
+ON CONFLICT (link_id, day) DO UPDATE SET click_count = note_link_click_day.click_count + EXCLUDED.click_count"#,
+        )
+        .bind::<Date, _>(before)
+        .execute(conn)?;
+        diesel::sql_query(
+            "DELETE FROM note_link_click_day WHERE day < $1 AND day <> date_trunc('month', day)::DATE",
+        )
+        .bind::<Date, _>(before)
+        .execute(conn)?;
+        Ok(())
+    }
+}
@@ -7,6 +7,11 @@ pub struct DatabaseUser {
     pub id: Uuid,
     pub name: String,
     pub password: String,
+    pub is_admin: bool,
+    pub totp_secret: Option<String>,
+    pub totp_last_step: Option<i64>,
+    /// The name of the timezone the user lives in, like `Europe/Amsterdam`
+    pub timezone: String,
 }
 
 #[derive(Insertable)]
@@ -48,4 +53,78 @@ impl DatabaseUser {
             .get_result(conn)
             .map_err(Into::into)
     }
+
+    pub fn set_password(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        password: &str,
+    ) -> Result<(), failure::Error> {
+        diesel::update(user::table.find(id))
+            .set(user::dsl::password.eq(password))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, id: Uuid) -> Result<(), failure::Error> {
+        diesel::delete(user::table.find(id)).execute(conn)?;
+        Ok(())
+    }
+
+    /// Enable two-factor authentication with the given secret, or disable it if the secret is `None`.
+    ///
+    /// `last_step` is the time step of the code that was used to confirm the secret, so it can not be used again.
+    pub fn set_totp(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        secret: Option<&str>,
+        last_step: Option<i64>,
+    ) -> Result<(), failure::Error> {
+        diesel::update(user::table.find(id))
+            .set((
+                user::dsl::totp_secret.eq(secret),
+                user::dsl::totp_last_step.eq(last_step),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Remember that a code for the time step was used. Returns `false` if a code for this or a later step was used before.
+    pub fn use_totp_step(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        step: i64,
+    ) -> Result<bool, failure::Error> {
+        let updated = diesel::update(
+            user::table.find(id).filter(
+                user::dsl::totp_last_step
+                    .is_null()
+                    .or(user::dsl::totp_last_step.lt(step)),
+            ),
+        )
+        .set(user::dsl::totp_last_step.eq(step))
+        .execute(conn)?;
+        Ok(updated == 1)
+    }
+
+    pub fn set_timezone(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        timezone: &str,
+    ) -> Result<(), failure::Error> {
+        diesel::update(user::table.find(id))
+            .set(user::dsl::timezone.eq(timezone))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn set_admin(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        is_admin: bool,
+    ) -> Result<(), failure::Error> {
+        diesel::update(user::table.find(id))
+            .set(user::dsl::is_admin.eq(is_admin))
+            .execute(conn)?;
+        Ok(())
+    }
 }
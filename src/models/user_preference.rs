@@ -0,0 +1,47 @@
+use crate::schema::user_preference;
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Queryable, Insertable, AsChangeset)]
+#[table_name = "user_preference"]
+#[primary_key(user_id)]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct UserPreference {
+    pub user_id: Uuid,
+    pub index_sort: String,
+    pub index_orphans: bool,
+    pub index_from: Option<NaiveDate>,
+    pub index_to: Option<NaiveDate>,
+}
+
+impl UserPreference {
+    pub fn load_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<Option<UserPreference>, failure::Error> {
+        user_preference::table
+            .find(user_id)
+            .get_result(conn)
+            .optional()
+            .map_err(Into::into)
+    }
+
+    pub fn save(&self, conn: &diesel::PgConnection) -> Result<(), failure::Error> {
+        diesel::insert_into(user_preference::table)
+            .values(self)
+            .on_conflict(user_preference::dsl::user_id)
+            .do_update()
+            .set(self)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(user_preference::table.find(user_id)).execute(conn)?;
+        Ok(())
+    }
+}
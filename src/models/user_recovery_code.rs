@@ -0,0 +1,68 @@
+use crate::schema::user_recovery_code;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Insertable)]
+#[table_name = "user_recovery_code"]
+struct InsertRecoveryCode<'a> {
+    pub user_id: Uuid,
+    pub code_hash: &'a str,
+}
+
+pub struct RecoveryCode;
+
+impl RecoveryCode {
+    /// Replace the recovery codes of the user with the codes with the given hashes
+    pub fn replace_all(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        code_hashes: &[String],
+    ) -> Result<(), failure::Error> {
+        RecoveryCode::delete_by_user(conn, user_id)?;
+        let codes: Vec<InsertRecoveryCode> = code_hashes
+            .iter()
+            .map(|code_hash| InsertRecoveryCode { user_id, code_hash })
+            .collect();
+        diesel::insert_into(user_recovery_code::table)
+            .values(&codes)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Use up the code with the given hash. Returns `false` if the user has no such code.
+    pub fn use_code(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        code_hash: &str,
+    ) -> Result<bool, failure::Error> {
+        let deleted = diesel::delete(
+            user_recovery_code::table
+                .filter(user_recovery_code::dsl::user_id.eq(user_id))
+                .filter(user_recovery_code::dsl::code_hash.eq(code_hash)),
+        )
+        .execute(conn)?;
+        Ok(deleted > 0)
+    }
+
+    pub fn count_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<i64, failure::Error> {
+        user_recovery_code::table
+            .filter(user_recovery_code::dsl::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .map_err(Into::into)
+    }
+
+    pub fn delete_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(
+            user_recovery_code::table.filter(user_recovery_code::dsl::user_id.eq(user_id)),
+        )
+        .execute(conn)?;
+        Ok(())
+    }
+}
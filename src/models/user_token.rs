@@ -48,6 +48,40 @@ impl UserToken {
         Ok(())
     }
 
+    /// Deactivate the token, so it can not be used to log in anymore
+    pub fn deactivate(conn: &diesel::PgConnection, id: Uuid) -> Result<(), failure::Error> {
+        diesel::update(user_token::table.find(id))
+            .set(user_token::dsl::active.eq(false))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Deactivate all tokens of the user, except for `except`. Returns the amount of tokens that were deactivated.
+    pub fn deactivate_all_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        except: Option<Uuid>,
+    ) -> Result<usize, failure::Error> {
+        diesel::update(
+            user_token::table
+                .filter(user_token::dsl::user_id.eq(user_id))
+                .filter(user_token::dsl::active.eq(true))
+                .filter(user_token::dsl::id.ne(except.unwrap_or_else(Uuid::nil))),
+        )
+        .set(user_token::dsl::active.eq(false))
+        .execute(conn)
+        .map_err(Into::into)
+    }
+
+    pub fn delete_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(user_token::table.filter(user_token::dsl::user_id.eq(user_id)))
+            .execute(conn)?;
+        Ok(())
+    }
+
     pub fn create(
         conn: &diesel::PgConnection,
         user_id: Uuid,
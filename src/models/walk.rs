@@ -0,0 +1,140 @@
+use super::note::Note;
+use super::note_acl::Accessor;
+use crate::schema::{note, walk, walk_step};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Queryable)]
+pub struct Walk {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub created: DateTime<Utc>,
+    pub workspace_id: Uuid,
+}
+
+#[derive(Insertable)]
+#[table_name = "walk"]
+struct InsertWalk {
+    pub user_id: Uuid,
+    pub created: DateTime<Utc>,
+    pub workspace_id: Uuid,
+}
+
+#[derive(Insertable)]
+#[table_name = "walk_step"]
+struct InsertWalkStep {
+    pub walk_id: Uuid,
+    pub position: i32,
+    pub note_id: Uuid,
+}
+
+impl Walk {
+    pub fn create(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        workspace_id: Uuid,
+    ) -> Result<Walk, failure::Error> {
+        diesel::insert_into(walk::table)
+            .values(InsertWalk {
+                user_id,
+                created: Utc::now(),
+                workspace_id,
+            })
+            .get_result(conn)
+            .map_err(Into::into)
+    }
+
+    pub fn load_by_id(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        user_id: Uuid,
+        workspace_id: Uuid,
+    ) -> Result<Option<Walk>, failure::Error> {
+        walk::table
+            .filter(walk::dsl::id.eq(id).and(walk::dsl::user_id.eq(user_id)))
+            .filter(walk::dsl::workspace_id.eq(workspace_id))
+            .get_result(conn)
+            .optional()
+            .map_err(Into::into)
+    }
+
+    pub fn load_recent(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        workspace_id: Uuid,
+        count: i64,
+    ) -> Result<Vec<Walk>, failure::Error> {
+        walk::table
+            .filter(walk::dsl::user_id.eq(user_id))
+            .filter(walk::dsl::workspace_id.eq(workspace_id))
+            .order(walk::dsl::created.desc())
+            .limit(count)
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    /// Load the notes of the walk in order, skipping the notes that the accessor is not allowed to read (anymore)
+    pub fn load_notes(
+        conn: &diesel::PgConnection,
+        walk_id: Uuid,
+        accessor: &Accessor,
+    ) -> Result<Vec<Note>, failure::Error> {
+        walk_step::table
+            .filter(walk_step::dsl::walk_id.eq(walk_id))
+            .inner_join(note::table)
+            .filter(note::dsl::id.eq_any(accessor.readable_notes()))
+            .order(walk_step::dsl::position.asc())
+            .select(note::all_columns)
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    pub fn add_step(
+        conn: &diesel::PgConnection,
+        walk_id: Uuid,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        let last_position: Option<i32> = walk_step::table
+            .filter(walk_step::dsl::walk_id.eq(walk_id))
+            .select(diesel::dsl::max(walk_step::dsl::position))
+            .get_result(conn)?;
+        diesel::insert_into(walk_step::table)
+            .values(InsertWalkStep {
+                walk_id,
+                position: last_position.map(|p| p + 1).unwrap_or(0),
+                note_id,
+            })
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete_steps_by_note(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(walk_step::table.filter(walk_step::dsl::note_id.eq(note_id)))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Delete the walks of the user, and all walks in the given workspaces
+    pub fn delete_by_user_or_workspaces(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        workspace_ids: &[Uuid],
+    ) -> Result<(), failure::Error> {
+        let walks = walk::table.filter(
+            walk::dsl::user_id
+                .eq(user_id)
+                .or(walk::dsl::workspace_id.eq_any(workspace_ids)),
+        );
+        diesel::delete(
+            walk_step::table
+                .filter(walk_step::dsl::walk_id.eq_any(walks.clone().select(walk::dsl::id))),
+        )
+        .execute(conn)?;
+        diesel::delete(walks).execute(conn)?;
+        Ok(())
+    }
+}
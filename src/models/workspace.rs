@@ -0,0 +1,199 @@
+use crate::schema::{user, workspace, workspace_member};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Queryable)]
+pub struct Workspace {
+    pub id: Uuid,
+    pub name: String,
+    pub created: DateTime<Utc>,
+    /// The template that new notes in the workspace start from, unless another one is chosen
+    pub default_template_id: Option<Uuid>,
+    /// The `strftime` format of the titles of journal notes
+    pub journal_title_format: String,
+    /// The template that journal notes start from
+    pub journal_template_id: Option<Uuid>,
+}
+
+#[derive(Insertable)]
+#[table_name = "workspace"]
+struct InsertWorkspace<'a> {
+    pub name: &'a str,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "workspace_member"]
+pub struct WorkspaceMember {
+    pub workspace_id: Uuid,
+    pub user_id: Uuid,
+    pub role: String,
+}
+
+/// A workspace together with the role that a user has in it
+#[derive(Queryable)]
+pub struct WorkspaceMembership {
+    pub workspace: Workspace,
+    pub role: String,
+}
+
+/// A member of a workspace together with their user name
+#[derive(Queryable)]
+pub struct WorkspaceMemberName {
+    pub user_id: Uuid,
+    pub name: String,
+    pub role: String,
+}
+
+impl Workspace {
+    pub fn create(conn: &diesel::PgConnection, name: &str) -> Result<Workspace, failure::Error> {
+        diesel::insert_into(workspace::table)
+            .values(InsertWorkspace {
+                name,
+                created: Utc::now(),
+            })
+            .get_result(conn)
+            .map_err(Into::into)
+    }
+
+    /// Delete the workspaces. Their notes, walks and members have to be deleted first.
+    pub fn delete_all(conn: &diesel::PgConnection, ids: &[Uuid]) -> Result<(), failure::Error> {
+        diesel::delete(workspace::table.filter(workspace::dsl::id.eq_any(ids))).execute(conn)?;
+        Ok(())
+    }
+
+    pub fn set_default_template(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        template_id: Option<Uuid>,
+    ) -> Result<(), failure::Error> {
+        diesel::update(workspace::table.find(id))
+            .set(workspace::dsl::default_template_id.eq(template_id))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn set_journal(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        title_format: &str,
+        template_id: Option<Uuid>,
+    ) -> Result<(), failure::Error> {
+        diesel::update(workspace::table.find(id))
+            .set((
+                workspace::dsl::journal_title_format.eq(title_format),
+                workspace::dsl::journal_template_id.eq(template_id),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Stop using the note as a template of any workspace, because it is deleted or no longer a template
+    pub fn clear_template(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::update(workspace::table.filter(workspace::dsl::default_template_id.eq(note_id)))
+            .set(workspace::dsl::default_template_id.eq(None::<Uuid>))
+            .execute(conn)?;
+        diesel::update(workspace::table.filter(workspace::dsl::journal_template_id.eq(note_id)))
+            .set(workspace::dsl::journal_template_id.eq(None::<Uuid>))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Load the workspace, if the user is a member of it
+    pub fn load_by_member(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<WorkspaceMembership>, failure::Error> {
+        workspace::table
+            .inner_join(workspace_member::table)
+            .filter(workspace::dsl::id.eq(id))
+            .filter(workspace_member::dsl::user_id.eq(user_id))
+            .select((workspace::all_columns, workspace_member::dsl::role))
+            .get_result(conn)
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// Load all workspaces the user is a member of, oldest first
+    pub fn load_all_by_member(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<Vec<WorkspaceMembership>, failure::Error> {
+        workspace::table
+            .inner_join(workspace_member::table)
+            .filter(workspace_member::dsl::user_id.eq(user_id))
+            .order((workspace::dsl::created.asc(), workspace::dsl::id))
+            .select((workspace::all_columns, workspace_member::dsl::role))
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+
+    pub fn load_members(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+    ) -> Result<Vec<WorkspaceMemberName>, failure::Error> {
+        workspace_member::table
+            .inner_join(user::table)
+            .filter(workspace_member::dsl::workspace_id.eq(id))
+            .order(user::dsl::name.asc())
+            .select((
+                workspace_member::dsl::user_id,
+                user::dsl::name,
+                workspace_member::dsl::role,
+            ))
+            .get_results(conn)
+            .map_err(Into::into)
+    }
+}
+
+impl WorkspaceMember {
+    /// Add the user to the workspace, or change their role if they are already a member
+    pub fn save(&self, conn: &diesel::PgConnection) -> Result<(), failure::Error> {
+        diesel::insert_into(workspace_member::table)
+            .values(self)
+            .on_conflict((
+                workspace_member::dsl::workspace_id,
+                workspace_member::dsl::user_id,
+            ))
+            .do_update()
+            .set(workspace_member::dsl::role.eq(&self.role))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete(
+        conn: &diesel::PgConnection,
+        workspace_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(workspace_member::table.find((workspace_id, user_id))).execute(conn)?;
+        Ok(())
+    }
+
+    pub fn delete_by_user(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        diesel::delete(workspace_member::table.filter(workspace_member::dsl::user_id.eq(user_id)))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn count_by_role(
+        conn: &diesel::PgConnection,
+        workspace_id: Uuid,
+        role: &str,
+    ) -> Result<i64, failure::Error> {
+        workspace_member::table
+            .filter(workspace_member::dsl::workspace_id.eq(workspace_id))
+            .filter(workspace_member::dsl::role.eq(role))
+            .count()
+            .get_result(conn)
+            .map_err(Into::into)
+    }
+}
@@ -1,18 +1,142 @@
-use crate::models::note::{Note as DatabaseNote, NoteHistory as DatabaseNoteHistory};
+use crate::attachment::Attachments;
+use crate::markdown::{Embed, MAX_EMBED_DEPTH};
+use crate::models::attachment::Attachment as DatabaseAttachment;
+use crate::models::journal::JournalEntry;
+use crate::models::note::{
+    transaction, Note as DatabaseNote, NoteHistory as DatabaseNoteHistory, NoteSeoAlias, Revision,
+};
+use crate::models::note_acl::{Accessor, NoteAcl};
 use crate::models::note_link::NoteLink as DatabaseNoteLink;
+use crate::models::note_share::NoteShare;
+use crate::models::statistics::{NoteLinkClickDay, NoteViewDay};
+use crate::models::user_preference::UserPreference;
+use crate::models::walk::Walk as DatabaseWalk;
+use crate::models::workspace::Workspace as DatabaseWorkspace;
 use crate::routes::SearchQuery;
-use chrono::{DateTime, Utc};
-use failure::format_err;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use failure::{bail, format_err};
 use slug::slugify;
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::str::FromStr;
 use uuid::Uuid;
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteSort {
+    Title,
+    MostViewed,
+    LeastViewed,
+    RecentlyCreated,
+    RecentlyEdited,
+    MostLinks,
+}
+
+impl Default for NoteSort {
+    fn default() -> NoteSort {
+        NoteSort::MostViewed
+    }
+}
+
+impl NoteSort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NoteSort::Title => "title",
+            NoteSort::MostViewed => "most_viewed",
+            NoteSort::LeastViewed => "least_viewed",
+            NoteSort::RecentlyCreated => "created",
+            NoteSort::RecentlyEdited => "edited",
+            NoteSort::MostLinks => "links",
+        }
+    }
+}
+
+impl FromStr for NoteSort {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<NoteSort, failure::Error> {
+        Ok(match s {
+            "title" => NoteSort::Title,
+            "most_viewed" => NoteSort::MostViewed,
+            "least_viewed" => NoteSort::LeastViewed,
+            "created" => NoteSort::RecentlyCreated,
+            "edited" => NoteSort::RecentlyEdited,
+            "links" => NoteSort::MostLinks,
+            _ => bail!("Unknown sort order {:?}", s),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NoteFilter {
+    pub sort: NoteSort,
+    /// Only show notes without any links
+    pub orphans: bool,
+    /// Only show notes created on or after this date
+    pub from: Option<NaiveDate>,
+    /// Only show notes created on or before this date
+    pub to: Option<NaiveDate>,
+}
+
+impl NoteFilter {
+    pub fn load_preference(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<NoteFilter, failure::Error> {
+        Ok(match UserPreference::load_by_user(conn, user_id)? {
+            Some(p) => NoteFilter {
+                sort: p.index_sort.parse().unwrap_or_default(),
+                orphans: p.index_orphans,
+                from: p.index_from,
+                to: p.index_to,
+            },
+            None => NoteFilter::default(),
+        })
+    }
+
+    pub fn save_preference(
+        &self,
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        UserPreference {
+            user_id,
+            index_sort: self.sort.as_str().to_owned(),
+            index_orphans: self.orphans,
+            index_from: self.from,
+            index_to: self.to,
+        }
+        .save(conn)
+    }
+}
+
+#[derive(Serialize, Clone)]
 pub struct Note {
     pub id: Uuid,
+    /// The user that created the note
     pub user_id: Uuid,
+    pub workspace_id: Uuid,
     pub seo_name: String,
     pub title: String,
     pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// If set, the seo name was chosen by the user and does not follow the title
+    pub seo_name_pinned: bool,
+    /// Whether new notes can be created from this note, see `Note::create_from_template`
+    pub is_template: bool,
+}
+
+impl From<Note> for Embed {
+    fn from(note: Note) -> Embed {
+        Embed {
+            url: format!("/n/{}", note.seo_name),
+            attachments: Some(format!("/attachment/{}/", note.id)),
+            seo_name: note.seo_name,
+            title: note.title,
+            body: note.body,
+        }
+    }
 }
 
 impl From<DatabaseNote> for Note {
@@ -20,40 +144,61 @@ impl From<DatabaseNote> for Note {
         Note {
             id: n.id,
             user_id: n.user_id,
+            workspace_id: n.workspace_id,
             seo_name: n.seo_name,
             title: n.title,
             body: n.body,
+            created_at: n.created_at,
+            updated_at: n.updated_at,
+            seo_name_pinned: n.seo_name_pinned,
+            is_template: n.is_template,
         }
     }
 }
 
+pub enum NoteLookup {
+    Found(Note),
+    /// The note was found by a seo name it had before it was renamed
+    Renamed(Note),
+    NotFound,
+}
+
 #[derive(Serialize)]
 pub struct NoteHistory {
     pub created: DateTime<Utc>,
     pub title: String,
     pub body: String,
+    /// The name of the user that made this revision, unknown for revisions made before this was recorded
+    pub user_name: Option<String>,
+    /// The session token this revision was made with
+    pub token_id: Option<Uuid>,
+    pub summary: Option<String>,
 }
 
-impl From<DatabaseNoteHistory> for NoteHistory {
-    fn from(n: DatabaseNoteHistory) -> NoteHistory {
+impl From<(DatabaseNoteHistory, Option<String>)> for NoteHistory {
+    fn from((n, user_name): (DatabaseNoteHistory, Option<String>)) -> NoteHistory {
         NoteHistory {
             created: n.created,
             title: n.title,
             body: n.body,
+            user_name,
+            token_id: n.token_id,
+            summary: n.summary,
         }
     }
 }
 
 #[derive(Serialize)]
 pub struct NoteLink {
-    pub note: Note,
+    /// The linked note, or `None` if it is a private note that the user is not allowed to read
+    pub note: Option<Note>,
     pub link: Link,
 }
 
 impl From<DatabaseNoteLink> for NoteLink {
     fn from(l: DatabaseNoteLink) -> NoteLink {
         NoteLink {
-            note: l.other.into(),
+            note: l.other.map(Into::into),
             link: Link { id: l.id },
         }
     }
@@ -65,36 +210,59 @@ pub struct Link {
 }
 
 impl Link {
-    pub fn increase_click_count(&self, conn: &diesel::PgConnection) -> Result<(), failure::Error> {
-        DatabaseNoteLink::increase_click_count(conn, self.id)
+    /// Count a click on this link, if it is a link between notes of the given workspace
+    pub fn increase_click_count(
+        &self,
+        conn: &diesel::PgConnection,
+        workspace_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        if DatabaseNoteLink::increase_click_count(conn, self.id, workspace_id)? {
+            NoteLinkClickDay::record(conn, self.id, Utc::today().naive_utc())?;
+        }
+        Ok(())
     }
 }
 
 impl Note {
-    pub fn load_paged(
+    pub fn load_filtered(
         conn: &diesel::PgConnection,
-        user_id: Uuid,
+        accessor: &Accessor,
+        filter: &NoteFilter,
         start_index: u64,
         count: u64,
     ) -> Result<Vec<Note>, failure::Error> {
+        // Pages past the end of what the database can count are just as empty as the ones past the last note
+        let start_index = i64::try_from(start_index).unwrap_or(i64::MAX);
+        let count = i64::try_from(count).unwrap_or(i64::MAX);
         Ok(
-            DatabaseNote::load_paged(conn, user_id, start_index as i64, count as i64)?
+            DatabaseNote::load_filtered(conn, accessor, filter, start_index, count)?
                 .into_iter()
                 .map(Into::into)
                 .collect(),
         )
     }
 
-    pub fn count_all(conn: &diesel::PgConnection, user_id: Uuid) -> Result<u64, failure::Error> {
-        DatabaseNote::count_by_user(conn, user_id).map(|count| count as u64)
+    pub fn count_filtered(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        filter: &NoteFilter,
+    ) -> Result<u64, failure::Error> {
+        DatabaseNote::count_filtered(conn, accessor, filter).map(|count| count as u64)
+    }
+
+    pub fn count_all(
+        conn: &diesel::PgConnection,
+        workspace_id: Uuid,
+    ) -> Result<u64, failure::Error> {
+        DatabaseNote::count_by_workspace(conn, workspace_id).map(|count| count as u64)
     }
 
     pub fn search(
         conn: &diesel::PgConnection,
         search: SearchQuery,
-        user_id: Uuid,
+        accessor: &Accessor,
     ) -> Result<Vec<Note>, failure::Error> {
-        Ok(DatabaseNote::search(conn, search, user_id)?
+        Ok(DatabaseNote::search(conn, search, accessor)?
             .into_iter()
             .map(Into::into)
             .collect())
@@ -103,84 +271,309 @@ impl Note {
     pub fn delete_by_seo_name(
         conn: &diesel::PgConnection,
         name: &str,
-        user_id: Uuid,
+        accessor: &Accessor,
+        attachments: &Attachments,
     ) -> Result<(), failure::Error> {
-        let note = Note::load_by_seo_name(conn, name, user_id)?
+        let note = Note::load_by_seo_name(conn, name, accessor)?
             .ok_or_else(|| format_err!("Note not found"))?;
-        DatabaseNoteLink::delete_by_note(conn, note.id)?;
-        DatabaseNoteHistory::delete_by_note(conn, note.id)?;
-        DatabaseNote::delete(conn, note.id)?;
+        let deleted = transaction(conn, || {
+            let deleted = delete_dependents(conn, note.id)?;
+            DatabaseNote::delete(conn, note.id, accessor)?;
+            Ok(deleted)
+        })?;
+        attachments.remove_files(&deleted);
         Ok(())
     }
 
+    /// Delete the notes the user created, and all notes in the given workspaces, without checking access.
+    ///
+    /// This is used when the account of the user is deleted. Returns the attachments of the deleted notes,
+    /// whose files should be removed once the transaction is committed.
+    pub fn delete_by_user_or_workspaces(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+        workspace_ids: &[Uuid],
+    ) -> Result<Vec<DatabaseAttachment>, failure::Error> {
+        transaction(conn, || {
+            let mut deleted = Vec::new();
+            for id in DatabaseNote::load_ids_by_user_or_workspaces(conn, user_id, workspace_ids)? {
+                deleted.extend(delete_dependents(conn, id)?);
+                DatabaseNote::delete_by_id(conn, id)?;
+            }
+            Ok(deleted)
+        })
+    }
+
     pub fn load_by_id(
         conn: &diesel::PgConnection,
         id: Uuid,
+        accessor: &Accessor,
     ) -> Result<Option<Note>, failure::Error> {
-        DatabaseNote::load_by_id(conn, id).map(|o| o.map(Into::into))
+        DatabaseNote::load_by_id(conn, id, accessor).map(|o| o.map(Into::into))
+    }
+
+    pub fn load_random(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<Option<Note>, failure::Error> {
+        DatabaseNote::load_random(conn, accessor).map(|o| o.map(Into::into))
     }
 
     pub fn load_by_seo_name(
         conn: &diesel::PgConnection,
         name: &str,
-        user_id: Uuid,
+        accessor: &Accessor,
     ) -> Result<Option<Note>, failure::Error> {
-        DatabaseNote::load_by_seo_name(conn, name, user_id).map(|o| o.map(Into::into))
+        DatabaseNote::load_by_seo_name(conn, name, accessor).map(|o| o.map(Into::into))
     }
 
+    /// Load the note by its current seo name, or by a seo name it had before it was renamed
+    pub fn lookup(
+        conn: &diesel::PgConnection,
+        name: &str,
+        accessor: &Accessor,
+    ) -> Result<NoteLookup, failure::Error> {
+        if let Some(note) = DatabaseNote::load_by_seo_name(conn, name, accessor)? {
+            return Ok(NoteLookup::Found(note.into()));
+        }
+        Ok(match DatabaseNote::load_by_alias(conn, name, accessor)? {
+            Some(note) => NoteLookup::Renamed(note.into()),
+            None => NoteLookup::NotFound,
+        })
+    }
+
+    /// Create a note in the workspace of the accessor, linked to the notes that it embeds
     pub fn create(
         conn: &diesel::PgConnection,
         title: &str,
         body: &str,
-        user_id: Uuid,
+        accessor: &Accessor,
+        revision: &Revision,
     ) -> Result<Note, failure::Error> {
-        let mut seo_name_base = slugify(title);
-        if seo_name_base.is_empty() {
-            seo_name_base = "-".to_owned();
-        }
-        let mut seo_name = seo_name_base.clone();
-        let mut counter = 1;
-        while DatabaseNote::load_by_seo_name(conn, &seo_name, user_id)?.is_some() {
-            seo_name = format!("{}_{}", seo_name_base, counter);
-            counter += 1;
-        }
+        let workspace_id = accessor.workspace_id;
+        transaction(conn, || {
+            let seo_name_base = seo_name_base(title);
+            let mut seo_name = seo_name_base.clone();
+            // Another request can claim the same seo name between picking and inserting it,
+            // in which case the insert does nothing and the next free name is tried.
+            let note = loop {
+                if let Some(note) =
+                    DatabaseNote::create(conn, &seo_name, title, body, workspace_id, revision)?
+                {
+                    break note;
+                }
+                seo_name = next_free_seo_name(conn, &seo_name_base, workspace_id, None)?;
+            };
+            NoteSeoAlias::delete_by_seo_name(conn, &note.seo_name, workspace_id)?;
+            let note: Note = note.into();
+            note.link_embedded_notes(conn, accessor)?;
+            Ok(note)
+        })
+    }
+
+    /// Create a note from a template, filling in the placeholders of its title and body:
+    /// `{{title}}` with the given title, and `{{date}}`, `{{time}}` and `{{weekday}}` with the moment `now`.
+    /// If the given title is empty, the note gets the title of the template instead.
+    pub fn create_from_template(
+        conn: &diesel::PgConnection,
+        title: &str,
+        template: &Note,
+        now: NaiveDateTime,
+        accessor: &Accessor,
+        revision: &Revision,
+    ) -> Result<Note, failure::Error> {
+        let title = if title.trim().is_empty() {
+            fill_placeholders(&template.title, "", now)
+        } else {
+            title.to_owned()
+        };
+        let body = fill_placeholders(&template.body, &title, now);
+        Note::create(conn, &title, &body, accessor, revision)
+    }
+
+    /// Load the templates of the workspace that the accessor can read, ordered by title
+    pub fn load_templates(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<Vec<Note>, failure::Error> {
+        DatabaseNote::load_templates(conn, accessor)
+            .map(|notes| notes.into_iter().map(Into::into).collect())
+    }
 
-        Ok(DatabaseNote::create(conn, &seo_name, title, body, user_id)?.into())
+    /// Load the template with the given id, if the accessor can read it
+    pub fn load_template(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        accessor: &Accessor,
+    ) -> Result<Option<Note>, failure::Error> {
+        Ok(Note::load_by_id(conn, id, accessor)?.filter(|note| note.is_template))
     }
 
+    /// Mark this note as a template that new notes can be created from, or stop using it as one.
+    /// A note that is no longer a template is no longer the default or journal template of its workspace either.
+    pub fn set_template(
+        &mut self,
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        is_template: bool,
+    ) -> Result<(), failure::Error> {
+        transaction(conn, || {
+            if !DatabaseNote::set_template(conn, self.id, accessor, is_template)? {
+                bail!("You are not allowed to change this note");
+            }
+            if !is_template {
+                DatabaseWorkspace::clear_template(conn, self.id)?;
+            }
+            Ok(())
+        })?;
+        self.is_template = is_template;
+        Ok(())
+    }
+
+    /// Update the title and body of this note.
+    ///
+    /// If `custom_seo_name` is given, the seo name is pinned to it. Otherwise the seo name is derived from the new title.
+    /// If the seo name changes, the old name is remembered so that existing urls keep working.
+    /// The note is linked to the notes that the new body embeds.
     pub fn update(
         &mut self,
         conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        revision: &Revision,
         new_title: &str,
         new_body: &str,
+        custom_seo_name: Option<&str>,
     ) -> Result<(), failure::Error> {
-        let mut seo_name_base = slugify(new_title);
-        if seo_name_base.is_empty() {
-            seo_name_base = "-".to_owned();
-        }
-        let mut seo_name = seo_name_base.clone();
-        let mut counter = 1;
-        loop {
-            if seo_name == self.seo_name {
-                break;
+        let result = transaction(conn, || {
+            let seo_name_base = seo_name_base(custom_seo_name.unwrap_or(new_title));
+            let mut seo_name = if seo_name_base == self.seo_name {
+                seo_name_base.clone()
+            } else {
+                next_free_seo_name(conn, &seo_name_base, self.workspace_id, Some(self.id))?
+            };
+            let result = loop {
+                if let Some(note) = DatabaseNote::update(
+                    conn,
+                    self.id,
+                    accessor,
+                    revision,
+                    &seo_name,
+                    custom_seo_name.is_some(),
+                    new_title,
+                    new_body,
+                )? {
+                    break note;
+                }
+                seo_name =
+                    next_free_seo_name(conn, &seo_name_base, self.workspace_id, Some(self.id))?;
+            };
+            if result.seo_name != self.seo_name {
+                NoteSeoAlias::delete_by_seo_name(conn, &result.seo_name, self.workspace_id)?;
+                NoteSeoAlias::create(conn, self.id, self.workspace_id, &self.seo_name)?;
             }
-            if DatabaseNote::load_by_seo_name(conn, &seo_name, self.user_id)?.is_none() {
-                break;
+            let result: Note = result.into();
+            result.link_embedded_notes(conn, accessor)?;
+            Ok(result)
+        })?;
+        *self = result;
+        Ok(())
+    }
+
+    /// Link this note to the notes that it embeds with `![[seo-name]]`, so embedding a note associates it like a link does.
+    /// The links it got for notes that it no longer embeds are removed.
+    fn link_embedded_notes(
+        &self,
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<(), failure::Error> {
+        let mut embedded = Vec::new();
+        for name in crate::markdown::embedded_notes(&self.body) {
+            if let NoteLookup::Found(other) | NoteLookup::Renamed(other) =
+                Note::lookup(conn, &name, accessor)?
+            {
+                if other.id != self.id {
+                    embedded.push(other.id);
+                }
+            }
+        }
+        DatabaseNoteLink::delete_embedded_except(conn, self.id, &embedded)?;
+        let mut linked = DatabaseNoteLink::load_linked_ids(conn, self.id)?;
+        for id in embedded {
+            if linked.insert(id) {
+                DatabaseNoteLink::create(conn, self.id, id, true)?;
             }
-            seo_name = format!("{}_{}", seo_name_base, counter);
-            counter += 1;
         }
-
-        let result = DatabaseNote::update(conn, self.id, &seo_name, new_title, new_body)?;
-        *self = result.into();
         Ok(())
     }
 
+    /// Load the notes that this note embeds with `![[seo-name]]`, and the notes that those embed,
+    /// up to `MAX_EMBED_DEPTH` levels deep. Notes that the accessor cannot read are left out.
+    pub fn load_embeds(
+        &self,
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<HashMap<String, Embed>, failure::Error> {
+        let mut embeds = HashMap::new();
+        let mut bodies = vec![self.body.clone()];
+        for _ in 0..MAX_EMBED_DEPTH {
+            let mut next = Vec::new();
+            for body in &bodies {
+                for name in crate::markdown::embedded_notes(body) {
+                    if embeds.contains_key(&name) {
+                        continue;
+                    }
+                    if let NoteLookup::Found(note) | NoteLookup::Renamed(note) =
+                        Note::lookup(conn, &name, accessor)?
+                    {
+                        next.push(note.body.clone());
+                        embeds.insert(name, note.into());
+                    }
+                }
+            }
+            bodies = next;
+        }
+        Ok(embeds)
+    }
+
+    /// Tick off or reopen the task with the given index in the task lists of this note.
+    /// Nothing changes if the note has no such task. Returns `false` if the accessor is not allowed to change this note.
+    pub fn toggle_task(
+        &mut self,
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        revision: &Revision,
+        index: usize,
+    ) -> Result<bool, failure::Error> {
+        if !self.can_write(conn, accessor)? {
+            return Ok(false);
+        }
+        let body = match crate::markdown::toggle_task(&self.body, index) {
+            Some(body) => body,
+            None => return Ok(true),
+        };
+        let title = self.title.clone();
+        let custom_seo_name = if self.seo_name_pinned {
+            Some(self.seo_name.clone())
+        } else {
+            None
+        };
+        self.update(
+            conn,
+            accessor,
+            revision,
+            &title,
+            &body,
+            custom_seo_name.as_deref(),
+        )?;
+        Ok(true)
+    }
+
     pub fn load_history(
         &self,
         conn: &diesel::PgConnection,
+        accessor: &Accessor,
     ) -> Result<Vec<NoteHistory>, failure::Error> {
-        Ok(DatabaseNote::load_history(conn, self.id)?
+        Ok(DatabaseNote::load_history(conn, self.id, accessor)?
             .into_iter()
             .map(Into::into)
             .collect())
@@ -190,21 +583,260 @@ impl Note {
         &self,
         conn: &diesel::PgConnection,
         other: &Note,
+        accessor: &Accessor,
     ) -> Result<(), failure::Error> {
-        DatabaseNoteLink::create(conn, self.id, other.id)?;
+        if !self.can_write(conn, accessor)? {
+            bail!("You are not allowed to change this note");
+        }
+        DatabaseNoteLink::create(conn, self.id, other.id, false)?;
         Ok(())
     }
 
+    /// Whether the access control list of this note allows the accessor to change it
+    pub fn can_write(
+        &self,
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<bool, failure::Error> {
+        DatabaseNote::is_writable(conn, self.id, accessor)
+    }
+
     pub fn increase_view_count(
         &mut self,
         conn: &diesel::PgConnection,
     ) -> Result<(), failure::Error> {
         DatabaseNote::increase_view_count(conn, self.id)?;
+        NoteViewDay::record(conn, self.id, Utc::today().naive_utc())?;
         Ok(())
     }
 
-    pub fn load_links(&self, conn: &diesel::PgConnection) -> Result<Vec<NoteLink>, failure::Error> {
-        let links = DatabaseNoteLink::load_by_note(conn, self.id)?;
+    /// Load the links of this note. Links to notes that the accessor cannot read are included, without the note.
+    pub fn load_links(
+        &self,
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<Vec<NoteLink>, failure::Error> {
+        let links = DatabaseNoteLink::load_by_note(conn, self.id, accessor)?;
         Ok(links.into_iter().map(Into::into).collect())
     }
+
+    /// Load the notes that are at most `depth` links away from this note, including this note itself.
+    ///
+    /// Notes that the accessor cannot read are left out, and their links are not followed.
+    pub fn load_neighbourhood(
+        self,
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        depth: u32,
+    ) -> Result<HashMap<Uuid, Note>, failure::Error> {
+        let mut frontier = vec![self.id];
+        let mut notes = HashMap::new();
+        notes.insert(self.id, self);
+        for _ in 0..depth {
+            let mut next = Vec::new();
+            for id in frontier {
+                for other in DatabaseNoteLink::load_by_note(conn, id, accessor)?
+                    .into_iter()
+                    .filter_map(|l| l.other)
+                {
+                    if let Entry::Vacant(entry) = notes.entry(other.id) {
+                        next.push(other.id);
+                        entry.insert(other.into());
+                    }
+                }
+            }
+            frontier = next;
+        }
+        Ok(notes)
+    }
+}
+
+/// Delete everything that refers to the note, and return its attachments, whose files still have to be removed
+fn delete_dependents(
+    conn: &diesel::PgConnection,
+    id: Uuid,
+) -> Result<Vec<DatabaseAttachment>, failure::Error> {
+    NoteLinkClickDay::delete_by_note(conn, id)?;
+    NoteViewDay::delete_by_note(conn, id)?;
+    DatabaseNoteLink::delete_by_note(conn, id)?;
+    DatabaseNoteHistory::delete_by_note(conn, id)?;
+    DatabaseWalk::delete_steps_by_note(conn, id)?;
+    NoteSeoAlias::delete_by_note(conn, id)?;
+    NoteShare::delete_by_note(conn, id)?;
+    NoteAcl::delete_by_note(conn, id)?;
+    DatabaseWorkspace::clear_template(conn, id)?;
+    JournalEntry::delete_by_note(conn, id)?;
+    DatabaseAttachment::delete_by_note(conn, id)
+}
+
+/// Fill in the placeholders of a template, see `Note::create_from_template`
+///
+/// The text is scanned once, so placeholders in the values that are filled in, like a title with `{{date}}` in it, stay as they are.
+fn fill_placeholders(text: &str, title: &str, now: NaiveDateTime) -> String {
+    let values = [
+        ("{{title}}", title.to_owned()),
+        ("{{date}}", now.format("%Y-%m-%d").to_string()),
+        ("{{time}}", now.format("%H:%M").to_string()),
+        ("{{weekday}}", now.format("%A").to_string()),
+    ];
+    let mut filled = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match values.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                filled.push_str(value);
+                rest = &rest[name.len()..];
+            }
+            None => {
+                filled.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn seo_name_base(title: &str) -> String {
+    let base = slugify(title);
+    if base.is_empty() {
+        "-".to_owned()
+    } else {
+        base
+    }
+}
+
+/// Find the first seo name of the form `base`, `base_1`, `base_2`, ... that is not used by any other note in the workspace.
+///
+/// This is only a suggestion; the unique index on the note table decides who gets the name.
+fn next_free_seo_name(
+    conn: &diesel::PgConnection,
+    base: &str,
+    workspace_id: Uuid,
+    note_id: Option<Uuid>,
+) -> Result<String, failure::Error> {
+    let taken: HashSet<String> =
+        DatabaseNote::load_seo_names_like(conn, base, workspace_id, note_id)?
+            .into_iter()
+            .collect();
+    if !taken.contains(base) {
+        return Ok(base.to_owned());
+    }
+    let mut counter = 1;
+    loop {
+        let seo_name = format!("{}_{}", base, counter);
+        if !taken.contains(&seo_name) {
+            return Ok(seo_name);
+        }
+        counter += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::{self, Fixture};
+    use diesel::connection::{Connection, TransactionManager};
+    use diesel::dsl::sql;
+    use diesel::sql_types::{Bool, Integer};
+    use diesel::RunQueryDsl;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn placeholders_are_filled_in_once() {
+        let now = NaiveDate::from_ymd(2026, 10, 19).and_hms(9, 5, 0);
+        assert_eq!(
+            fill_placeholders(
+                "# {{title}}\n{{weekday}} {{date}} {{time}} {{other}} {{{{date}}",
+                "Notes {{date}}",
+                now
+            ),
+            "# Notes {{date}}\nMonday 2026-10-19 09:05 {{other}} {{2026-10-19"
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn concurrent_notes_with_the_same_title_get_distinct_seo_names() {
+        let conn = test_db::connect();
+        let fixture = Fixture::create(&conn);
+        let accessor = fixture.accessor();
+        let revision = fixture.user.revision(None);
+
+        // The first note is created in a transaction that stays open until the second one is waiting for it
+        let first = test_db::connect();
+        first
+            .transaction_manager()
+            .begin_transaction(&first)
+            .unwrap();
+        let first_note = Note::create(&first, "Foo", "", &accessor, &revision).unwrap();
+
+        let second = test_db::connect();
+        let second_pid: i32 = diesel::select(sql::<Integer>("pg_backend_pid()"))
+            .get_result(&second)
+            .unwrap();
+        let (user_id, second_accessor) = (fixture.user.id, fixture.accessor());
+        let second_note = std::thread::spawn(move || {
+            let revision = Revision {
+                user_id,
+                token_id: None,
+                summary: None,
+            };
+            Note::create(&second, "Foo", "", &second_accessor, &revision)
+        });
+
+        // The second transaction did not see the uncommitted name, so it blocks on inserting the same one
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let waiting_for_lock = format!(
+            "EXISTS (SELECT 1 FROM pg_stat_activity WHERE pid = {} AND wait_event_type = 'Lock')",
+            second_pid
+        );
+        while !diesel::select(sql::<Bool>(&waiting_for_lock))
+            .get_result::<bool>(&conn)
+            .unwrap()
+        {
+            assert!(
+                Instant::now() < deadline,
+                "The second note never waited for the first"
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        first
+            .transaction_manager()
+            .commit_transaction(&first)
+            .unwrap();
+
+        // Its insert then does nothing, and the retry picks the next free name
+        let second_note = second_note.join().unwrap();
+        fixture.remove(&conn);
+        let second_note = second_note.unwrap();
+        assert_eq!(first_note.seo_name, "foo");
+        assert_eq!(second_note.seo_name, "foo_1");
+    }
+
+    #[test]
+    #[ignore]
+    fn links_to_notes_that_are_no_longer_embedded_are_removed() {
+        let conn = test_db::connect();
+        conn.begin_test_transaction().unwrap();
+        let fixture = Fixture::create(&conn);
+        let accessor = fixture.accessor();
+        let revision = fixture.user.revision(None);
+        let embedded = Note::create(&conn, "Embedded", "", &accessor, &revision).unwrap();
+        let linked = Note::create(&conn, "Linked", "", &accessor, &revision).unwrap();
+        let mut note = Note::create(&conn, "Note", "![[embedded]]", &accessor, &revision).unwrap();
+        note.create_link_to(&conn, &linked, &accessor).unwrap();
+        let linked_ids = |note: &Note| DatabaseNoteLink::load_linked_ids(&conn, note.id).unwrap();
+        assert_eq!(
+            linked_ids(&note),
+            [embedded.id, linked.id].iter().copied().collect()
+        );
+
+        // The link that was made for the embed goes, the one that was made by hand stays
+        note.update(&conn, &accessor, &revision, "Note", "", None)
+            .unwrap();
+        assert_eq!(linked_ids(&note), [linked.id].iter().copied().collect());
+    }
 }
@@ -0,0 +1,201 @@
+use argon2::{Config, Variant};
+use crypto::pbkdf2::{pbkdf2_check, pbkdf2_simple};
+use failure::{bail, format_err};
+use rand::RngCore;
+use std::convert::TryFrom;
+
+/// The prefix of the hashes that were made with `pbkdf2_simple`, before passwords were hashed with Argon2id
+const LEGACY_PREFIX: &str = "$rpbkdf2$";
+/// The PBKDF2 iterations of those hashes
+const LEGACY_ITERATIONS: u32 = 10_000;
+/// The length of the random salt of every hash, in bytes
+const SALT_LENGTH: usize = 16;
+
+/// Hashes and verifies passwords with Argon2id.
+///
+/// The parameters are read from `Rocket.toml`, see `from_config`. Hashes that were made with other parameters,
+/// or with the PBKDF2 scheme that was used before, are still accepted but report that they need to be rehashed.
+///
+/// Every verification computes both an Argon2 and a PBKDF2 hash, one of them against a dummy hash, so the time a login
+/// takes does not tell whether the user exists, or whether they still have a PBKDF2 hash.
+pub struct PasswordHasher {
+    /// Memory cost, in KiB
+    memory: u32,
+    iterations: u32,
+    parallelism: u32,
+    /// A hash of a random password, so unknown users can be verified against something
+    dummy_hash: String,
+    /// The same, but with the PBKDF2 scheme that was used before
+    dummy_legacy_hash: String,
+}
+
+/// The outcome of checking a password against a hash
+pub struct Verification {
+    pub valid: bool,
+    /// The hash was made with an old scheme or other parameters, and should be replaced by a new one
+    pub needs_rehash: bool,
+}
+
+impl PasswordHasher {
+    pub const DEFAULT_MEMORY: u32 = 19 * 1024;
+    pub const DEFAULT_ITERATIONS: u32 = 2;
+    pub const DEFAULT_PARALLELISM: u32 = 1;
+
+    pub fn new(
+        memory: u32,
+        iterations: u32,
+        parallelism: u32,
+    ) -> Result<PasswordHasher, failure::Error> {
+        let mut hasher = PasswordHasher {
+            memory,
+            iterations,
+            parallelism,
+            dummy_hash: String::new(),
+            dummy_legacy_hash: String::new(),
+        };
+        let mut dummy_password = [0u8; SALT_LENGTH];
+        rand::thread_rng().fill_bytes(&mut dummy_password);
+        hasher.dummy_hash = hasher.hash_bytes(&dummy_password)?;
+        hasher.dummy_legacy_hash = pbkdf2_simple(&hasher.dummy_hash, LEGACY_ITERATIONS)
+            .map_err(|e| format_err!("Could not hash password: {}", e))?;
+        Ok(hasher)
+    }
+
+    /// Load the parameters from the `argon2_memory_kib`, `argon2_iterations` and `argon2_parallelism` settings,
+    /// falling back to the defaults for the settings that are missing
+    pub fn from_config(config: &rocket::Config) -> Result<PasswordHasher, failure::Error> {
+        let setting = |name: &str, default: u32| -> Result<u32, failure::Error> {
+            match config.get_int(name) {
+                Ok(value) => match u32::try_from(value) {
+                    Ok(value) => Ok(value),
+                    Err(_) => bail!("Invalid setting {}: {} is out of range", name, value),
+                },
+                Err(rocket::config::ConfigError::Missing(_)) => Ok(default),
+                Err(e) => bail!("Invalid setting {}: {}", name, e),
+            }
+        };
+        PasswordHasher::new(
+            setting("argon2_memory_kib", PasswordHasher::DEFAULT_MEMORY)?,
+            setting("argon2_iterations", PasswordHasher::DEFAULT_ITERATIONS)?,
+            setting("argon2_parallelism", PasswordHasher::DEFAULT_PARALLELISM)?,
+        )
+    }
+
+    fn config(&self) -> Config {
+        Config {
+            variant: Variant::Argon2id,
+            mem_cost: self.memory,
+            time_cost: self.iterations,
+            lanes: self.parallelism,
+            ..Config::default()
+        }
+    }
+
+    fn hash_bytes(&self, password: &[u8]) -> Result<String, failure::Error> {
+        let mut salt = [0u8; SALT_LENGTH];
+        rand::thread_rng().fill_bytes(&mut salt);
+        argon2::hash_encoded(password, &salt, &self.config())
+            .map_err(|e| format_err!("Could not hash password: {}", e))
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, failure::Error> {
+        self.hash_bytes(password.as_bytes())
+    }
+
+    pub fn verify(&self, password: &str, hash: &str) -> Result<Verification, failure::Error> {
+        if hash.starts_with(LEGACY_PREFIX) {
+            let valid = pbkdf2_check(password, hash)
+                .map_err(|e| format_err!("Could not validate password: {}", e))?;
+            let _ = argon2::verify_encoded(&self.dummy_hash, password.as_bytes());
+            return Ok(Verification {
+                valid,
+                needs_rehash: true,
+            });
+        }
+        let valid = argon2::verify_encoded(hash, password.as_bytes())
+            .map_err(|e| format_err!("Could not validate password: {}", e))?;
+        let _ = pbkdf2_check(password, &self.dummy_legacy_hash);
+        Ok(Verification {
+            valid,
+            needs_rehash: !self.is_current(hash),
+        })
+    }
+
+    /// Verify the password against hashes that nothing matches, taking as long as a real verification.
+    ///
+    /// This is used when a user does not exist, so the response time does not tell whether it does.
+    pub fn dummy_verify(&self, password: &str) {
+        let _ = argon2::verify_encoded(&self.dummy_hash, password.as_bytes());
+        let _ = pbkdf2_check(password, &self.dummy_legacy_hash);
+    }
+
+    /// Whether the hash was made with Argon2id and the current parameters
+    fn is_current(&self, hash: &str) -> bool {
+        let params = format!(
+            "m={},t={},p={}",
+            self.memory, self.iterations, self.parallelism
+        );
+        hash.starts_with("$argon2id$") && hash.split('$').any(|part| part == params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small parameters, so the tests run fast
+    fn hasher() -> PasswordHasher {
+        PasswordHasher::new(64, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn verify_accepts_only_the_right_password() {
+        let hasher = hasher();
+        let hash = hasher.hash("hunter2").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+
+        let verification = hasher.verify("hunter2", &hash).unwrap();
+        assert!(verification.valid);
+        assert!(!verification.needs_rehash);
+        assert!(!hasher.verify("hunter3", &hash).unwrap().valid);
+    }
+
+    #[test]
+    fn hashes_with_other_parameters_need_a_rehash() {
+        let old = PasswordHasher::new(32, 1, 1).unwrap();
+        let hasher = hasher();
+        let hash = old.hash("hunter2").unwrap();
+        assert!(old.is_current(&hash));
+        assert!(!hasher.is_current(&hash));
+
+        let verification = hasher.verify("hunter2", &hash).unwrap();
+        assert!(verification.valid);
+        assert!(verification.needs_rehash);
+    }
+
+    #[test]
+    fn legacy_hashes_are_accepted_and_need_a_rehash() {
+        let hasher = hasher();
+        let hash = pbkdf2_simple("hunter2", LEGACY_ITERATIONS).unwrap();
+        assert!(hash.starts_with(LEGACY_PREFIX));
+        assert!(!hasher.is_current(&hash));
+
+        let verification = hasher.verify("hunter2", &hash).unwrap();
+        assert!(verification.valid);
+        assert!(verification.needs_rehash);
+        assert!(!hasher.verify("hunter3", &hash).unwrap().valid);
+    }
+
+    #[test]
+    fn settings_out_of_range_are_rejected() {
+        use rocket::config::Environment;
+
+        for value in &[-1, i64::from(u32::max_value()) + 1] {
+            let config = rocket::Config::build(Environment::Development)
+                .extra("argon2_iterations", *value)
+                .finalize()
+                .unwrap();
+            assert!(PasswordHasher::from_config(&config).is_err());
+        }
+    }
+}
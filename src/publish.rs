@@ -0,0 +1,318 @@
+use crate::attachment::Attachments;
+use crate::audit::AuditEvent;
+use crate::markdown::Embed;
+use crate::models::note_link::NoteLink as DatabaseNoteLink;
+use crate::models::user::DatabaseUser;
+use crate::note::Note;
+use crate::workspace::Workspace;
+use failure::{bail, format_err};
+use rocket_contrib::templates::tera::Tera;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+/// The directory that the templates are loaded from, the same one that rocket uses
+const TEMPLATE_DIR: &str = "templates";
+/// The stylesheet that is copied next to the generated pages
+const STYLESHEET: &str = "static/style.css";
+/// The size of the (square) graph image, in pixels
+const GRAPH_SIZE: f64 = 800.0;
+/// The directory that the attachments of the published notes are copied to, with a directory per note
+const ATTACHMENT_DIR: &str = "attachments";
+
+#[derive(Serialize)]
+struct PublishedNote<'a> {
+    note: Note,
+    links: Vec<Note>,
+    embeds: &'a HashMap<String, Embed>,
+    /// The pages of the published notes, by seo name, that links to them are rewritten to
+    note_urls: &'a HashMap<String, String>,
+    /// Where the attachments of the note are copied to, relative to its page
+    attachments: String,
+}
+
+#[derive(Serialize)]
+struct IndexModel<'a> {
+    root: &'a Note,
+    notes: Vec<&'a Note>,
+}
+
+#[derive(Serialize)]
+struct GraphModel {
+    size: f64,
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+#[derive(Serialize)]
+struct GraphNode {
+    seo_name: String,
+    title: String,
+    x: f64,
+    y: f64,
+}
+
+#[derive(Serialize)]
+struct GraphEdge {
+    x1: f64,
+    y1: f64,
+    x2: f64,
+    y2: f64,
+}
+
+#[derive(Serialize)]
+struct SearchEntry<'a> {
+    title: &'a str,
+    url: String,
+    body: &'a str,
+}
+
+/// Publish the note `seo_name` in the workspace `workspace` of user `user_name`, and all notes up to `depth` links away from it, to `output`.
+/// The workspace is given by its name or id, and the user has to be a member of it.
+///
+/// Links between the published notes are rewritten to relative paths, so the directory can be hosted anywhere.
+/// Besides a page for every note this writes an index, a graph page and a `search.json` index,
+/// and copies the attachments of the notes.
+///
+/// Returns the amount of notes that were published.
+pub fn publish(
+    conn: &diesel::PgConnection,
+    user_name: &str,
+    workspace: &str,
+    seo_name: &str,
+    depth: u32,
+    output: &Path,
+    attachments: &Attachments,
+) -> Result<usize, failure::Error> {
+    let user = DatabaseUser::load_by_name(conn, user_name)?
+        .ok_or_else(|| format_err!("User {:?} not found", user_name))?;
+    let workspace = find_workspace(conn, user.id, workspace)?.ok_or_else(|| {
+        format_err!(
+            "User {:?} is not a member of workspace {:?}",
+            user_name,
+            workspace
+        )
+    })?;
+    let accessor = workspace.accessor();
+    let root = Note::load_by_seo_name(conn, seo_name, &accessor)?
+        .ok_or_else(|| format_err!("Note {:?} not found", seo_name))?;
+    let root_id = root.id;
+    // Private notes that the user cannot read are left out, so they never end up in the pages or the graph
+    let notes = root.load_neighbourhood(conn, &accessor, depth)?;
+
+    let mut neighbours: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for edge in DatabaseNoteLink::load_edges_by_workspace(conn, workspace.id)? {
+        if notes.contains_key(&edge.left) && notes.contains_key(&edge.right) {
+            neighbours.entry(edge.left).or_default().push(edge.right);
+            neighbours.entry(edge.right).or_default().push(edge.left);
+        }
+    }
+
+    let mut sorted: Vec<&Note> = notes.values().collect();
+    sorted.sort_by(|a, b| a.title.cmp(&b.title));
+
+    // Only published notes are embedded, the pages must not show anything else
+    let embeds: HashMap<String, Embed> = notes
+        .values()
+        .map(|note| {
+            let embed = Embed {
+                seo_name: note.seo_name.clone(),
+                title: note.title.clone(),
+                url: page_name(note),
+                body: note.body.clone(),
+                attachments: Some(attachment_dir(note)),
+            };
+            (note.seo_name.clone(), embed)
+        })
+        .collect();
+
+    let note_urls: HashMap<String, String> = notes
+        .values()
+        .map(|note| (note.seo_name.clone(), page_name(note)))
+        .collect();
+
+    let tera = load_templates()?;
+    fs::create_dir_all(output)?;
+    fs::copy(STYLESHEET, output.join("style.css"))?;
+
+    for note in &sorted {
+        let mut seen = HashSet::new();
+        let mut links: Vec<Note> = neighbours
+            .get(&note.id)
+            .map(|ids| {
+                ids.iter()
+                    .filter(|id| seen.insert(**id))
+                    .map(|id| notes[id].clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        links.sort_by(|a, b| a.title.cmp(&b.title));
+        let model = PublishedNote {
+            note: (*note).clone(),
+            links,
+            embeds: &embeds,
+            note_urls: &note_urls,
+            attachments: attachment_dir(note),
+        };
+        let html = render(&tera, "publish_note", &model)?;
+        fs::write(output.join(page_name(note)), html)?;
+
+        let files = Attachments::load_by_note(conn, note)?;
+        if !files.is_empty() {
+            let dir = output.join(attachment_dir(note));
+            fs::create_dir_all(&dir)?;
+            for file in files {
+                if let Some(file) = attachments.load_file(conn, note, &file.file_name, false)? {
+                    fs::write(dir.join(&file.file_name), file.data)?;
+                }
+            }
+        }
+    }
+
+    let index = IndexModel {
+        root: &notes[&root_id],
+        notes: sorted.clone(),
+    };
+    fs::write(
+        output.join("index.html"),
+        render(&tera, "publish_index", &index)?,
+    )?;
+
+    let graph = graph_model(&sorted, &neighbours);
+    fs::write(
+        output.join("graph.html"),
+        render(&tera, "publish_graph", &graph)?,
+    )?;
+
+    let search: Vec<SearchEntry> = sorted
+        .iter()
+        .map(|n| SearchEntry {
+            title: &n.title,
+            url: page_name(n),
+            body: &n.body,
+        })
+        .collect();
+    fs::write(output.join("search.json"), serde_json::to_string(&search)?)?;
+
+    let details = format!(
+        "{} notes around {:?} to {}",
+        sorted.len(),
+        seo_name,
+        output.display()
+    );
+    AuditEvent::Exported.record(conn, Some(user.id), None, Some(&details))?;
+
+    Ok(sorted.len())
+}
+
+/// The workspace with the given id or name, if the user is a member of it
+fn find_workspace(
+    conn: &diesel::PgConnection,
+    user_id: Uuid,
+    workspace: &str,
+) -> Result<Option<Workspace>, failure::Error> {
+    if let Ok(id) = Uuid::parse_str(workspace) {
+        return Workspace::load_by_member(conn, id, user_id);
+    }
+    let mut named: Vec<Workspace> = Workspace::load_all_by_member(conn, user_id)?
+        .into_iter()
+        .filter(|w| w.name == workspace)
+        .collect();
+    if named.len() > 1 {
+        bail!(
+            "There are several workspaces named {:?}, use the id of one instead",
+            workspace
+        );
+    }
+    Ok(named.pop())
+}
+
+fn page_name(note: &Note) -> String {
+    format!("{}.html", note.seo_name)
+}
+
+fn attachment_dir(note: &Note) -> String {
+    format!("{}/{}/", ATTACHMENT_DIR, note.seo_name)
+}
+
+/// Load all templates the same way rocket does, so the published pages can extend the regular layouts
+fn load_templates() -> Result<Tera, failure::Error> {
+    let mut templates = Vec::new();
+    for entry in fs::read_dir(TEMPLATE_DIR)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("tera") {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+            templates.push((name.to_owned(), fs::read_to_string(&path)?));
+        }
+    }
+    let mut tera = Tera::default();
+    tera.add_raw_templates(
+        templates
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_str()))
+            .collect(),
+    )
+    .map_err(|e| format_err!("Could not load templates: {}", e))?;
+    crate::tera_utils::register(&mut tera);
+    Ok(tera)
+}
+
+fn render<T: serde::Serialize>(
+    tera: &Tera,
+    template: &str,
+    model: &T,
+) -> Result<String, failure::Error> {
+    tera.render(template, model).map_err(|e| {
+        let messages: Vec<String> = e.iter().map(ToString::to_string).collect();
+        format_err!("Could not render {}: {}", template, messages.join(": "))
+    })
+}
+
+/// Place the notes on a circle, with lines for the links between them
+fn graph_model(notes: &[&Note], neighbours: &HashMap<Uuid, Vec<Uuid>>) -> GraphModel {
+    let center = GRAPH_SIZE / 2.0;
+    let radius = GRAPH_SIZE * 0.4;
+    let positions: HashMap<Uuid, (f64, f64)> = notes
+        .iter()
+        .enumerate()
+        .map(|(i, note)| {
+            let angle = 2.0 * std::f64::consts::PI * i as f64 / notes.len() as f64;
+            (
+                note.id,
+                (center + radius * angle.cos(), center + radius * angle.sin()),
+            )
+        })
+        .collect();
+
+    let mut edges = Vec::new();
+    for (left, rights) in neighbours {
+        for right in rights {
+            // Every link is in the map in both directions, only draw it once
+            if left < right {
+                let (x1, y1) = positions[left];
+                let (x2, y2) = positions[right];
+                edges.push(GraphEdge { x1, y1, x2, y2 });
+            }
+        }
+    }
+
+    GraphModel {
+        size: GRAPH_SIZE,
+        nodes: notes
+            .iter()
+            .map(|note| {
+                let (x, y) = positions[&note.id];
+                GraphNode {
+                    seo_name: note.seo_name.clone(),
+                    title: note.title.clone(),
+                    x,
+                    y,
+                }
+            })
+            .collect(),
+        edges,
+    }
+}
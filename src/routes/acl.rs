@@ -0,0 +1,126 @@
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::acl::{AclEntry, Grantee};
+use crate::csrf::{CsrfForm, CsrfToken, NoFields};
+use crate::either::Either;
+use crate::models::note_acl::Accessor;
+use crate::note::{Note, NoteLookup};
+use crate::workspace::{Role, WorkspaceEditor};
+use crate::MindmapDB;
+
+#[get("/access/<seo_name..>")]
+pub fn list(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: WorkspaceEditor,
+    seo_name: PathBuf,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(note) => {
+            if !note.can_write(&conn, &accessor)? {
+                return Ok(Either::Right(Redirect::to(format!("/n/{}", note.seo_name))));
+            }
+            let model = AccessListModel::load(&conn, note, None)?;
+            Ok(Either::Left(csrf.render("note_access", &model)))
+        }
+        NoteLookup::Renamed(note) => Ok(Either::Right(Redirect::permanent(format!(
+            "/access/{}",
+            note.seo_name
+        )))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
+    }
+}
+
+#[post("/access/<seo_name..>", data = "<data>")]
+pub fn save(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: WorkspaceEditor,
+    seo_name: PathBuf,
+    data: CsrfForm<SaveAccessModel>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    let grantee = match (data.role, &data.user_name) {
+        (Some(role), _) => Grantee::Role(role),
+        (None, Some(name)) if !name.trim().is_empty() => Grantee::User(name.trim()),
+        (None, _) => return Ok(Either::Right(Redirect::to(format!("/access/{}", seo_name)))),
+    };
+    update(&csrf, &conn, &accessor, seo_name, |note| {
+        AclEntry::save(&conn, note, &accessor, grantee, data.can_write)
+    })
+}
+
+#[post("/remove_access/<id>/<seo_name..>", data = "<_form>")]
+pub fn remove(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: WorkspaceEditor,
+    id: String,
+    seo_name: PathBuf,
+    _form: CsrfForm<NoFields>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let id = Uuid::parse_str(&id)?;
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    update(&csrf, &conn, &accessor, seo_name, |note| {
+        AclEntry::remove(&conn, note, &accessor, id)
+    })
+}
+
+/// Change the access control list of a note, showing the error on the list page if that fails
+fn update(
+    csrf: &CsrfToken,
+    conn: &MindmapDB,
+    accessor: &Accessor,
+    seo_name: &str,
+    change: impl FnOnce(&Note) -> Result<(), failure::Error>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    match Note::lookup(conn, seo_name, accessor)? {
+        NoteLookup::Found(note) | NoteLookup::Renamed(note) => match change(&note) {
+            Ok(()) => Ok(Either::Right(Redirect::to(format!(
+                "/access/{}",
+                note.seo_name
+            )))),
+            Err(e) => {
+                let model = AccessListModel::load(conn, note, Some(e.to_string()))?;
+                Ok(Either::Left(csrf.render("note_access", &model)))
+            }
+        },
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
+    }
+}
+
+#[derive(Serialize)]
+pub struct AccessListModel {
+    pub note: Note,
+    pub entries: Vec<AclEntry>,
+    pub error: Option<String>,
+}
+
+impl AccessListModel {
+    fn load(
+        conn: &diesel::PgConnection,
+        note: Note,
+        error: Option<String>,
+    ) -> Result<AccessListModel, failure::Error> {
+        Ok(AccessListModel {
+            entries: AclEntry::load_by_note(conn, &note)?,
+            note,
+            error,
+        })
+    }
+}
+
+#[derive(FromForm)]
+pub struct SaveAccessModel {
+    pub user_name: Option<String>,
+    /// Left empty if the entry is for a single user
+    pub role: Option<Role>,
+    pub can_write: bool,
+}
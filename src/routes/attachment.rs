@@ -0,0 +1,160 @@
+use rocket::response::Redirect;
+use rocket::State;
+use rocket_contrib::templates::Template;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::attachment::{Attachment, AttachmentFile, Attachments, Upload};
+use crate::csrf::{CsrfForm, CsrfToken, NoFields};
+use crate::either::Either;
+use crate::models::note_acl::Accessor;
+use crate::note::{Note, NoteLookup};
+use crate::share::SharedNote;
+use crate::workspace::{Workspace, WorkspaceEditor};
+use crate::MindmapDB;
+
+#[get("/attachments/<seo_name..>")]
+pub fn list(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    attachments: State<Attachments>,
+    workspace: WorkspaceEditor,
+    seo_name: PathBuf,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(note) => {
+            if !note.can_write(&conn, &accessor)? {
+                return Ok(Either::Right(Redirect::to(format!("/n/{}", note.seo_name))));
+            }
+            let model = AttachmentListModel::load(&conn, &attachments, note, None)?;
+            Ok(Either::Left(csrf.render("attachments", &model)))
+        }
+        NoteLookup::Renamed(note) => Ok(Either::Right(Redirect::permanent(format!(
+            "/attachments/{}",
+            note.seo_name
+        )))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
+    }
+}
+
+#[post("/attachments/<seo_name..>", data = "<upload>")]
+pub fn upload(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    attachments: State<Attachments>,
+    workspace: WorkspaceEditor,
+    seo_name: PathBuf,
+    upload: Upload,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    update(&csrf, &conn, &attachments, &accessor, seo_name, |note| {
+        attachments
+            .upload(&conn, note, &accessor, &upload.file_name, &upload.data)
+            .map(|_| ())
+    })
+}
+
+#[post("/delete_attachment/<file_name>/<seo_name..>", data = "<_form>")]
+pub fn delete(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    attachments: State<Attachments>,
+    workspace: WorkspaceEditor,
+    file_name: String,
+    seo_name: PathBuf,
+    _form: CsrfForm<NoFields>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    update(&csrf, &conn, &attachments, &accessor, seo_name, |note| {
+        attachments.delete(&conn, note, &accessor, &file_name)
+    })
+}
+
+/// The file of an attachment of a note in the current workspace, or its thumbnail
+#[get("/attachment/<note_id>/<file_name>?<thumbnail>")]
+pub fn view(
+    conn: MindmapDB,
+    attachments: State<Attachments>,
+    workspace: Workspace,
+    note_id: String,
+    file_name: String,
+    thumbnail: Option<bool>,
+) -> Result<Option<AttachmentFile>, failure::Error> {
+    let note_id = match Uuid::parse_str(&note_id) {
+        Ok(id) => id,
+        Err(_) => return Ok(None),
+    };
+    match Note::load_by_id(&conn, note_id, &workspace.accessor())? {
+        Some(note) => attachments.load_file(&conn, &note, &file_name, thumbnail.unwrap_or(false)),
+        None => Ok(None),
+    }
+}
+
+/// The file of an attachment of a note that is shared with the given token
+#[get("/s/<token>/attachment/<seo_name>/<file_name>")]
+pub fn view_shared(
+    conn: MindmapDB,
+    attachments: State<Attachments>,
+    token: String,
+    seo_name: String,
+    file_name: String,
+) -> Result<Option<AttachmentFile>, failure::Error> {
+    match SharedNote::load(&conn, &token, Some(&seo_name))? {
+        Some(shared) => attachments.load_file(&conn, &shared.note, &file_name, false),
+        None => Ok(None),
+    }
+}
+
+/// Change the attachments of a note, showing the error on the list page if that fails
+fn update(
+    csrf: &CsrfToken,
+    conn: &MindmapDB,
+    attachments: &Attachments,
+    accessor: &Accessor,
+    seo_name: &str,
+    change: impl FnOnce(&Note) -> Result<(), failure::Error>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    match Note::lookup(conn, seo_name, accessor)? {
+        NoteLookup::Found(note) | NoteLookup::Renamed(note) => match change(&note) {
+            Ok(()) => Ok(Either::Right(Redirect::to(format!(
+                "/attachments/{}",
+                note.seo_name
+            )))),
+            Err(e) => {
+                let model =
+                    AttachmentListModel::load(conn, attachments, note, Some(e.to_string()))?;
+                Ok(Either::Left(csrf.render("attachments", &model)))
+            }
+        },
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
+    }
+}
+
+#[derive(Serialize)]
+pub struct AttachmentListModel {
+    pub note: Note,
+    pub attachments: Vec<Attachment>,
+    /// The largest file that can be uploaded, in KiB
+    pub max_size_kib: u64,
+    pub error: Option<String>,
+}
+
+impl AttachmentListModel {
+    fn load(
+        conn: &diesel::PgConnection,
+        attachments: &Attachments,
+        note: Note,
+        error: Option<String>,
+    ) -> Result<AttachmentListModel, failure::Error> {
+        Ok(AttachmentListModel {
+            attachments: Attachments::load_by_note(conn, &note)?,
+            max_size_kib: attachments.max_size() / 1024,
+            note,
+            error,
+        })
+    }
+}
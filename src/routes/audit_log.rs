@@ -0,0 +1,77 @@
+use rocket::http::RawStr;
+use rocket::request::{FromFormValue, LenientForm};
+use rocket_contrib::templates::Template;
+
+use crate::audit::{AuditEntry, AuditEvent, PAGE_SIZE};
+use crate::csrf::CsrfToken;
+use crate::user::User;
+use crate::MindmapDB;
+
+#[get("/audit_log?<query..>")]
+pub fn view(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    user: User,
+    query: LenientForm<AuditLogQuery>,
+) -> Result<Template, failure::Error> {
+    let page = query.page.unwrap_or(1).max(1);
+    let filter_name = query
+        .user
+        .as_ref()
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty());
+
+    // Users only see their own events, administrators can see the events of everyone
+    let mut error = None;
+    let entries = match filter_name {
+        _ if !user.is_admin => AuditEntry::load(&conn, Some(user.id), query.event, page)?,
+        None => AuditEntry::load(&conn, None, query.event, page)?,
+        Some(name) => match User::load_by_name(&conn, name)? {
+            Some(u) => AuditEntry::load(&conn, Some(u.id), query.event, page)?,
+            None => {
+                error = Some(format!("User {:?} not found", name));
+                Vec::new()
+            }
+        },
+    };
+
+    let model = AuditLogModel {
+        has_next_page: entries.len() as u64 == PAGE_SIZE,
+        entries,
+        is_admin: user.is_admin,
+        user: filter_name.filter(|_| user.is_admin).map(str::to_owned),
+        event: query.event,
+        events: AuditEvent::ALL.to_vec(),
+        page,
+        error,
+    };
+    Ok(csrf.render("audit_log", &model))
+}
+
+#[derive(Serialize)]
+pub struct AuditLogModel {
+    pub entries: Vec<AuditEntry>,
+    pub is_admin: bool,
+    /// The name of the user the log is filtered on, only used by administrators
+    pub user: Option<String>,
+    pub event: Option<AuditEvent>,
+    pub events: Vec<AuditEvent>,
+    pub page: u64,
+    pub has_next_page: bool,
+    pub error: Option<String>,
+}
+
+#[derive(FromForm)]
+pub struct AuditLogQuery {
+    pub user: Option<String>,
+    pub event: Option<AuditEvent>,
+    pub page: Option<u64>,
+}
+
+impl<'v> FromFormValue<'v> for AuditEvent {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<AuditEvent, &'v RawStr> {
+        form_value.as_str().parse().map_err(|_| form_value)
+    }
+}
@@ -1,98 +1,215 @@
-use rocket::http::{Cookie, Cookies};
-use rocket::request::Form;
+use chrono::{Duration, TimeZone, Utc};
+use rocket::http::{Cookie, Cookies, SameSite};
 use rocket::response::Redirect;
+use rocket::State;
 use rocket_contrib::templates::Template;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use uuid::Uuid;
 
+use crate::csrf::{CsrfForm, CsrfToken, NoFields};
 use crate::either::Either;
-use crate::user::User;
-use crate::{HtmlSafeString, MindmapDB};
+use crate::models::user_token::UserToken;
+use crate::password::PasswordHasher;
+use crate::throttle::Throttle;
+use crate::totp::Clock;
+use crate::user::{Login, User};
+use crate::MindmapDB;
+
+/// The private cookie that holds the user that entered a correct password, but still has to enter a two-factor code,
+/// together with the time that the login expires
+const PENDING_LOGIN_COOKIE: &str = "PENDING_LOGIN";
+/// The amount of minutes a user has to enter their two-factor code after entering their password
+const PENDING_LOGIN_MINUTES: i64 = 5;
 
 #[get("/", rank = 2)]
-pub fn index_not_logged_in() -> Template {
+pub fn index_not_logged_in(csrf: CsrfToken) -> Template {
     let map = HashMap::<(), ()>::new();
-    Template::render("index_not_logged_in", &map)
+    csrf.render("index_not_logged_in", &map)
 }
 
-#[get("/logout")]
-pub fn logout(mut cookies: Cookies) -> Redirect {
+#[post("/logout", data = "<_form>")]
+pub fn logout(
+    ip: SocketAddr,
+    conn: MindmapDB,
+    user: Option<User>,
+    mut cookies: Cookies,
+    _form: CsrfForm<NoFields>,
+) -> Result<Redirect, failure::Error> {
+    if let Some(user) = user {
+        user.logout(&conn, &ip.ip().to_string())?;
+    }
     let names: Vec<String> = cookies.iter().map(|c| c.name().to_owned()).collect();
     for name in names {
         cookies.remove(Cookie::named(name));
     }
-    Redirect::to("/")
+    Ok(Redirect::to("/"))
 }
 
 #[post("/login", data = "<login>")]
+#[allow(clippy::too_many_arguments)]
 pub fn login_submit(
+    csrf: CsrfToken,
     ip: SocketAddr,
     conn: MindmapDB,
+    hasher: State<PasswordHasher>,
+    throttle: State<Throttle>,
+    clock: State<Clock>,
     mut cookies: Cookies,
-    login: Form<LoginSubmitModel>,
+    login: CsrfForm<LoginSubmitModel>,
 ) -> Either<Template, Redirect> {
     let login = login.into_inner();
     match User::attempt_login(
         &conn,
+        &hasher,
+        &throttle,
+        &clock,
         &login.username,
         &login.password,
         &ip.ip().to_string(),
     ) {
-        Ok((user, token)) => {
-            cookies.add_private(Cookie::new("UID", user.id.to_string()));
-            cookies.add_private(Cookie::new("TID", token.id.to_string()));
+        Ok(Login::LoggedIn(user, token)) => {
+            add_session_cookies(&mut cookies, &user, &token);
             Either::Right(Redirect::to("/"))
         }
+        Ok(Login::SecondFactorRequired(user_id)) => {
+            let expires = clock.now() + Duration::minutes(PENDING_LOGIN_MINUTES);
+            cookies.add_private(Cookie::new(
+                PENDING_LOGIN_COOKIE,
+                format!("{} {}", user_id, expires.timestamp()),
+            ));
+            Either::Right(Redirect::to("/login/two_factor"))
+        }
         Err(e) => {
             let render_model = LoginRenderModel {
-                username: login.username.get(),
+                username: login.username,
+                error: e.to_string(),
+            };
+            Either::Left(csrf.render("login", &render_model))
+        }
+    }
+}
+
+#[get("/login/two_factor")]
+pub fn two_factor(
+    csrf: CsrfToken,
+    clock: State<Clock>,
+    mut cookies: Cookies,
+) -> Either<Template, Redirect> {
+    match pending_login(&clock, &mut cookies) {
+        Some(_) => Either::Left(csrf.render("login_two_factor", &TwoFactorRenderModel::default())),
+        None => Either::Right(Redirect::to("/")),
+    }
+}
+
+#[post("/login/two_factor", data = "<data>")]
+pub fn two_factor_submit(
+    csrf: CsrfToken,
+    ip: SocketAddr,
+    conn: MindmapDB,
+    throttle: State<Throttle>,
+    clock: State<Clock>,
+    mut cookies: Cookies,
+    data: CsrfForm<TwoFactorSubmitModel>,
+) -> Either<Template, Redirect> {
+    let user_id = match pending_login(&clock, &mut cookies) {
+        Some(user_id) => user_id,
+        None => return Either::Right(Redirect::to("/")),
+    };
+    match User::attempt_second_factor(
+        &conn,
+        &throttle,
+        &clock,
+        user_id,
+        &data.code,
+        &ip.ip().to_string(),
+    ) {
+        Ok((user, token)) => {
+            cookies.remove_private(Cookie::named(PENDING_LOGIN_COOKIE));
+            add_session_cookies(&mut cookies, &user, &token);
+            Either::Right(Redirect::to("/"))
+        }
+        Err(e) => {
+            let render_model = TwoFactorRenderModel {
                 error: e.to_string(),
             };
-            Either::Left(Template::render("login", &render_model))
+            Either::Left(csrf.render("login_two_factor", &render_model))
         }
     }
 }
 
+/// Remember the session in the browser. The cookies are not sent along with requests that other websites make,
+/// but they are when following a link from another website, so shared links to notes open logged in.
+fn add_session_cookies(cookies: &mut Cookies, user: &User, token: &UserToken) {
+    for (name, value) in &[("UID", user.id), ("TID", token.id)] {
+        cookies.add_private(
+            Cookie::build(*name, value.to_string())
+                .same_site(SameSite::Lax)
+                .finish(),
+        );
+    }
+}
+
+/// The user that still has to enter a two-factor code, if that login has not expired yet
+fn pending_login(clock: &Clock, cookies: &mut Cookies) -> Option<Uuid> {
+    let cookie = cookies.get_private(PENDING_LOGIN_COOKIE)?;
+    let mut parts = cookie.value().split(' ');
+    let user_id = parts.next()?.parse().ok()?;
+    let expires = Utc.timestamp(parts.next()?.parse().ok()?, 0);
+    if expires < clock.now() {
+        cookies.remove_private(Cookie::named(PENDING_LOGIN_COOKIE));
+        return None;
+    }
+    Some(user_id)
+}
+
 #[post("/register", data = "<register>")]
+#[allow(clippy::too_many_arguments)]
 pub fn register_submit(
+    csrf: CsrfToken,
     ip: SocketAddr,
     conn: MindmapDB,
+    hasher: State<PasswordHasher>,
+    throttle: State<Throttle>,
+    clock: State<Clock>,
     mut cookies: Cookies,
-    register: Form<RegisterSubmitModel>,
+    register: CsrfForm<RegisterSubmitModel>,
 ) -> Either<Template, Redirect> {
     let register = register.into_inner();
 
     if register.password != register.repeat_password {
         let render_model = RegisterRenderModel {
-            username: register.username.get(),
+            username: register.username,
             error: String::from("Passwords don't match"),
         };
-        return Either::Left(Template::render("register", &render_model));
+        return Either::Left(csrf.render("register", &render_model));
     }
     match User::attempt_register(
         &conn,
+        &hasher,
+        &throttle,
+        &clock,
         &register.username,
         &register.password,
         &ip.ip().to_string(),
     ) {
         Ok((user, token)) => {
-            cookies.add_private(Cookie::new("UID", user.id.to_string()));
-            cookies.add_private(Cookie::new("TID", token.id.to_string()));
+            add_session_cookies(&mut cookies, &user, &token);
             Either::Right(Redirect::to("/"))
         }
         Err(e) => {
             let render_model = RegisterRenderModel {
-                username: register.username.get(),
+                username: register.username,
                 error: e.to_string(),
             };
-            Either::Left(Template::render("register", &render_model))
+            Either::Left(csrf.render("register", &render_model))
         }
     }
 }
 
 #[derive(FromForm)]
 pub struct LoginSubmitModel {
-    pub username: HtmlSafeString,
+    pub username: String,
     pub password: String,
 }
 
@@ -102,9 +219,20 @@ pub struct LoginRenderModel {
     pub error: String,
 }
 
+#[derive(FromForm)]
+pub struct TwoFactorSubmitModel {
+    /// A code from the authenticator app, or a recovery code
+    pub code: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct TwoFactorRenderModel {
+    pub error: String,
+}
+
 #[derive(FromForm)]
 pub struct RegisterSubmitModel {
-    pub username: HtmlSafeString,
+    pub username: String,
     pub password: String,
     pub repeat_password: String,
 }
@@ -0,0 +1,15 @@
+use crate::csrf::CsrfToken;
+use crate::insights::Insights;
+use crate::workspace::Workspace;
+use crate::MindmapDB;
+use rocket_contrib::templates::Template;
+
+#[get("/insights")]
+pub fn view(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: Workspace,
+) -> Result<Template, failure::Error> {
+    let insights = Insights::load(&conn, &workspace.accessor())?;
+    Ok(csrf.render("insights", &insights))
+}
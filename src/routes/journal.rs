@@ -0,0 +1,58 @@
+use chrono::NaiveDate;
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+
+use crate::csrf::{CsrfForm, CsrfToken, NoFields};
+use crate::journal::{self, Calendar};
+use crate::user::User;
+use crate::workspace::{Workspace, WorkspaceEditor};
+use crate::MindmapDB;
+
+/// Go to the daily note of today, or to the journal if it has not been started yet
+#[get("/today")]
+pub fn today(
+    conn: MindmapDB,
+    user: User,
+    workspace: Workspace,
+) -> Result<Redirect, failure::Error> {
+    match journal::find_today(&conn, &user, &workspace)? {
+        Some(note) => Ok(Redirect::to(format!("/n/{}", note.seo_name))),
+        None => Ok(Redirect::to("/journal")),
+    }
+}
+
+#[post("/today", data = "<_form>")]
+pub fn start_today(
+    conn: MindmapDB,
+    user: User,
+    workspace: WorkspaceEditor,
+    _form: CsrfForm<NoFields>,
+) -> Result<Redirect, failure::Error> {
+    let note = journal::today(&conn, &user, &workspace)?;
+    Ok(Redirect::to(format!("/n/{}", note.seo_name)))
+}
+
+/// The days of a month, given as "2026-10", that have a daily note
+#[get("/journal?<month>")]
+pub fn calendar(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    user: User,
+    workspace: Workspace,
+    month: Option<String>,
+) -> Result<Template, failure::Error> {
+    let month =
+        month.and_then(|m| NaiveDate::parse_from_str(&format!("{}-01", m), "%Y-%m-%d").ok());
+    let model = JournalModel {
+        calendar: Calendar::load(&conn, &user, &workspace, month)?,
+        can_edit: workspace.role.can_edit(),
+    };
+    Ok(csrf.render("journal", &model))
+}
+
+#[derive(Serialize)]
+pub struct JournalModel {
+    pub calendar: Calendar,
+    /// Whether the user can start the daily note of today
+    pub can_edit: bool,
+}
@@ -1,25 +1,33 @@
 use rocket::response::Redirect;
 use uuid::Uuid;
 
-use crate::note::{Link, Note};
-use crate::user::User;
+use crate::csrf::{CsrfForm, NoFields};
+use crate::note::{Link, Note, NoteLookup};
+use crate::workspace::{Workspace, WorkspaceEditor};
 use crate::MindmapDB;
 
-#[get("/create_link/<left_seo_name>/<right_seo_name>")]
+#[post("/create_link/<left_seo_name>/<right_seo_name>", data = "<_form>")]
 pub fn create(
     conn: MindmapDB,
-    user: User,
+    workspace: WorkspaceEditor,
     left_seo_name: String,
     right_seo_name: String,
+    _form: CsrfForm<NoFields>,
 ) -> Result<Redirect, failure::Error> {
+    let accessor = workspace.accessor();
     match (
-        Note::load_by_seo_name(&conn, &left_seo_name, user.id)?,
-        Note::load_by_seo_name(&conn, &right_seo_name, user.id)?,
+        Note::lookup(&conn, &left_seo_name, &accessor)?,
+        Note::lookup(&conn, &right_seo_name, &accessor)?,
     ) {
-        (Some(left), Some(right)) => {
-            left.create_link_to(&conn, &right)?;
+        (NoteLookup::Found(left), NoteLookup::Found(right)) => {
+            left.create_link_to(&conn, &right, &accessor)?;
             Ok(Redirect::to(format!("/n/{}", left.seo_name)))
         }
+        (NoteLookup::Found(left), NoteLookup::Renamed(right))
+        | (NoteLookup::Renamed(left), NoteLookup::Found(right))
+        | (NoteLookup::Renamed(left), NoteLookup::Renamed(right)) => Ok(Redirect::permanent(
+            format!("/create_link/{}/{}", left.seo_name, right.seo_name),
+        )),
         (_, _) => Ok(Redirect::to("/")),
     }
 }
@@ -27,12 +35,12 @@ pub fn create(
 #[get("/link/<id>/<seo_name>")]
 pub fn follow(
     conn: MindmapDB,
-    _user: User,
+    workspace: Workspace,
     id: String,
     seo_name: String,
 ) -> Result<Redirect, failure::Error> {
     let id = Uuid::parse_str(&id)?;
     let link = Link { id };
-    link.increase_click_count(&conn)?;
+    link.increase_click_count(&conn, workspace.id)?;
     Ok(Redirect::to(format!("/n/{}", seo_name)))
 }
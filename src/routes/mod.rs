@@ -1,57 +1,196 @@
-use crate::note::Note;
+use crate::csrf::{CsrfForm, CsrfToken};
+use crate::note::{Note, NoteFilter, NoteSort};
 use crate::user::User;
+use crate::workspace::Workspace;
 use crate::MindmapDB;
+use chrono::NaiveDate;
+use rocket::http::RawStr;
+use rocket::request::{FromFormValue, LenientForm};
+use rocket::response::Redirect;
 use rocket::Route;
 use rocket_contrib::templates::Template;
 
+mod acl;
+mod attachment;
+mod audit_log;
 mod auth;
+mod insights;
+mod journal;
 mod link;
 mod note;
 mod note_history;
 mod search;
+mod settings;
+mod share;
+mod statistics;
+mod wander;
+mod workspace;
 
 pub use self::search::SearchQuery;
 
+/// The most notes the index shows on a page
+const MAX_NOTES_PER_PAGE: u64 = 500;
+
 pub fn get() -> Vec<Route> {
     routes![
         index,
+        save_filter,
+        acl::list,
+        acl::save,
+        acl::remove,
+        attachment::list,
+        attachment::upload,
+        attachment::delete,
+        attachment::view,
+        attachment::view_shared,
+        audit_log::view,
         auth::index_not_logged_in,
         auth::login_submit,
         auth::logout,
+        auth::two_factor,
+        auth::two_factor_submit,
         auth::register_submit,
+        insights::view,
+        journal::today,
+        journal::start_today,
+        journal::calendar,
         link::create,
         link::follow,
         note_history::view,
+        note::new_from_template,
         note::new,
         note::edit,
         note::edit_submit,
         note::view,
         note::delete_preview,
         note::delete_submit,
+        note::toggle_task,
+        note::set_template,
         search::search,
         search::search_for_link,
+        settings::view,
+        settings::change_password,
+        settings::set_timezone,
+        settings::setup_two_factor,
+        settings::enable_two_factor,
+        settings::disable_two_factor,
+        settings::delete,
+        share::list,
+        share::create,
+        share::revoke,
+        share::view,
+        share::view_linked,
+        statistics::user,
+        statistics::note,
+        wander::surprise,
+        wander::start,
+        wander::next,
+        wander::step,
+        wander::overview,
+        wander::list,
+        workspace::list,
+        workspace::create,
+        workspace::switch,
+        workspace::save_member,
+        workspace::remove_member,
+        workspace::set_default_template,
+        workspace::set_journal,
     ]
 }
 
-#[get("/?<page>&<count>", rank = 1)]
+#[get("/?<query..>", rank = 1)]
 fn index(
+    csrf: CsrfToken,
     conn: MindmapDB,
     user: User,
-    page: Option<u64>,
-    count: Option<u64>,
+    workspace: Workspace,
+    query: LenientForm<IndexQuery>,
 ) -> Result<Template, failure::Error> {
-    let page = page.unwrap_or(1);
-    let count = count.unwrap_or(100);
+    let page = query.page.unwrap_or(1).max(1);
+    let count = query.count.unwrap_or(100).max(1).min(MAX_NOTES_PER_PAGE);
+
+    // A link with a sort order shows the notes that way once, the filter form saves it as the preference
+    let filter = match query.sort {
+        Some(sort) => NoteFilter {
+            sort,
+            orphans: query.orphans.unwrap_or(false),
+            from: parse_date(&query.from),
+            to: parse_date(&query.to),
+        },
+        None => NoteFilter::load_preference(&conn, user.id)?,
+    };
 
-    let notes = Note::load_paged(&conn, user.id, (page - 1) * count, count)?;
-    let total_notes = Note::count_all(&conn, user.id)?;
+    let accessor = workspace.accessor();
+    let notes = Note::load_filtered(
+        &conn,
+        &accessor,
+        &filter,
+        (page - 1).saturating_mul(count),
+        count,
+    )?;
+    let total_notes = Note::count_filtered(&conn, &accessor, &filter)?;
     let model = IndexModel {
         notes,
         page,
         total_pages: (total_notes / count) + 1,
         notes_per_page: count,
+        sort: filter.sort.as_str(),
+        orphans: filter.orphans,
+        from: filter.from,
+        to: filter.to,
     };
-    Ok(Template::render("index", &model))
+    Ok(csrf.render("index", &model))
+}
+
+#[post("/filter", data = "<data>")]
+fn save_filter(
+    conn: MindmapDB,
+    user: User,
+    data: CsrfForm<FilterModel>,
+) -> Result<Redirect, failure::Error> {
+    let filter = NoteFilter {
+        sort: data.sort,
+        orphans: data.orphans.unwrap_or(false),
+        from: parse_date(&data.from),
+        to: parse_date(&data.to),
+    };
+    filter.save_preference(&conn, user.id)?;
+    Ok(Redirect::to(format!(
+        "/?count={}",
+        data.count.unwrap_or(100).max(1)
+    )))
+}
+
+fn parse_date(date: &Option<String>) -> Option<NaiveDate> {
+    date.as_ref()
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+}
+
+#[derive(FromForm)]
+pub struct IndexQuery {
+    pub page: Option<u64>,
+    pub count: Option<u64>,
+    pub sort: Option<NoteSort>,
+    pub orphans: Option<bool>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(FromForm)]
+pub struct FilterModel {
+    pub sort: NoteSort,
+    pub orphans: Option<bool>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub count: Option<u64>,
+}
+
+impl<'v> FromFormValue<'v> for NoteSort {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<NoteSort, &'v RawStr> {
+        form_value.as_str().parse().map_err(|_| form_value)
+    }
 }
 
 #[derive(Serialize)]
@@ -60,4 +199,8 @@ pub struct IndexModel {
     pub page: u64,
     pub total_pages: u64,
     pub notes_per_page: u64,
+    pub sort: &'static str,
+    pub orphans: bool,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
 }
@@ -1,14 +1,20 @@
-use rocket::http::RawStr;
-use rocket::request::Form;
+use rocket::http::{RawStr, Status};
 use rocket::request::FromFormValue;
 use rocket::response::Redirect;
+use rocket::State;
 use rocket_contrib::templates::Template;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use uuid::Uuid;
 
+use crate::attachment::Attachments;
+use crate::csrf::{CsrfForm, CsrfToken};
 use crate::either::Either;
-use crate::note::{Note, NoteLink};
+use crate::markdown::Embed;
+use crate::note::{Note, NoteLink, NoteLookup};
 use crate::user::User;
-use crate::{HtmlSafeString, MindmapDB};
+use crate::workspace::{Workspace, WorkspaceEditor};
+use crate::MindmapDB;
 
 pub fn get_seo_name_from_path(p: &Path) -> &str {
     let mut first = p;
@@ -21,75 +27,152 @@ pub fn get_seo_name_from_path(p: &Path) -> &str {
     first.to_str().unwrap()
 }
 
+#[get("/new_note")]
+pub fn new_from_template(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: WorkspaceEditor,
+) -> Result<Template, failure::Error> {
+    let model = NewFromTemplateModel {
+        templates: Note::load_templates(&conn, &workspace.accessor())?,
+        default_template_id: workspace.default_template_id,
+    };
+    Ok(csrf.render("new_note", &model))
+}
+
 #[post("/new_note", data = "<data>")]
-pub fn new(conn: MindmapDB, user: User, data: Form<NewNote>) -> Result<Redirect, failure::Error> {
-    let note = Note::create(&conn, &data.title, &data.body, user.id)?;
+pub fn new(
+    conn: MindmapDB,
+    user: User,
+    workspace: WorkspaceEditor,
+    data: CsrfForm<NewNote>,
+) -> Result<Redirect, failure::Error> {
+    let accessor = workspace.accessor();
+    let template_id = match data.template {
+        TemplateChoice::Note(id) => Some(id),
+        TemplateChoice::Empty => None,
+        // The form next to every page does not choose a template, so notes without contents start from the default one
+        TemplateChoice::Default if data.body.trim().is_empty() => workspace.default_template_id,
+        TemplateChoice::Default => None,
+    };
+    let template = match template_id {
+        Some(id) => Note::load_template(&conn, id, &accessor)?,
+        None => None,
+    };
+    let revision = user.revision(None);
+    let note = match template {
+        Some(template) => Note::create_from_template(
+            &conn,
+            &data.title,
+            &template,
+            user.local_now(),
+            &accessor,
+            &revision,
+        )?,
+        None => Note::create(&conn, &data.title, &data.body, &accessor, &revision)?,
+    };
     Ok(Redirect::to(format!("/n/{}", note.seo_name)))
 }
 
 #[get("/n/<seo_name..>")]
 pub fn view(
+    csrf: CsrfToken,
     conn: MindmapDB,
-    user: User,
+    workspace: Workspace,
     seo_name: PathBuf,
 ) -> Result<Either<Template, Redirect>, failure::Error> {
     let seo_name = get_seo_name_from_path(&seo_name);
-    match Note::load_by_seo_name(&conn, seo_name, user.id)? {
-        Some(mut note) => {
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(mut note) => {
             note.increase_view_count(&conn)?;
-            let links = note.load_links(&conn)?;
-            let model = ViewNoteModel { note, links };
-            Ok(Either::Left(Template::render("note", model)))
+            let links = note.load_links(&conn, &accessor)?;
+            let embeds = note.load_embeds(&conn, &accessor)?;
+            let model = ViewNoteModel {
+                can_edit: workspace.role.can_edit() && note.can_write(&conn, &accessor)?,
+                note,
+                links,
+                embeds,
+            };
+            Ok(Either::Left(csrf.render("note", model)))
         }
-        None => Ok(Either::Right(Redirect::to("/"))),
+        NoteLookup::Renamed(note) => Ok(Either::Right(Redirect::permanent(format!(
+            "/n/{}",
+            note.seo_name
+        )))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
     }
 }
 
 #[get("/delete/<seo_name..>")]
 pub fn delete_preview(
+    csrf: CsrfToken,
     conn: MindmapDB,
-    user: User,
+    workspace: WorkspaceEditor,
     seo_name: PathBuf,
 ) -> Result<Either<Template, Redirect>, failure::Error> {
     let seo_name = get_seo_name_from_path(&seo_name);
-    match Note::load_by_seo_name(&conn, seo_name, user.id)? {
-        Some(note) => {
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(note) => {
+            if !note.can_write(&conn, &accessor)? {
+                return Ok(Either::Right(Redirect::to(format!("/n/{}", note.seo_name))));
+            }
             let model = DeletePreviewModel { note };
-            Ok(Either::Left(Template::render("delete_preview", model)))
+            Ok(Either::Left(csrf.render("delete_preview", model)))
         }
-        None => Ok(Either::Right(Redirect::to("/"))),
+        NoteLookup::Renamed(note) => Ok(Either::Right(Redirect::permanent(format!(
+            "/delete/{}",
+            note.seo_name
+        )))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
     }
 }
 
 #[post("/delete/<seo_name..>", data = "<data>")]
 pub fn delete_submit(
     conn: MindmapDB,
-    user: User,
+    attachments: State<Attachments>,
+    workspace: WorkspaceEditor,
     seo_name: PathBuf,
-    data: Form<DeleteSubmitModel>,
+    data: CsrfForm<DeleteSubmitModel>,
 ) -> Result<Redirect, failure::Error> {
     match data.action {
         DeleteActionType::Cancel => Ok(Redirect::to(format!("/n/{}", seo_name.to_str().unwrap()))),
         DeleteActionType::Delete => {
             let seo_name = get_seo_name_from_path(&seo_name);
-            Note::delete_by_seo_name(&conn, seo_name, user.id)?;
+            let accessor = workspace.accessor();
+            if let NoteLookup::Found(note) | NoteLookup::Renamed(note) =
+                Note::lookup(&conn, seo_name, &accessor)?
+            {
+                Note::delete_by_seo_name(&conn, &note.seo_name, &accessor, &attachments)?;
+            }
             Ok(Redirect::to("/"))
         }
     }
 }
 #[get("/edit/<seo_name..>")]
 pub fn edit(
+    csrf: CsrfToken,
     conn: MindmapDB,
-    user: User,
+    workspace: WorkspaceEditor,
     seo_name: PathBuf,
 ) -> Result<Either<Template, Redirect>, failure::Error> {
     let seo_name = get_seo_name_from_path(&seo_name);
-    match Note::load_by_seo_name(&conn, seo_name, user.id)? {
-        Some(note) => {
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(note) => {
+            if !note.can_write(&conn, &accessor)? {
+                return Ok(Either::Right(Redirect::to(format!("/n/{}", note.seo_name))));
+            }
             let model = EditNoteModel { note };
-            Ok(Either::Left(Template::render("edit_note", model)))
+            Ok(Either::Left(csrf.render("edit_note", model)))
         }
-        None => Ok(Either::Right(Redirect::to("/"))),
+        NoteLookup::Renamed(note) => Ok(Either::Right(Redirect::permanent(format!(
+            "/edit/{}",
+            note.seo_name
+        )))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
     }
 }
 
@@ -97,16 +180,79 @@ pub fn edit(
 pub fn edit_submit(
     conn: MindmapDB,
     user: User,
+    workspace: WorkspaceEditor,
     seo_name: PathBuf,
-    data: Form<SaveNoteModel>,
+    data: CsrfForm<SaveNoteModel>,
 ) -> Result<Either<Template, Redirect>, failure::Error> {
     let seo_name = get_seo_name_from_path(&seo_name);
-    match Note::load_by_seo_name(&conn, seo_name, user.id)? {
-        Some(mut note) => {
-            note.update(&conn, &data.title, &data.body)?;
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(mut note) | NoteLookup::Renamed(mut note) => {
+            let custom_seo_name = match (data.pin_seo_name, &data.seo_name) {
+                (Some(true), Some(name)) if !name.trim().is_empty() => Some(&**name),
+                _ => None,
+            };
+            let summary = data
+                .summary
+                .as_ref()
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty());
+            note.update(
+                &conn,
+                &accessor,
+                &user.revision(summary),
+                &data.title,
+                &data.body,
+                custom_seo_name,
+            )?;
             Ok(Either::Right(Redirect::to(format!("/n/{}", note.seo_name))))
         }
-        None => Ok(Either::Right(Redirect::to("/"))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
+    }
+}
+
+#[post("/toggle_task/<seo_name..>", data = "<data>")]
+pub fn toggle_task(
+    conn: MindmapDB,
+    user: User,
+    workspace: WorkspaceEditor,
+    seo_name: PathBuf,
+    data: CsrfForm<ToggleTaskModel>,
+) -> Result<Either<Redirect, Status>, failure::Error> {
+    let seo_name = get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(mut note) | NoteLookup::Renamed(mut note) => {
+            let toggled = note.toggle_task(
+                &conn,
+                &accessor,
+                &user.revision(Some("Toggled a task")),
+                data.task,
+            )?;
+            if !toggled {
+                return Ok(Either::Right(Status::Forbidden));
+            }
+            Ok(Either::Left(Redirect::to(format!("/n/{}", note.seo_name))))
+        }
+        NoteLookup::NotFound => Ok(Either::Left(Redirect::to("/"))),
+    }
+}
+
+#[post("/template/<seo_name..>", data = "<data>")]
+pub fn set_template(
+    conn: MindmapDB,
+    workspace: WorkspaceEditor,
+    seo_name: PathBuf,
+    data: CsrfForm<SetTemplateModel>,
+) -> Result<Redirect, failure::Error> {
+    let seo_name = get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(mut note) | NoteLookup::Renamed(mut note) => {
+            note.set_template(&conn, &accessor, data.is_template)?;
+            Ok(Redirect::to(format!("/n/{}", note.seo_name)))
+        }
+        NoteLookup::NotFound => Ok(Redirect::to("/")),
     }
 }
 
@@ -122,14 +268,61 @@ pub struct EditNoteModel {
 
 #[derive(FromForm, Debug)]
 pub struct SaveNoteModel {
-    pub title: HtmlSafeString,
-    pub body: HtmlSafeString,
+    pub title: String,
+    pub body: String,
+    pub seo_name: Option<String>,
+    pub pin_seo_name: Option<bool>,
+    /// A short description of the change, shown in the history of the note
+    pub summary: Option<String>,
+}
+
+#[derive(FromForm)]
+pub struct ToggleTaskModel {
+    /// The index of the task in the note, counting from the start
+    pub task: usize,
+}
+
+#[derive(FromForm)]
+pub struct SetTemplateModel {
+    pub is_template: bool,
 }
 
 #[derive(FromForm)]
 pub struct NewNote {
-    pub title: HtmlSafeString,
-    pub body: HtmlSafeString,
+    pub title: String,
+    pub body: String,
+    pub template: TemplateChoice,
+}
+
+/// The id of the template to start from, or empty to start without one.
+/// If it is left out, notes without a body start from the default template of the workspace.
+pub enum TemplateChoice {
+    Default,
+    Empty,
+    Note(Uuid),
+}
+
+impl<'v> FromFormValue<'v> for TemplateChoice {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<TemplateChoice, &'v RawStr> {
+        match form_value.as_str() {
+            "" => Ok(TemplateChoice::Empty),
+            id => Uuid::parse_str(id)
+                .map(TemplateChoice::Note)
+                .map_err(|_| form_value),
+        }
+    }
+
+    fn default() -> Option<TemplateChoice> {
+        Some(TemplateChoice::Default)
+    }
+}
+
+#[derive(Serialize)]
+pub struct NewFromTemplateModel {
+    pub templates: Vec<Note>,
+    pub default_template_id: Option<Uuid>,
 }
 #[derive(FromForm, Debug)]
 pub struct DeleteSubmitModel {
@@ -158,4 +351,8 @@ impl<'v> FromFormValue<'v> for DeleteActionType {
 pub struct ViewNoteModel {
     pub note: Note,
     pub links: Vec<NoteLink>,
+    /// The notes that the note embeds, see `Note::load_embeds`
+    pub embeds: HashMap<String, Embed>,
+    /// Whether the current user is allowed to edit the note
+    pub can_edit: bool,
 }
@@ -1,6 +1,7 @@
+use crate::csrf::CsrfToken;
 use crate::either::Either;
-use crate::note::{Note, NoteHistory};
-use crate::user::User;
+use crate::note::{Note, NoteHistory, NoteLookup};
+use crate::workspace::Workspace;
 use crate::MindmapDB;
 use rocket::response::Redirect;
 use rocket_contrib::templates::Template;
@@ -8,18 +9,24 @@ use std::path::PathBuf;
 
 #[get("/history/<seo_name..>")]
 pub fn view(
+    csrf: CsrfToken,
     conn: MindmapDB,
-    user: User,
+    workspace: Workspace,
     seo_name: PathBuf,
 ) -> Result<Either<Template, Redirect>, failure::Error> {
     let seo_name = super::note::get_seo_name_from_path(&seo_name);
-    match Note::load_by_seo_name(&conn, seo_name, user.id)? {
-        Some(note) => {
-            let history = note.load_history(&conn)?;
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(note) => {
+            let history = note.load_history(&conn, &accessor)?;
             let model = ViewNoteHistoryModel { note, history };
-            Ok(Either::Left(Template::render("note_history", &model)))
+            Ok(Either::Left(csrf.render("note_history", &model)))
         }
-        None => Ok(Either::Right(Redirect::to("/"))),
+        NoteLookup::Renamed(note) => Ok(Either::Right(Redirect::permanent(format!(
+            "/history/{}",
+            note.seo_name
+        )))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
     }
 }
 
@@ -1,12 +1,18 @@
+use crate::csrf::CsrfToken;
 use crate::either::Either;
-use crate::note::Note;
-use crate::user::User;
-use crate::{HtmlSafeString, MindmapDB};
+use crate::note::{Note, NoteLookup};
+use crate::workspace::{Workspace, WorkspaceEditor};
+use crate::MindmapDB;
 use rocket::response::Redirect;
 use rocket_contrib::templates::Template;
 
 #[get("/search?<q>")]
-pub fn search(conn: MindmapDB, user: User, q: HtmlSafeString) -> Result<Template, failure::Error> {
+pub fn search(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: Workspace,
+    q: String,
+) -> Result<Template, failure::Error> {
     let mut query = SearchQuery::default();
 
     for part in q.split(' ') {
@@ -17,24 +23,23 @@ pub fn search(conn: MindmapDB, user: User, q: HtmlSafeString) -> Result<Template
         }
     }
 
-    let results = Note::search(&conn, query, user.id)?;
+    let results = Note::search(&conn, query, &workspace.accessor())?;
 
-    let results = SearchResults {
-        search: q.get(),
-        results,
-    };
-    Ok(Template::render("search", &results))
+    let results = SearchResults { search: q, results };
+    Ok(csrf.render("search", &results))
 }
 
 #[get("/create_link/<seo_name>?<q>")]
 pub fn search_for_link(
+    csrf: CsrfToken,
     conn: MindmapDB,
-    user: User,
+    workspace: WorkspaceEditor,
     seo_name: String,
-    q: HtmlSafeString,
+    q: String,
 ) -> Result<Either<Template, Redirect>, failure::Error> {
-    match Note::load_by_seo_name(&conn, &seo_name, user.id)? {
-        Some(note) => {
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, &seo_name, &accessor)? {
+        NoteLookup::Found(note) | NoteLookup::Renamed(note) => {
             let mut query = SearchQuery::default();
 
             for part in q.split(' ') {
@@ -45,16 +50,16 @@ pub fn search_for_link(
                 }
             }
 
-            let results = Note::search(&conn, query, user.id)?;
+            let results = Note::search(&conn, query, &accessor)?;
 
             let results = SearchLinkResults {
-                search: q.get(),
+                search: q,
                 results,
                 note,
             };
-            Ok(Either::Left(Template::render("search_link", &results)))
+            Ok(Either::Left(csrf.render("search_link", &results)))
         }
-        None => Ok(Either::Right(Redirect::to("/"))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
     }
 }
 
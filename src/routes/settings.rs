@@ -0,0 +1,256 @@
+use chrono_tz::TZ_VARIANTS;
+use rocket::http::{Cookie, Cookies};
+use rocket::response::Redirect;
+use rocket::State;
+use rocket_contrib::templates::Template;
+use std::net::SocketAddr;
+
+use crate::attachment::Attachments;
+use crate::csrf::{CsrfForm, CsrfToken, NoFields};
+use crate::either::Either;
+use crate::password::PasswordHasher;
+use crate::totp::{self, Clock};
+use crate::user::User;
+use crate::MindmapDB;
+
+/// The private cookie that holds the two-factor secret while the user is adding it to their authenticator app
+const TOTP_SETUP_COOKIE: &str = "TOTP_SETUP";
+
+#[get("/settings")]
+pub fn view(csrf: CsrfToken, conn: MindmapDB, user: User) -> Result<Template, failure::Error> {
+    let model = SettingsModel::load(&conn, &user)?;
+    Ok(csrf.render("settings", &model))
+}
+
+#[post("/settings/password", data = "<data>")]
+pub fn change_password(
+    csrf: CsrfToken,
+    ip: SocketAddr,
+    conn: MindmapDB,
+    hasher: State<PasswordHasher>,
+    user: User,
+    data: CsrfForm<ChangePasswordModel>,
+) -> Result<Template, failure::Error> {
+    let mut model = SettingsModel::load(&conn, &user)?;
+    if data.new_password != data.repeat_password {
+        model.password_error = Some(String::from("Passwords don't match"));
+    } else {
+        match user.change_password(
+            &conn,
+            &hasher,
+            &data.old_password,
+            &data.new_password,
+            &ip.ip().to_string(),
+        ) {
+            Ok(()) => model.password_changed = true,
+            Err(e) => model.password_error = Some(e.to_string()),
+        }
+    }
+    Ok(csrf.render("settings", &model))
+}
+
+#[post("/settings/timezone", data = "<data>")]
+pub fn set_timezone(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    mut user: User,
+    data: CsrfForm<TimezoneModel>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    match user.set_timezone(&conn, &data.timezone) {
+        Ok(()) => Ok(Either::Right(Redirect::to("/settings"))),
+        Err(e) => {
+            let mut model = SettingsModel::load(&conn, &user)?;
+            model.timezone_error = Some(e.to_string());
+            Ok(Either::Left(csrf.render("settings", &model)))
+        }
+    }
+}
+
+#[post("/settings/two_factor/setup", data = "<_form>")]
+pub fn setup_two_factor(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    user: User,
+    mut cookies: Cookies,
+    _form: CsrfForm<NoFields>,
+) -> Result<Template, failure::Error> {
+    let secret = totp::generate_secret();
+    cookies.add_private(Cookie::new(TOTP_SETUP_COOKIE, secret.clone()));
+    let mut model = SettingsModel::load(&conn, &user)?;
+    model.two_factor_setup = Some(TwoFactorSetupModel::new(secret, &user)?);
+    Ok(csrf.render("settings", &model))
+}
+
+#[post("/settings/two_factor/enable", data = "<data>")]
+#[allow(clippy::too_many_arguments)]
+pub fn enable_two_factor(
+    csrf: CsrfToken,
+    ip: SocketAddr,
+    conn: MindmapDB,
+    hasher: State<PasswordHasher>,
+    clock: State<Clock>,
+    user: User,
+    mut cookies: Cookies,
+    data: CsrfForm<EnableTwoFactorModel>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let secret = match cookies.get_private(TOTP_SETUP_COOKIE) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => return Ok(Either::Right(Redirect::to("/settings"))),
+    };
+    match user.enable_two_factor(
+        &conn,
+        &hasher,
+        &clock,
+        &secret,
+        &data.password,
+        &data.code,
+        &ip.ip().to_string(),
+    ) {
+        Ok(recovery_codes) => {
+            cookies.remove_private(Cookie::named(TOTP_SETUP_COOKIE));
+            let user = User::load_by_id(&conn, user.id)?;
+            let mut model = SettingsModel::load(&conn, &user)?;
+            model.recovery_codes = recovery_codes;
+            Ok(Either::Left(csrf.render("settings", &model)))
+        }
+        Err(e) => {
+            let mut model = SettingsModel::load(&conn, &user)?;
+            model.two_factor_setup = Some(TwoFactorSetupModel::new(secret, &user)?);
+            model.two_factor_error = Some(e.to_string());
+            Ok(Either::Left(csrf.render("settings", &model)))
+        }
+    }
+}
+
+#[post("/settings/two_factor/disable", data = "<data>")]
+pub fn disable_two_factor(
+    csrf: CsrfToken,
+    ip: SocketAddr,
+    conn: MindmapDB,
+    hasher: State<PasswordHasher>,
+    user: User,
+    data: CsrfForm<ConfirmPasswordModel>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    match user.disable_two_factor(&conn, &hasher, &data.password, &ip.ip().to_string()) {
+        Ok(()) => Ok(Either::Right(Redirect::to("/settings"))),
+        Err(e) => {
+            let mut model = SettingsModel::load(&conn, &user)?;
+            model.two_factor_error = Some(e.to_string());
+            Ok(Either::Left(csrf.render("settings", &model)))
+        }
+    }
+}
+
+#[post("/settings/delete", data = "<data>")]
+#[allow(clippy::too_many_arguments)]
+pub fn delete(
+    csrf: CsrfToken,
+    ip: SocketAddr,
+    conn: MindmapDB,
+    hasher: State<PasswordHasher>,
+    attachments: State<Attachments>,
+    user: User,
+    mut cookies: Cookies,
+    data: CsrfForm<ConfirmPasswordModel>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let mut model = SettingsModel::load(&conn, &user)?;
+    match user.delete(
+        &conn,
+        &hasher,
+        &attachments,
+        &data.password,
+        &ip.ip().to_string(),
+    ) {
+        Ok(()) => {
+            let names: Vec<String> = cookies.iter().map(|c| c.name().to_owned()).collect();
+            for name in names {
+                cookies.remove(Cookie::named(name));
+            }
+            Ok(Either::Right(Redirect::to("/")))
+        }
+        Err(e) => {
+            model.delete_error = Some(e.to_string());
+            Ok(Either::Left(csrf.render("settings", &model)))
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SettingsModel {
+    pub name: String,
+    pub password_changed: bool,
+    pub password_error: Option<String>,
+    pub timezone: &'static str,
+    /// The names of every timezone the user can choose from
+    pub timezones: Vec<&'static str>,
+    pub timezone_error: Option<String>,
+    pub two_factor: bool,
+    pub recovery_codes_left: i64,
+    /// Set while the user is adding a new secret to their authenticator app
+    pub two_factor_setup: Option<TwoFactorSetupModel>,
+    /// The recovery codes that were just generated. These are only shown once.
+    pub recovery_codes: Vec<String>,
+    pub two_factor_error: Option<String>,
+    pub delete_error: Option<String>,
+}
+
+impl SettingsModel {
+    fn load(conn: &MindmapDB, user: &User) -> Result<SettingsModel, failure::Error> {
+        Ok(SettingsModel {
+            name: user.name.clone(),
+            password_changed: false,
+            password_error: None,
+            timezone: user.timezone.name(),
+            timezones: TZ_VARIANTS.iter().map(|tz| tz.name()).collect(),
+            timezone_error: None,
+            two_factor: user.two_factor,
+            recovery_codes_left: user.count_recovery_codes(conn)?,
+            two_factor_setup: None,
+            recovery_codes: Vec::new(),
+            two_factor_error: None,
+            delete_error: None,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorSetupModel {
+    pub secret: String,
+    pub uri: String,
+    /// The uri as an svg image
+    pub qr_code: String,
+}
+
+impl TwoFactorSetupModel {
+    fn new(secret: String, user: &User) -> Result<TwoFactorSetupModel, failure::Error> {
+        let uri = totp::provisioning_uri(&secret, &user.name);
+        Ok(TwoFactorSetupModel {
+            qr_code: totp::qr_code_svg(&uri)?,
+            secret,
+            uri,
+        })
+    }
+}
+
+#[derive(FromForm)]
+pub struct TimezoneModel {
+    pub timezone: String,
+}
+
+#[derive(FromForm)]
+pub struct ChangePasswordModel {
+    pub old_password: String,
+    pub new_password: String,
+    pub repeat_password: String,
+}
+
+#[derive(FromForm)]
+pub struct EnableTwoFactorModel {
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(FromForm)]
+pub struct ConfirmPasswordModel {
+    pub password: String,
+}
@@ -0,0 +1,129 @@
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::audit::AuditEvent;
+use crate::csrf::{CsrfForm, CsrfToken, NoFields};
+use crate::either::Either;
+use crate::note::{Note, NoteLookup};
+use crate::share::{Share, SharedNote, MAX_DEPTH};
+use crate::user::User;
+use crate::workspace::WorkspaceEditor;
+use crate::MindmapDB;
+
+#[get("/share/<seo_name..>")]
+pub fn list(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: WorkspaceEditor,
+    seo_name: PathBuf,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(note) => {
+            if !note.can_write(&conn, &accessor)? {
+                return Ok(Either::Right(Redirect::to(format!("/n/{}", note.seo_name))));
+            }
+            let shares = Share::load_by_note(&conn, &note)?;
+            let model = ShareListModel {
+                note,
+                shares,
+                depths: (0..=MAX_DEPTH).collect(),
+            };
+            Ok(Either::Left(csrf.render("share", &model)))
+        }
+        NoteLookup::Renamed(note) => Ok(Either::Right(Redirect::permanent(format!(
+            "/share/{}",
+            note.seo_name
+        )))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
+    }
+}
+
+#[post("/share/<seo_name..>", data = "<data>")]
+pub fn create(
+    ip: SocketAddr,
+    conn: MindmapDB,
+    user: User,
+    workspace: WorkspaceEditor,
+    seo_name: PathBuf,
+    data: CsrfForm<CreateShareModel>,
+) -> Result<Redirect, failure::Error> {
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    let accessor = workspace.accessor();
+    match Note::lookup(&conn, seo_name, &accessor)? {
+        NoteLookup::Found(note) | NoteLookup::Renamed(note) => {
+            if !note.can_write(&conn, &accessor)? {
+                return Ok(Redirect::to(format!("/n/{}", note.seo_name)));
+            }
+            Share::create(&conn, &note, user.id, data.depth, data.expires_in_days)?;
+            AuditEvent::ShareCreated.record(
+                &conn,
+                Some(user.id),
+                Some(&ip.ip().to_string()),
+                Some(&note.seo_name),
+            )?;
+            Ok(Redirect::to(format!("/share/{}", note.seo_name)))
+        }
+        NoteLookup::NotFound => Ok(Redirect::to("/")),
+    }
+}
+
+#[post("/revoke_share/<id>", data = "<_form>")]
+pub fn revoke(
+    ip: SocketAddr,
+    conn: MindmapDB,
+    user: User,
+    workspace: WorkspaceEditor,
+    id: String,
+    _form: CsrfForm<NoFields>,
+) -> Result<Redirect, failure::Error> {
+    let id = Uuid::parse_str(&id)?;
+    match Share::revoke(&conn, id, &workspace.accessor())? {
+        Some(note) => {
+            AuditEvent::ShareRevoked.record(
+                &conn,
+                Some(user.id),
+                Some(&ip.ip().to_string()),
+                Some(&note.seo_name),
+            )?;
+            Ok(Redirect::to(format!("/share/{}", note.seo_name)))
+        }
+        None => Ok(Redirect::to("/")),
+    }
+}
+
+#[get("/s/<token>")]
+pub fn view(conn: MindmapDB, token: String) -> Result<Option<Template>, failure::Error> {
+    Ok(SharedNote::load(&conn, &token, None)?.map(|note| Template::render("shared_note", &note)))
+}
+
+// Ranked below the attachments of shared notes, `/s/<token>/attachment/...`
+#[get("/s/<token>/<seo_name..>", rank = 2)]
+pub fn view_linked(
+    conn: MindmapDB,
+    token: String,
+    seo_name: PathBuf,
+) -> Result<Option<Template>, failure::Error> {
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    Ok(SharedNote::load(&conn, &token, Some(seo_name))?
+        .map(|note| Template::render("shared_note", &note)))
+}
+
+#[derive(Serialize)]
+pub struct ShareListModel {
+    pub note: Note,
+    pub shares: Vec<Share>,
+    /// The depths that can be chosen for a new share
+    pub depths: Vec<u32>,
+}
+
+#[derive(FromForm)]
+pub struct CreateShareModel {
+    pub depth: u32,
+    /// Left empty if the share should never expire
+    pub expires_in_days: Option<u32>,
+}
@@ -0,0 +1,47 @@
+use crate::csrf::CsrfToken;
+use crate::either::Either;
+use crate::note::{Note, NoteLookup};
+use crate::statistics::{NoteStatistics, UserStatistics};
+use crate::workspace::Workspace;
+use crate::MindmapDB;
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+use std::path::PathBuf;
+
+#[get("/stats")]
+pub fn user(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: Workspace,
+) -> Result<Template, failure::Error> {
+    let statistics = UserStatistics::load(&conn, &workspace.accessor())?;
+    Ok(csrf.render("statistics", &statistics))
+}
+
+#[get("/stats/<seo_name..>")]
+pub fn note(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: Workspace,
+    seo_name: PathBuf,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let seo_name = super::note::get_seo_name_from_path(&seo_name);
+    match Note::lookup(&conn, seo_name, &workspace.accessor())? {
+        NoteLookup::Found(note) => {
+            let statistics = NoteStatistics::load(&conn, note.id)?;
+            let model = ViewNoteStatisticsModel { note, statistics };
+            Ok(Either::Left(csrf.render("note_statistics", &model)))
+        }
+        NoteLookup::Renamed(note) => Ok(Either::Right(Redirect::permanent(format!(
+            "/stats/{}",
+            note.seo_name
+        )))),
+        NoteLookup::NotFound => Ok(Either::Right(Redirect::to("/"))),
+    }
+}
+
+#[derive(Serialize)]
+pub struct ViewNoteStatisticsModel {
+    pub note: Note,
+    pub statistics: NoteStatistics,
+}
@@ -0,0 +1,125 @@
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::csrf::{CsrfForm, CsrfToken, NoFields};
+use crate::either::Either;
+use crate::markdown::Embed;
+use crate::note::{Note, NoteLink};
+use crate::walk::Walk;
+use crate::workspace::Workspace;
+use crate::MindmapDB;
+
+#[get("/surprise")]
+pub fn surprise(conn: MindmapDB, workspace: Workspace) -> Result<Redirect, failure::Error> {
+    match Note::load_random(&conn, &workspace.accessor())? {
+        Some(note) => Ok(Redirect::to(format!("/n/{}", note.seo_name))),
+        None => Ok(Redirect::to("/")),
+    }
+}
+
+#[post("/wander", data = "<_form>")]
+pub fn start(
+    conn: MindmapDB,
+    workspace: Workspace,
+    _form: CsrfForm<NoFields>,
+) -> Result<Redirect, failure::Error> {
+    match Walk::start(&conn, &workspace.accessor())? {
+        Some(walk) => Ok(Redirect::to(format!("/wander/{}?step=0", walk.id))),
+        None => Ok(Redirect::to("/")),
+    }
+}
+
+#[post("/wander/<id>/next", data = "<_form>")]
+pub fn next(
+    conn: MindmapDB,
+    workspace: Workspace,
+    id: String,
+    _form: CsrfForm<NoFields>,
+) -> Result<Redirect, failure::Error> {
+    let id = Uuid::parse_str(&id)?;
+    let accessor = workspace.accessor();
+    match Walk::load(&conn, id, &accessor)? {
+        Some(mut walk) => {
+            walk.step(&conn, &accessor)?;
+            Ok(Redirect::to(format!(
+                "/wander/{}?step={}",
+                walk.id,
+                walk.steps.len().saturating_sub(1)
+            )))
+        }
+        None => Ok(Redirect::to("/")),
+    }
+}
+
+#[get("/wander/<id>?<step>")]
+pub fn step(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: Workspace,
+    id: String,
+    step: usize,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let id = Uuid::parse_str(&id)?;
+    let accessor = workspace.accessor();
+    let mut walk = match Walk::load(&conn, id, &accessor)? {
+        Some(walk) => walk,
+        None => return Ok(Either::Right(Redirect::to("/"))),
+    };
+    if step >= walk.steps.len() {
+        return Ok(Either::Right(Redirect::to(format!("/wander/{}", walk.id))));
+    }
+    let step_count = walk.steps.len();
+    let note = walk.steps.swap_remove(step);
+    let links = note.load_links(&conn, &accessor)?;
+    let embeds = note.load_embeds(&conn, &accessor)?;
+    let model = WanderStepModel {
+        walk_id: walk.id,
+        step,
+        step_count,
+        note,
+        links,
+        embeds,
+    };
+    Ok(Either::Left(csrf.render("wander", &model)))
+}
+
+#[get("/wander/<id>")]
+pub fn overview(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: Workspace,
+    id: String,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let id = Uuid::parse_str(&id)?;
+    match Walk::load(&conn, id, &workspace.accessor())? {
+        Some(walk) => Ok(Either::Left(csrf.render("walk", &walk))),
+        None => Ok(Either::Right(Redirect::to("/"))),
+    }
+}
+
+#[get("/walks")]
+pub fn list(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    workspace: Workspace,
+) -> Result<Template, failure::Error> {
+    let walks = Walk::load_recent(&conn, &workspace.accessor(), 20)?;
+    Ok(csrf.render("walks", &WalkListModel { walks }))
+}
+
+#[derive(Serialize)]
+pub struct WanderStepModel {
+    pub walk_id: Uuid,
+    pub step: usize,
+    pub step_count: usize,
+    pub note: Note,
+    pub links: Vec<NoteLink>,
+    pub embeds: HashMap<String, Embed>,
+}
+
+#[derive(Serialize)]
+pub struct WalkListModel {
+    pub walks: Vec<Walk>,
+}
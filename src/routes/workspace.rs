@@ -0,0 +1,197 @@
+use failure::bail;
+use rocket::http::{Cookie, Cookies, RawStr};
+use rocket::request::FromFormValue;
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+use uuid::Uuid;
+
+use crate::csrf::{CsrfForm, CsrfToken, NoFields};
+use crate::either::Either;
+use crate::note::Note;
+use crate::user::User;
+use crate::workspace::{Member, Role, Workspace, WorkspaceOwner, WORKSPACE_COOKIE};
+use crate::MindmapDB;
+
+#[get("/workspaces")]
+pub fn list(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    user: User,
+    workspace: Workspace,
+) -> Result<Template, failure::Error> {
+    let model = WorkspacesModel::load(&conn, &user, workspace, None)?;
+    Ok(csrf.render("workspaces", &model))
+}
+
+#[post("/workspaces", data = "<data>")]
+pub fn create(
+    conn: MindmapDB,
+    user: User,
+    mut cookies: Cookies,
+    data: CsrfForm<CreateWorkspaceModel>,
+) -> Result<Redirect, failure::Error> {
+    let workspace = Workspace::create(&conn, &data.name, user.id)?;
+    cookies.add_private(Cookie::new(WORKSPACE_COOKIE, workspace.id.to_string()));
+    Ok(Redirect::to("/workspaces"))
+}
+
+#[post("/workspaces/<id>/switch", data = "<_form>")]
+pub fn switch(
+    conn: MindmapDB,
+    user: User,
+    mut cookies: Cookies,
+    id: String,
+    _form: CsrfForm<NoFields>,
+) -> Result<Redirect, failure::Error> {
+    let id = Uuid::parse_str(&id)?;
+    if let Some(workspace) = Workspace::load_by_member(&conn, id, user.id)? {
+        cookies.add_private(Cookie::new(WORKSPACE_COOKIE, workspace.id.to_string()));
+    }
+    Ok(Redirect::to("/"))
+}
+
+#[post("/workspace/members", data = "<data>")]
+pub fn save_member(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    user: User,
+    workspace: WorkspaceOwner,
+    data: CsrfForm<SaveMemberModel>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    match workspace.save_member(&conn, &data.name, data.role) {
+        Ok(()) => Ok(Either::Right(Redirect::to("/workspaces"))),
+        Err(e) => {
+            let model = WorkspacesModel::load(&conn, &user, workspace.0, Some(e.to_string()))?;
+            Ok(Either::Left(csrf.render("workspaces", &model)))
+        }
+    }
+}
+
+#[post("/workspace/members/<user_id>/remove", data = "<_form>")]
+pub fn remove_member(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    user: User,
+    workspace: WorkspaceOwner,
+    user_id: String,
+    _form: CsrfForm<NoFields>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let user_id = Uuid::parse_str(&user_id)?;
+    match workspace.remove_member(&conn, user_id) {
+        Ok(()) => Ok(Either::Right(Redirect::to("/workspaces"))),
+        Err(e) => {
+            let model = WorkspacesModel::load(&conn, &user, workspace.0, Some(e.to_string()))?;
+            Ok(Either::Left(csrf.render("workspaces", &model)))
+        }
+    }
+}
+
+/// The id of the template that a form chose, or `None` if it chose no template
+fn parse_template_id(id: &str) -> Result<Option<Uuid>, failure::Error> {
+    match id {
+        "" => Ok(None),
+        id => match Uuid::parse_str(id) {
+            Ok(id) => Ok(Some(id)),
+            Err(_) => bail!("Only a template of this workspace can be chosen"),
+        },
+    }
+}
+
+#[post("/workspace/default_template", data = "<data>")]
+pub fn set_default_template(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    user: User,
+    workspace: WorkspaceOwner,
+    data: CsrfForm<DefaultTemplateModel>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let result = parse_template_id(&data.template)
+        .and_then(|template_id| workspace.set_default_template(&conn, template_id));
+    match result {
+        Ok(()) => Ok(Either::Right(Redirect::to("/workspaces"))),
+        Err(e) => {
+            let model = WorkspacesModel::load(&conn, &user, workspace.0, Some(e.to_string()))?;
+            Ok(Either::Left(csrf.render("workspaces", &model)))
+        }
+    }
+}
+
+#[post("/workspace/journal", data = "<data>")]
+pub fn set_journal(
+    csrf: CsrfToken,
+    conn: MindmapDB,
+    user: User,
+    workspace: WorkspaceOwner,
+    data: CsrfForm<JournalSettingsModel>,
+) -> Result<Either<Template, Redirect>, failure::Error> {
+    let result = parse_template_id(&data.template).and_then(|template_id| {
+        workspace.set_journal(&conn, data.title_format.trim(), template_id)
+    });
+    match result {
+        Ok(()) => Ok(Either::Right(Redirect::to("/workspaces"))),
+        Err(e) => {
+            let model = WorkspacesModel::load(&conn, &user, workspace.0, Some(e.to_string()))?;
+            Ok(Either::Left(csrf.render("workspaces", &model)))
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct WorkspacesModel {
+    pub current: Workspace,
+    pub workspaces: Vec<Workspace>,
+    pub members: Vec<Member>,
+    /// The templates that can be the default or journal template of the current workspace
+    pub templates: Vec<Note>,
+    pub error: Option<String>,
+}
+
+impl WorkspacesModel {
+    fn load(
+        conn: &diesel::PgConnection,
+        user: &User,
+        current: Workspace,
+        error: Option<String>,
+    ) -> Result<WorkspacesModel, failure::Error> {
+        Ok(WorkspacesModel {
+            workspaces: Workspace::load_all_by_member(conn, user.id)?,
+            members: current.load_members(conn)?,
+            templates: Note::load_templates(conn, &current.accessor())?,
+            current,
+            error,
+        })
+    }
+}
+
+#[derive(FromForm)]
+pub struct CreateWorkspaceModel {
+    pub name: String,
+}
+
+#[derive(FromForm)]
+pub struct DefaultTemplateModel {
+    /// The id of the template, or empty for no default template
+    pub template: String,
+}
+
+#[derive(FromForm)]
+pub struct SaveMemberModel {
+    pub name: String,
+    pub role: Role,
+}
+
+impl<'v> FromFormValue<'v> for Role {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<Role, &'v RawStr> {
+        form_value.as_str().parse().map_err(|_| form_value)
+    }
+}
+
+#[derive(FromForm)]
+pub struct JournalSettingsModel {
+    /// The strftime format of the titles of daily notes
+    pub title_format: String,
+    /// The id of the template that daily notes start from, or empty for none
+    pub template: String,
+}
@@ -1,3 +1,45 @@
+table! {
+    attachment (id) {
+        id -> Uuid,
+        note_id -> Uuid,
+        file_name -> Text,
+        content_type -> Text,
+        size -> Int8,
+        has_thumbnail -> Bool,
+        created -> Timestamptz,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Uuid,
+        created -> Timestamptz,
+        user_id -> Nullable<Uuid>,
+        event -> Text,
+        ip -> Nullable<Text>,
+        details -> Nullable<Text>,
+    }
+}
+
+table! {
+    auth_attempt (id) {
+        id -> Uuid,
+        created -> Timestamptz,
+        kind -> Text,
+        ip -> Text,
+        user_name -> Nullable<Text>,
+    }
+}
+
+table! {
+    journal_entry (user_id, workspace_id, day) {
+        user_id -> Uuid,
+        workspace_id -> Uuid,
+        day -> Date,
+        note_id -> Uuid,
+    }
+}
+
 table! {
     note (id) {
         id -> Uuid,
@@ -7,6 +49,21 @@ table! {
         title -> Text,
         body -> Text,
         deleted -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        seo_name_pinned -> Bool,
+        workspace_id -> Uuid,
+        is_template -> Bool,
+    }
+}
+
+table! {
+    note_acl (id) {
+        id -> Uuid,
+        note_id -> Uuid,
+        user_id -> Nullable<Uuid>,
+        role -> Nullable<Text>,
+        can_write -> Bool,
     }
 }
 
@@ -17,6 +74,9 @@ table! {
         created -> Timestamptz,
         title -> Text,
         body -> Text,
+        user_id -> Nullable<Uuid>,
+        token_id -> Nullable<Uuid>,
+        summary -> Nullable<Text>,
     }
 }
 
@@ -26,6 +86,46 @@ table! {
         left -> Uuid,
         right -> Uuid,
         click_count -> Int4,
+        embedded -> Bool,
+    }
+}
+
+table! {
+    note_link_click_day (link_id, day) {
+        link_id -> Uuid,
+        day -> Date,
+        click_count -> Int4,
+    }
+}
+
+table! {
+    note_seo_alias (id) {
+        id -> Uuid,
+        note_id -> Uuid,
+        seo_name -> Text,
+        created -> Timestamptz,
+        workspace_id -> Uuid,
+    }
+}
+
+table! {
+    note_share (id) {
+        id -> Uuid,
+        note_id -> Uuid,
+        user_id -> Uuid,
+        token -> Text,
+        depth -> Int4,
+        created -> Timestamptz,
+        expires -> Nullable<Timestamptz>,
+        revoked -> Bool,
+    }
+}
+
+table! {
+    note_view_day (note_id, day) {
+        note_id -> Uuid,
+        day -> Date,
+        view_count -> Int4,
     }
 }
 
@@ -34,6 +134,28 @@ table! {
         id -> Uuid,
         name -> Text,
         password -> Text,
+        is_admin -> Bool,
+        totp_secret -> Nullable<Text>,
+        totp_last_step -> Nullable<Int8>,
+        timezone -> Text,
+    }
+}
+
+table! {
+    user_preference (user_id) {
+        user_id -> Uuid,
+        index_sort -> Text,
+        index_orphans -> Bool,
+        index_from -> Nullable<Date>,
+        index_to -> Nullable<Date>,
+    }
+}
+
+table! {
+    user_recovery_code (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        code_hash -> Text,
     }
 }
 
@@ -47,8 +169,90 @@ table! {
     }
 }
 
+table! {
+    walk (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        created -> Timestamptz,
+        workspace_id -> Uuid,
+    }
+}
+
+table! {
+    walk_step (id) {
+        id -> Uuid,
+        walk_id -> Uuid,
+        position -> Int4,
+        note_id -> Uuid,
+    }
+}
+
+table! {
+    workspace (id) {
+        id -> Uuid,
+        name -> Text,
+        created -> Timestamptz,
+        default_template_id -> Nullable<Uuid>,
+        journal_title_format -> Text,
+        journal_template_id -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    workspace_member (workspace_id, user_id) {
+        workspace_id -> Uuid,
+        user_id -> Uuid,
+        role -> Text,
+    }
+}
+
+joinable!(attachment -> note (note_id));
+joinable!(audit_log -> user (user_id));
+joinable!(journal_entry -> note (note_id));
+joinable!(journal_entry -> user (user_id));
+joinable!(journal_entry -> workspace (workspace_id));
 joinable!(note -> user (user_id));
+joinable!(note -> workspace (workspace_id));
+joinable!(note_acl -> note (note_id));
+joinable!(note_acl -> user (user_id));
 joinable!(note_history -> note (note_id));
+joinable!(note_history -> user (user_id));
+joinable!(note_history -> user_token (token_id));
+joinable!(note_link_click_day -> note_link (link_id));
+joinable!(note_seo_alias -> note (note_id));
+joinable!(note_seo_alias -> workspace (workspace_id));
+joinable!(note_share -> note (note_id));
+joinable!(note_share -> user (user_id));
+joinable!(note_view_day -> note (note_id));
+joinable!(user_preference -> user (user_id));
+joinable!(user_recovery_code -> user (user_id));
 joinable!(user_token -> user (user_id));
+joinable!(walk -> user (user_id));
+joinable!(walk -> workspace (workspace_id));
+joinable!(walk_step -> note (note_id));
+joinable!(walk_step -> walk (walk_id));
+joinable!(workspace_member -> user (user_id));
+joinable!(workspace_member -> workspace (workspace_id));
 
-allow_tables_to_appear_in_same_query!(note, note_history, note_link, user, user_token,);
+allow_tables_to_appear_in_same_query!(
+    attachment,
+    audit_log,
+    auth_attempt,
+    journal_entry,
+    note,
+    note_acl,
+    note_history,
+    note_link,
+    note_link_click_day,
+    note_seo_alias,
+    note_share,
+    note_view_day,
+    user,
+    user_preference,
+    user_recovery_code,
+    user_token,
+    walk,
+    walk_step,
+    workspace,
+    workspace_member,
+);
@@ -0,0 +1,161 @@
+use crate::models::note_acl::Accessor;
+use crate::models::note_link::NoteLink as DatabaseNoteLink;
+use crate::models::note_share::NoteShare as DatabaseNoteShare;
+use crate::note::Note;
+use chrono::{DateTime, Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// The length of the random token in a share url
+const TOKEN_LENGTH: usize = 32;
+/// The maximum amount of links that can be followed from a shared note
+pub const MAX_DEPTH: u32 = 3;
+
+/// A read-only link to a note, that can be opened without logging in
+#[derive(Serialize)]
+pub struct Share {
+    pub id: Uuid,
+    pub token: String,
+    pub depth: i32,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub revoked: bool,
+    pub active: bool,
+}
+
+impl From<DatabaseNoteShare> for Share {
+    fn from(s: DatabaseNoteShare) -> Share {
+        Share {
+            active: !s.revoked && s.expires.map(|e| e > Utc::now()).unwrap_or(true),
+            id: s.id,
+            token: s.token,
+            depth: s.depth,
+            created: s.created,
+            expires: s.expires,
+            revoked: s.revoked,
+        }
+    }
+}
+
+impl Share {
+    /// Share the note, and the notes up to `depth` links away from it.
+    /// If `expires_in_days` is given, the share stops working after that many days.
+    pub fn create(
+        conn: &diesel::PgConnection,
+        note: &Note,
+        user_id: Uuid,
+        depth: u32,
+        expires_in_days: Option<u32>,
+    ) -> Result<Share, failure::Error> {
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LENGTH)
+            .collect();
+        let expires = expires_in_days.map(|days| Utc::now() + Duration::days(i64::from(days)));
+        let share = DatabaseNoteShare::create(
+            conn,
+            note.id,
+            user_id,
+            &token,
+            depth.min(MAX_DEPTH) as i32,
+            expires,
+        )?;
+        Ok(share.into())
+    }
+
+    pub fn load_by_note(
+        conn: &diesel::PgConnection,
+        note: &Note,
+    ) -> Result<Vec<Share>, failure::Error> {
+        Ok(DatabaseNoteShare::load_by_note(conn, note.id)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Revoke the share, and return the note that was shared. Returns `None` if the accessor can not change the note.
+    pub fn revoke(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        accessor: &Accessor,
+    ) -> Result<Option<Note>, failure::Error> {
+        match DatabaseNoteShare::revoke(conn, id, accessor)? {
+            Some(share) => Note::load_by_id(conn, share.note_id, accessor),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A note as seen through a share, together with the links that stay within the shared notes
+#[derive(Serialize)]
+pub struct SharedNote {
+    pub token: String,
+    pub root: Note,
+    pub note: Note,
+    pub links: Vec<Note>,
+}
+
+impl SharedNote {
+    /// Load a note through the share with the given token.
+    ///
+    /// If `seo_name` is `None`, the shared note itself is loaded. Otherwise the note with that seo name is loaded,
+    /// if it is within the shared neighbourhood.
+    /// Returns `None` if the share does not exist, is revoked or expired, or the note is not shared.
+    ///
+    /// Only the notes that the user who created the share can (still) read are shared.
+    ///
+    /// This does not count as a view of the note.
+    pub fn load(
+        conn: &diesel::PgConnection,
+        token: &str,
+        seo_name: Option<&str>,
+    ) -> Result<Option<SharedNote>, failure::Error> {
+        let share = match DatabaseNoteShare::load_active_by_token(conn, token)? {
+            Some(share) => share,
+            None => return Ok(None),
+        };
+        let accessor = match Accessor::load_for_note(conn, share.note_id, share.user_id)? {
+            Some(accessor) => accessor,
+            None => return Ok(None),
+        };
+        let root = match Note::load_by_id(conn, share.note_id, &accessor)? {
+            Some(note) => note,
+            None => return Ok(None),
+        };
+        let root_id = root.id;
+        let mut shared = root.load_neighbourhood(conn, &accessor, share.depth as u32)?;
+        let note_id = match seo_name {
+            None => root_id,
+            Some(name) => match shared.values().find(|n| n.seo_name == name) {
+                Some(note) => note.id,
+                None => return Ok(None),
+            },
+        };
+
+        // A note can be linked in both directions, and is only listed once
+        let mut seen = HashSet::new();
+        let links: Vec<Note> = DatabaseNoteLink::load_by_note(conn, note_id, &accessor)?
+            .into_iter()
+            .filter_map(|l| l.other)
+            .filter(|other| shared.contains_key(&other.id) && seen.insert(other.id))
+            .map(Into::into)
+            .collect();
+
+        let note = match shared.remove(&note_id) {
+            Some(note) => note,
+            None => return Ok(None),
+        };
+        let root = match shared.remove(&root_id) {
+            Some(root) => root,
+            None => note.clone(),
+        };
+        Ok(Some(SharedNote {
+            token: share.token,
+            root,
+            note,
+            links,
+        }))
+    }
+}
@@ -0,0 +1,182 @@
+use crate::models::note_acl::Accessor;
+use crate::models::statistics::{DayTotal, NoteLinkClickDay, NoteViewDay};
+use crate::note::Note;
+use chrono::{Duration, NaiveDate, Utc};
+use diesel::Connection;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The amount of days that are shown in the charts
+const CHART_DAYS: i64 = 90;
+/// Trending notes are determined by comparing the last `TRENDING_DAYS` days with the `TRENDING_DAYS` before that
+const TRENDING_DAYS: i64 = 7;
+/// Notes that have not been viewed for this many days are considered forgotten
+const FORGOTTEN_DAYS: i64 = 60;
+/// The amount of trending and forgotten notes that are shown
+const LIST_COUNT: i64 = 10;
+/// Daily statistics older than this are rolled up into monthly totals
+const RETENTION_DAYS: i64 = 365;
+
+#[derive(Serialize)]
+pub struct Chart {
+    pub total: i64,
+    pub max: i64,
+    pub days: Vec<ChartDay>,
+}
+
+#[derive(Serialize)]
+pub struct ChartDay {
+    pub day: NaiveDate,
+    pub count: i64,
+    /// The height of the bar, as a percentage of the highest bar in the chart
+    pub height: i64,
+}
+
+impl Chart {
+    fn from_totals(totals: Vec<DayTotal>, since: NaiveDate, until: NaiveDate) -> Chart {
+        let counts: HashMap<NaiveDate, i64> =
+            totals.into_iter().map(|t| (t.day, t.total)).collect();
+        let max = counts.values().copied().max().unwrap_or(0);
+        let mut days = Vec::new();
+        let mut day = since;
+        while day <= until {
+            let count = counts.get(&day).copied().unwrap_or(0);
+            days.push(ChartDay {
+                day,
+                count,
+                height: if max == 0 { 0 } else { count * 100 / max },
+            });
+            day = day.succ();
+        }
+        Chart {
+            total: days.iter().map(|d| d.count).sum(),
+            max,
+            days,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TrendingNote {
+    pub note: Note,
+    pub recent: i64,
+    pub previous: i64,
+}
+
+#[derive(Serialize)]
+pub struct ForgottenNote {
+    pub note: Note,
+    pub view_count: i32,
+    pub last_viewed: Option<NaiveDate>,
+}
+
+#[derive(Serialize)]
+/// The statistics of all notes in a workspace
+pub struct UserStatistics {
+    pub views: Chart,
+    pub clicks: Chart,
+    pub trending: Vec<TrendingNote>,
+    pub forgotten: Vec<ForgottenNote>,
+}
+
+impl UserStatistics {
+    pub fn load(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<UserStatistics, failure::Error> {
+        let today = Utc::today().naive_utc();
+        let since = today - Duration::days(CHART_DAYS - 1);
+        let trending = NoteViewDay::load_trending(
+            conn,
+            accessor,
+            today - Duration::days(TRENDING_DAYS * 2 - 1),
+            today - Duration::days(TRENDING_DAYS - 1),
+            LIST_COUNT,
+        )?;
+        let forgotten = NoteViewDay::load_forgotten(
+            conn,
+            accessor,
+            today - Duration::days(FORGOTTEN_DAYS),
+            LIST_COUNT,
+        )?;
+        Ok(UserStatistics {
+            views: Chart::from_totals(
+                NoteViewDay::load_totals_by_workspace(conn, accessor, since)?,
+                since,
+                today,
+            ),
+            clicks: Chart::from_totals(
+                NoteLinkClickDay::load_totals_by_workspace(conn, accessor, since)?,
+                since,
+                today,
+            ),
+            trending: trending
+                .into_iter()
+                .map(|t| TrendingNote {
+                    note: t.note.into(),
+                    recent: t.recent,
+                    previous: t.previous,
+                })
+                .collect(),
+            forgotten: forgotten
+                .into_iter()
+                .map(|f| ForgottenNote {
+                    view_count: f.note.view_count,
+                    note: f.note.into(),
+                    last_viewed: f.last_viewed,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct NoteStatistics {
+    pub views: Chart,
+    pub clicks: Chart,
+}
+
+impl NoteStatistics {
+    pub fn load(
+        conn: &diesel::PgConnection,
+        note_id: Uuid,
+    ) -> Result<NoteStatistics, failure::Error> {
+        let today = Utc::today().naive_utc();
+        let since = today - Duration::days(CHART_DAYS - 1);
+        Ok(NoteStatistics {
+            views: Chart::from_totals(
+                NoteViewDay::load_totals_by_note(conn, note_id, since)?,
+                since,
+                today,
+            ),
+            clicks: Chart::from_totals(
+                NoteLinkClickDay::load_totals_by_note(conn, note_id, since)?,
+                since,
+                today,
+            ),
+        })
+    }
+}
+
+/// Roll up the daily statistics that are older than `RETENTION_DAYS` into monthly totals
+pub fn compact(conn: &diesel::PgConnection) -> Result<(), failure::Error> {
+    let before = Utc::today().naive_utc() - Duration::days(RETENTION_DAYS);
+    conn.transaction::<_, failure::Error, _>(|| {
+        NoteViewDay::compact(conn, before)?;
+        NoteLinkClickDay::compact(conn, before)?;
+        Ok(())
+    })
+}
+
+/// Start a background thread that compacts the statistics once a day
+pub fn spawn_compaction_job(database_url: String) {
+    std::thread::spawn(move || loop {
+        let result = diesel::PgConnection::establish(&database_url)
+            .map_err(failure::Error::from)
+            .and_then(|conn| compact(&conn));
+        if let Err(e) = result {
+            eprintln!("Could not compact statistics: {}", e);
+        }
+        std::thread::sleep(std::time::Duration::from_secs(24 * 60 * 60));
+    });
+}
@@ -1,9 +1,12 @@
-use pulldown_cmark::{html, Parser};
+use crate::markdown::{to_html, Context, TaskForm};
 use rocket_contrib::templates::tera::{Error, ErrorKind};
 use serde_json::Value;
 use std::collections::HashMap;
 
 pub fn register(tera: &mut rocket_contrib::templates::tera::Tera) {
+    // Every template renders html, so escape all values. Rocket loads the templates with their path,
+    // the publish command without, so match every name instead of the `.tera` extension.
+    tera.autoescape_on(vec![""]);
     tera.register_filter("markdown", markdown_filter);
     tera.register_function("current_version", Box::new(current_version));
     tera.register_function("min", Box::new(min));
@@ -15,18 +18,45 @@ fn fail<S: Into<String>, T>(f: S) -> Result<T, Error> {
     Err(Error::from_kind(ErrorKind::Msg(f.into())))
 }
 
+/// Render markdown. The optional arguments are `note`, the seo name of the note that is rendered,
+/// `embeds`, the notes that it can embed, `attachments`, the url that its attachments are found under,
+/// `note_urls`, where the links to other notes point to instead, and `csrf_token`, which makes the checkboxes of task lists forms that toggle the task in the note.
 fn markdown_filter<S: std::hash::BuildHasher>(
     v: Value,
-    _data: HashMap<String, Value, S>,
+    data: HashMap<String, Value, S>,
 ) -> Result<Value, Error> {
-    if let Some(s) = v.as_str() {
-        let parser = Parser::new(s);
-        let mut html_buf = String::new();
-        html::push_html(&mut html_buf, parser);
-        Ok(Value::String(html_buf))
-    } else {
-        fail("Value is not a valid string")
-    }
+    let s = match v.as_str() {
+        Some(s) => s,
+        None => return fail("Value is not a valid string"),
+    };
+    let note = data.get("note").and_then(Value::as_str);
+    let action = note.map(|seo_name| format!("/toggle_task/{}", seo_name));
+    let tasks = match (&action, data.get("csrf_token").and_then(Value::as_str)) {
+        (Some(action), Some(csrf_token)) => Some(TaskForm { action, csrf_token }),
+        _ => None,
+    };
+    let embeds = match data.get("embeds") {
+        Some(embeds) => match serde_json::from_value(embeds.clone()) {
+            Ok(embeds) => embeds,
+            Err(e) => return fail(format!("Invalid embeds: {}", e)),
+        },
+        None => HashMap::new(),
+    };
+    let note_urls = match data.get("note_urls") {
+        Some(note_urls) => match serde_json::from_value(note_urls.clone()) {
+            Ok(note_urls) => note_urls,
+            Err(e) => return fail(format!("Invalid note urls: {}", e)),
+        },
+        None => HashMap::new(),
+    };
+    let context = Context {
+        note,
+        tasks,
+        embeds,
+        attachments: data.get("attachments").and_then(Value::as_str),
+        note_urls,
+    };
+    Ok(Value::String(to_html(s, &context)))
 }
 
 fn min<S: std::hash::BuildHasher>(map: HashMap<String, Value, S>) -> Result<Value, Error> {
@@ -0,0 +1,47 @@
+//! Helpers for tests that need a database.
+//!
+//! These tests are `#[ignore]`d, because they need a database with all migrations applied in `DATABASE_URL`
+//! (in the environment or in `.env`). Run them with `cargo test -- --ignored`.
+
+use crate::models::note_acl::Accessor;
+use crate::models::user::DatabaseUser;
+use crate::models::workspace::{Workspace as DatabaseWorkspace, WorkspaceMember};
+use crate::note::Note;
+use crate::user::User;
+use crate::workspace::Workspace;
+use diesel::{Connection, PgConnection};
+use uuid::Uuid;
+
+pub fn connect() -> PgConnection {
+    dotenv::dotenv().ok();
+    let url = std::env::var("DATABASE_URL")
+        .expect("The database tests need DATABASE_URL, in the environment or in .env");
+    PgConnection::establish(&url).expect("Could not connect to DATABASE_URL")
+}
+
+/// A new user that owns a workspace of their own
+pub struct Fixture {
+    pub user: User,
+    pub workspace: Workspace,
+}
+
+impl Fixture {
+    pub fn create(conn: &PgConnection) -> Fixture {
+        let name = format!("test-{}", Uuid::new_v4());
+        let user: User = DatabaseUser::create(conn, &name, "").unwrap().into();
+        let workspace = Workspace::create(conn, &name, user.id).unwrap();
+        Fixture { user, workspace }
+    }
+
+    pub fn accessor(&self) -> Accessor {
+        self.workspace.accessor()
+    }
+
+    /// Remove the user, the workspace and its notes, for tests that commit them
+    pub fn remove(&self, conn: &PgConnection) {
+        Note::delete_by_user_or_workspaces(conn, self.user.id, &[self.workspace.id]).unwrap();
+        WorkspaceMember::delete_by_user(conn, self.user.id).unwrap();
+        DatabaseWorkspace::delete_all(conn, &[self.workspace.id]).unwrap();
+        DatabaseUser::delete(conn, self.user.id).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+use crate::models::auth_attempt::AuthAttempt;
+use chrono::{DateTime, Duration, Utc};
+use failure::bail;
+
+const FAILED_LOGIN: &str = "failed_login";
+const REGISTRATION: &str = "registration";
+/// Failed logins of one user that are allowed before they have to wait between attempts
+const FREE_ATTEMPTS: i64 = 3;
+/// Many users can share an address, so the limits for an address are this many times the limits for a single user
+const IP_FACTOR: i64 = 5;
+
+/// Slows down and locks out clients that guess passwords, and limits how many accounts one address can register.
+///
+/// Failed logins are counted per user name and per address. After a few of them every next attempt has to wait
+/// twice as long as the one before, until the lockout limit is reached and attempts are refused for the lockout duration.
+/// Failures are forgotten after a day, or when the user logs in. The limits are read from `Rocket.toml`, see `from_config`.
+pub struct Throttle {
+    lockout_attempts: i64,
+    lockout: Duration,
+    registrations_per_ip_per_day: i64,
+}
+
+impl Throttle {
+    pub const DEFAULT_LOCKOUT_ATTEMPTS: i64 = 10;
+    pub const DEFAULT_LOCKOUT_MINUTES: i64 = 15;
+    pub const DEFAULT_REGISTRATIONS_PER_IP_PER_DAY: i64 = 5;
+
+    /// Load the limits from the `login_lockout_attempts`, `login_lockout_minutes` and `registrations_per_ip_per_day` settings,
+    /// falling back to the defaults for the settings that are missing
+    pub fn from_config(config: &rocket::Config) -> Result<Throttle, failure::Error> {
+        let setting = |name: &str, default: i64| -> Result<i64, failure::Error> {
+            match config.get_int(name) {
+                Ok(value) if value >= 0 => Ok(value),
+                Ok(value) => bail!("Invalid setting {}: {} is negative", name, value),
+                Err(rocket::config::ConfigError::Missing(_)) => Ok(default),
+                Err(e) => bail!("Invalid setting {}: {}", name, e),
+            }
+        };
+        Ok(Throttle {
+            lockout_attempts: setting(
+                "login_lockout_attempts",
+                Throttle::DEFAULT_LOCKOUT_ATTEMPTS,
+            )?,
+            lockout: Duration::minutes(setting(
+                "login_lockout_minutes",
+                Throttle::DEFAULT_LOCKOUT_MINUTES,
+            )?),
+            registrations_per_ip_per_day: setting(
+                "registrations_per_ip_per_day",
+                Throttle::DEFAULT_REGISTRATIONS_PER_IP_PER_DAY,
+            )?,
+        })
+    }
+
+    /// How long to wait after the last of `failures` failed logins, with the limits multiplied by `factor`
+    fn delay(&self, failures: i64, factor: i64) -> Duration {
+        let free = FREE_ATTEMPTS * factor;
+        if failures >= self.lockout_attempts * factor {
+            self.lockout
+        } else if failures < free {
+            Duration::zero()
+        } else {
+            Duration::seconds(1 << (failures - free).min(30)).min(self.lockout)
+        }
+    }
+
+    fn wait(
+        &self,
+        conn: &diesel::PgConnection,
+        now: DateTime<Utc>,
+        ip: Option<&str>,
+        user_name: Option<&str>,
+        factor: i64,
+    ) -> Result<Duration, failure::Error> {
+        let since = now - Duration::days(1);
+        Ok(
+            match AuthAttempt::count_since(conn, FAILED_LOGIN, ip, user_name, since)? {
+                (failures, Some(last)) => last + self.delay(failures, factor) - now,
+                (_, None) => Duration::zero(),
+            },
+        )
+    }
+
+    /// Fail if there were too many failed logins for `user_name` or from `ip` to try again yet
+    pub fn check_login(
+        &self,
+        conn: &diesel::PgConnection,
+        now: DateTime<Utc>,
+        ip: &str,
+        user_name: &str,
+    ) -> Result<(), failure::Error> {
+        let wait = self
+            .wait(conn, now, None, Some(user_name), 1)?
+            .max(self.wait(conn, now, Some(ip), None, IP_FACTOR)?);
+        if wait > Duration::zero() {
+            bail!("Too many failed logins, try again in {}", describe(wait));
+        }
+        Ok(())
+    }
+
+    pub fn login_failed(
+        &self,
+        conn: &diesel::PgConnection,
+        now: DateTime<Utc>,
+        ip: &str,
+        user_name: &str,
+    ) -> Result<(), failure::Error> {
+        AuthAttempt::delete_before(conn, now - Duration::days(1))?;
+        AuthAttempt::create(conn, now, FAILED_LOGIN, ip, Some(user_name))
+    }
+
+    /// Forget the failed logins of the user, so they are not held against them later
+    pub fn login_succeeded(
+        &self,
+        conn: &diesel::PgConnection,
+        user_name: &str,
+    ) -> Result<(), failure::Error> {
+        AuthAttempt::delete_by_user_name(conn, FAILED_LOGIN, user_name)
+    }
+
+    /// Fail if `ip` registered as many accounts as it is allowed to in the last day
+    pub fn check_registration(
+        &self,
+        conn: &diesel::PgConnection,
+        now: DateTime<Utc>,
+        ip: &str,
+    ) -> Result<(), failure::Error> {
+        let since = now - Duration::days(1);
+        let (registrations, _) =
+            AuthAttempt::count_since(conn, REGISTRATION, Some(ip), None, since)?;
+        if registrations >= self.registrations_per_ip_per_day {
+            bail!("Too many accounts were registered from your address, try again later");
+        }
+        Ok(())
+    }
+
+    pub fn registered(
+        &self,
+        conn: &diesel::PgConnection,
+        now: DateTime<Utc>,
+        ip: &str,
+    ) -> Result<(), failure::Error> {
+        AuthAttempt::delete_before(conn, now - Duration::days(1))?;
+        AuthAttempt::create(conn, now, REGISTRATION, ip, None)
+    }
+}
+
+fn describe(wait: Duration) -> String {
+    let seconds = wait.num_seconds().max(1);
+    let (amount, unit) = if seconds < 60 {
+        (seconds, "second")
+    } else {
+        ((seconds + 59) / 60, "minute")
+    };
+    if amount == 1 {
+        format!("1 {}", unit)
+    } else {
+        format!("{} {}s", amount, unit)
+    }
+}
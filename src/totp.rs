@@ -0,0 +1,256 @@
+use base32::Alphabet;
+use chrono::{DateTime, Utc};
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha1::Sha1;
+use crypto::sha2::Sha256;
+use failure::format_err;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand::distributions::Alphanumeric;
+use rand::{Rng, RngCore};
+
+/// The name that authenticator apps show next to the codes
+const ISSUER: &str = "Mindmap";
+/// The length of generated secrets, in bytes
+const SECRET_LENGTH: usize = 20;
+/// The amount of seconds a code is valid for
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// The amount of steps a code may be off, to allow for clocks that are not in sync
+const ALLOWED_DRIFT: i64 = 1;
+/// The amount of recovery codes a user gets when enabling two-factor authentication
+pub const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+const BASE32: Alphabet = Alphabet::RFC4648 { padding: false };
+
+/// Where the current time for two-factor codes comes from.
+///
+/// The server uses the system clock, a fixed clock makes it possible to check codes without depending on the time.
+pub enum Clock {
+    System,
+    Fixed(DateTime<Utc>),
+}
+
+impl Clock {
+    pub fn now(&self) -> DateTime<Utc> {
+        match self {
+            Clock::System => Utc::now(),
+            Clock::Fixed(time) => *time,
+        }
+    }
+}
+
+/// Generate a new random secret, base32 encoded like authenticator apps expect
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LENGTH];
+    rand::thread_rng().fill_bytes(&mut secret);
+    base32::encode(BASE32, &secret)
+}
+
+/// The time step that contains `time`
+fn step_at(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(STEP_SECONDS)
+}
+
+/// The code for the given time step, as described in RFC 4226 and RFC 6238
+fn code_for_step(key: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::new(Sha1::new(), key);
+    mac.input(&step.to_be_bytes());
+    let hash = mac.result();
+    let hash = hash.code();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    value % 10u32.pow(DIGITS)
+}
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>, failure::Error> {
+    base32::decode(BASE32, secret).ok_or_else(|| format_err!("Invalid two-factor secret"))
+}
+
+/// The code that is valid at the given time
+pub fn code_at(secret: &str, time: DateTime<Utc>) -> Result<String, failure::Error> {
+    let key = decode_secret(secret)?;
+    Ok(format!(
+        "{:0width$}",
+        code_for_step(&key, step_at(time)),
+        width = DIGITS as usize
+    ))
+}
+
+/// Check a code that was entered at `now`. Returns the time step the code is for if it is valid.
+///
+/// Codes of the steps right before and after the current one are accepted as well.
+pub fn verify(secret: &str, code: &str, now: DateTime<Utc>) -> Result<Option<i64>, failure::Error> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize {
+        return Ok(None);
+    }
+    let code: u32 = match code.parse() {
+        Ok(code) => code,
+        Err(_) => return Ok(None),
+    };
+    let key = decode_secret(secret)?;
+    let current = step_at(now);
+    Ok(((current - ALLOWED_DRIFT)..=(current + ALLOWED_DRIFT))
+        .find(|step| code_for_step(&key, *step) == code))
+}
+
+/// The `otpauth://` uri that authenticator apps can be set up with
+pub fn provisioning_uri(secret: &str, user_name: &str) -> String {
+    let label: String = url_encode(&format!("{}:{}", ISSUER, user_name));
+    format!(
+        "otpauth://totp/{}?secret={}&issuer={}&digits={}&period={}",
+        label, secret, ISSUER, DIGITS, STEP_SECONDS
+    )
+}
+
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Render the uri as a QR code, as an svg image that can be put inside html
+pub fn qr_code_svg(uri: &str) -> Result<String, failure::Error> {
+    let code = QrCode::new(uri).map_err(|e| format_err!("Could not create QR code: {:?}", e))?;
+    let svg = code.render::<svg::Color>().min_dimensions(200, 200).build();
+    // Leave out the xml declaration in front of the svg element
+    Ok(match svg.find("<svg") {
+        Some(start) => svg[start..].to_owned(),
+        None => svg,
+    })
+}
+
+/// Generate a new set of recovery codes, formatted like `abcde-12345`
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (&mut rng)
+                .sample_iter(&Alphanumeric)
+                .take(RECOVERY_CODE_LENGTH)
+                .collect::<String>()
+                .to_lowercase();
+            let (left, right) = code.split_at(RECOVERY_CODE_LENGTH / 2);
+            format!("{}-{}", left, right)
+        })
+        .collect()
+}
+
+/// The hash that a recovery code is stored as. The code is normalized first, so dashes, spaces and case don't matter.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    let mut hasher = Sha256::new();
+    hasher.input_str(&normalized);
+    hasher.result_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::user::DatabaseUser;
+    use crate::test_db::{self, Fixture};
+    use chrono::TimeZone;
+    use diesel::Connection;
+
+    /// The secret of the test vectors in RFC 6238, "12345678901234567890", base32 encoded
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn at(timestamp: i64) -> Clock {
+        Clock::Fixed(Utc.timestamp(timestamp, 0))
+    }
+
+    #[test]
+    fn codes_match_the_rfc_test_vectors() {
+        // The SHA-1 vectors of RFC 6238, which have 8 digits, of which the last 6 are the code
+        let vectors = [
+            (59, "287082"),
+            (1_111_111_109, "081804"),
+            (1_111_111_111, "050471"),
+            (1_234_567_890, "005924"),
+            (2_000_000_000, "279037"),
+            (20_000_000_000, "353130"),
+        ];
+        for (timestamp, code) in &vectors {
+            assert_eq!(code_at(SECRET, at(*timestamp).now()).unwrap(), *code);
+        }
+    }
+
+    #[test]
+    fn codes_may_be_one_step_off() {
+        let now = at(1_234_567_890).now();
+        let step = step_at(now);
+        for drift in -ALLOWED_DRIFT..=ALLOWED_DRIFT {
+            let code = code_at(
+                SECRET,
+                now + chrono::Duration::seconds(drift * STEP_SECONDS),
+            )
+            .unwrap();
+            assert_eq!(verify(SECRET, &code, now).unwrap(), Some(step + drift));
+        }
+        let spaced = format!(
+            "{} {}",
+            &code_at(SECRET, now).unwrap()[..3],
+            &code_at(SECRET, now).unwrap()[3..]
+        );
+        assert_eq!(verify(SECRET, &spaced, now).unwrap(), Some(step));
+    }
+
+    #[test]
+    fn codes_outside_of_the_window_are_rejected() {
+        let now = at(1_234_567_890).now();
+        for steps in &[-ALLOWED_DRIFT - 1, ALLOWED_DRIFT + 1] {
+            let code = code_at(
+                SECRET,
+                now + chrono::Duration::seconds(steps * STEP_SECONDS),
+            )
+            .unwrap();
+            assert_eq!(verify(SECRET, &code, now).unwrap(), None);
+        }
+        assert_eq!(verify(SECRET, "12345", now).unwrap(), None);
+        assert_eq!(verify(SECRET, "abcdef", now).unwrap(), None);
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_dashes_and_whitespace() {
+        let hash = hash_recovery_code("abcde-12345");
+        assert_eq!(hash_recovery_code("ABCDE-12345"), hash);
+        assert_eq!(hash_recovery_code("abcde12345"), hash);
+        assert_eq!(hash_recovery_code(" abcde 12345\n"), hash);
+        assert_ne!(hash_recovery_code("abcde-12346"), hash);
+    }
+
+    #[test]
+    #[ignore]
+    fn a_step_can_only_be_used_once() {
+        let conn = test_db::connect();
+        conn.begin_test_transaction().unwrap();
+        let fixture = Fixture::create(&conn);
+        let id = fixture.user.id;
+        let step = step_at(at(1_234_567_890).now());
+
+        // The step of the code that enabled two-factor authentication can not be used to log in
+        DatabaseUser::set_totp(&conn, id, Some(SECRET), Some(step)).unwrap();
+        assert!(!DatabaseUser::use_totp_step(&conn, id, step).unwrap());
+        assert!(DatabaseUser::use_totp_step(&conn, id, step + 1).unwrap());
+        assert!(!DatabaseUser::use_totp_step(&conn, id, step + 1).unwrap());
+        assert!(!DatabaseUser::use_totp_step(&conn, id, step).unwrap());
+    }
+}
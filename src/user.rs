@@ -1,8 +1,24 @@
+use crate::attachment::Attachments;
+use crate::audit::AuditEvent;
+use crate::models::audit_log::AuditLog;
+use crate::models::journal::JournalEntry;
+use crate::models::note::{transaction, NoteHistory, Revision};
+use crate::models::note_acl::NoteAcl;
+use crate::models::note_share::NoteShare;
 use crate::models::user::DatabaseUser;
+use crate::models::user_preference::UserPreference;
+use crate::models::user_recovery_code::RecoveryCode;
 use crate::models::user_token::UserToken;
+use crate::models::walk::Walk;
+use crate::models::workspace::{Workspace as DatabaseWorkspace, WorkspaceMember};
+use crate::note::Note;
+use crate::password::PasswordHasher;
+use crate::throttle::Throttle;
+use crate::totp::{self, Clock};
+use crate::workspace::{Role, Workspace};
 use crate::MindmapDB;
-use crypto::pbkdf2::pbkdf2_check;
-use crypto::pbkdf2::pbkdf2_simple;
+use chrono::{NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use failure::{bail, format_err};
 use rocket::http::Status;
 use rocket::request::FromRequest;
@@ -13,6 +29,14 @@ use uuid::Uuid;
 pub struct User {
     pub id: Uuid,
     pub name: String,
+    /// The session token the user is logged in with, if this user is the one making the request
+    pub token_id: Option<Uuid>,
+    /// Administrators can see the audit log of every user
+    pub is_admin: bool,
+    /// Whether the user needs a code from an authenticator app to log in
+    pub two_factor: bool,
+    /// The timezone that dates are shown in, UTC if the user never chose one
+    pub timezone: Tz,
 }
 
 impl From<DatabaseUser> for User {
@@ -20,10 +44,21 @@ impl From<DatabaseUser> for User {
         User {
             id: u.id,
             name: u.name,
+            token_id: None,
+            is_admin: u.is_admin,
+            two_factor: u.totp_secret.is_some(),
+            timezone: Tz::from_str(&u.timezone).unwrap_or(Tz::UTC),
         }
     }
 }
 
+/// The outcome of a login with a correct password
+pub enum Login {
+    LoggedIn(User, UserToken),
+    /// The user with this id has two-factor authentication enabled, and has to enter a code before they are logged in
+    SecondFactorRequired(Uuid),
+}
+
 impl<'a, 'b> FromRequest<'a, 'b> for User {
     type Error = failure::Error;
 
@@ -60,7 +95,10 @@ impl<'a, 'b> FromRequest<'a, 'b> for User {
         }
 
         match DatabaseUser::load_by_id(&connection, uid) {
-            Ok(Some(u)) => Outcome::Success(u.into()),
+            Ok(Some(u)) => Outcome::Success(User {
+                token_id: Some(token.id),
+                ..u.into()
+            }),
             Ok(None) => Outcome::Forward(()),
             Err(e) => Outcome::Failure((Status::InternalServerError, e)),
         }
@@ -70,43 +108,338 @@ impl<'a, 'b> FromRequest<'a, 'b> for User {
 impl User {
     pub fn attempt_login(
         conn: &MindmapDB,
+        hasher: &PasswordHasher,
+        throttle: &Throttle,
+        clock: &Clock,
         name: &str,
         password: &str,
         ip: &str,
-    ) -> Result<(User, UserToken), failure::Error> {
-        let user = match DatabaseUser::load_by_name(conn, name)? {
+    ) -> Result<Login, failure::Error> {
+        let now = clock.now();
+        let user = DatabaseUser::load_by_name(conn, name)?;
+        if let Err(e) = throttle.check_login(conn, now, ip, name) {
+            let details = format!("User {:?}", name);
+            AuditEvent::LoginThrottled.record(
+                conn,
+                user.as_ref().map(|u| u.id),
+                Some(ip),
+                Some(&details),
+            )?;
+            return Err(e);
+        }
+        let user = match user {
             Some(u) => u,
             None => {
-                // TODO: Validate password anyway to prevent timing attacks
+                // Verify the password anyway, so the time this takes does not tell whether the user exists
+                hasher.dummy_verify(password);
+                throttle.login_failed(conn, now, ip, name)?;
+                let details = format!("Unknown user {:?}", name);
+                AuditEvent::LoginFailed.record(conn, None, Some(ip), Some(&details))?;
                 bail!("Login credentials are invalid");
             }
         };
-        let result = pbkdf2_check(password, &user.password)
-            .map_err(|e| format_err!("Could not validate password: {}", e))?;
-
-        if !result {
+        let verification = hasher.verify(password, &user.password)?;
+        if !verification.valid {
+            throttle.login_failed(conn, now, ip, name)?;
+            AuditEvent::LoginFailed.record(conn, Some(user.id), Some(ip), None)?;
             bail!("Login credentials are invalid");
         }
+        if verification.needs_rehash {
+            DatabaseUser::set_password(conn, user.id, &hasher.hash(password)?)?;
+        }
+        if user.totp_secret.is_some() {
+            return Ok(Login::SecondFactorRequired(user.id));
+        }
+        let (user, token) = User::start_session(conn, throttle, user, ip, None)?;
+        Ok(Login::LoggedIn(user, token))
+    }
+
+    /// Finish the login of a user with two-factor authentication, with a code from their authenticator app or one of their recovery codes
+    pub fn attempt_second_factor(
+        conn: &MindmapDB,
+        throttle: &Throttle,
+        clock: &Clock,
+        user_id: Uuid,
+        code: &str,
+        ip: &str,
+    ) -> Result<(User, UserToken), failure::Error> {
+        let now = clock.now();
+        let user = DatabaseUser::load_by_id(conn, user_id)?
+            .ok_or_else(|| format_err!("User not found"))?;
+        if let Err(e) = throttle.check_login(conn, now, ip, &user.name) {
+            AuditEvent::LoginThrottled.record(conn, Some(user.id), Some(ip), None)?;
+            return Err(e);
+        }
+        let secret = match &user.totp_secret {
+            Some(secret) => secret,
+            None => bail!("Two-factor authentication is not enabled"),
+        };
+        let valid_step = match totp::verify(secret, code, now)? {
+            Some(step) => DatabaseUser::use_totp_step(conn, user.id, step)?,
+            None => false,
+        };
+        if valid_step {
+            return User::start_session(conn, throttle, user, ip, None);
+        }
+        if RecoveryCode::use_code(conn, user.id, &totp::hash_recovery_code(code))? {
+            return User::start_session(conn, throttle, user, ip, Some("With a recovery code"));
+        }
+        throttle.login_failed(conn, now, ip, &user.name)?;
+        AuditEvent::LoginFailed.record(
+            conn,
+            Some(user.id),
+            Some(ip),
+            Some("Invalid two-factor code"),
+        )?;
+        bail!("The code is invalid");
+    }
+
+    /// Log the user in by creating a new session token
+    fn start_session(
+        conn: &MindmapDB,
+        throttle: &Throttle,
+        user: DatabaseUser,
+        ip: &str,
+        details: Option<&str>,
+    ) -> Result<(User, UserToken), failure::Error> {
+        throttle.login_succeeded(conn, &user.name)?;
         let token = UserToken::create(conn, user.id, ip)?;
-        Ok((user.into(), token))
+        AuditEvent::LoginSucceeded.record(conn, Some(user.id), Some(ip), details)?;
+        AuditEvent::SessionCreated.record(
+            conn,
+            Some(user.id),
+            Some(ip),
+            Some(&token.id.to_string()),
+        )?;
+        let user = User {
+            token_id: Some(token.id),
+            ..user.into()
+        };
+        Ok((user, token))
     }
 
     pub fn attempt_register(
         conn: &MindmapDB,
+        hasher: &PasswordHasher,
+        throttle: &Throttle,
+        clock: &Clock,
         name: &str,
         password: &str,
         ip: &str,
     ) -> Result<(User, UserToken), failure::Error> {
+        throttle.check_registration(conn, clock.now(), ip)?;
         if DatabaseUser::load_by_name(conn, name)?.is_some() {
             bail!("Username already in use");
         }
 
-        let password = pbkdf2_simple(password, 10_000)?;
+        let password = hasher.hash(password)?;
 
-        let user = DatabaseUser::create(conn, name, &password)?;
-        let token = UserToken::create(conn, user.id, ip)?;
+        let (user, token) = transaction(conn, || {
+            let user = DatabaseUser::create(conn, name, &password)?;
+            Workspace::create(conn, name, user.id)?;
+            let token = UserToken::create(conn, user.id, ip)?;
+            AuditEvent::Registered.record(conn, Some(user.id), Some(ip), None)?;
+            throttle.registered(conn, clock.now(), ip)?;
+            AuditEvent::SessionCreated.record(
+                conn,
+                Some(user.id),
+                Some(ip),
+                Some(&token.id.to_string()),
+            )?;
+            Ok((user, token))
+        })?;
+
+        let user = User {
+            token_id: Some(token.id),
+            ..user.into()
+        };
+        Ok((user, token))
+    }
+
+    /// The revision of a note that this user makes with the given edit summary
+    pub fn revision<'a>(&self, summary: Option<&'a str>) -> Revision<'a> {
+        Revision {
+            user_id: self.id,
+            token_id: self.token_id,
+            summary,
+        }
+    }
+
+    /// The current date and time in the timezone of this user
+    pub fn local_now(&self) -> NaiveDateTime {
+        Utc::now().with_timezone(&self.timezone).naive_local()
+    }
+
+    /// Change the timezone of this user to the one with the given name, like "Europe/Amsterdam"
+    pub fn set_timezone(
+        &mut self,
+        conn: &diesel::PgConnection,
+        name: &str,
+    ) -> Result<(), failure::Error> {
+        let timezone = match Tz::from_str(name) {
+            Ok(timezone) => timezone,
+            Err(_) => bail!("Unknown timezone {:?}", name),
+        };
+        DatabaseUser::set_timezone(conn, self.id, timezone.name())?;
+        self.timezone = timezone;
+        Ok(())
+    }
+
+    /// End the session this user is logged in with, so its token can not be used anymore
+    pub fn logout(&self, conn: &MindmapDB, ip: &str) -> Result<(), failure::Error> {
+        if let Some(token_id) = self.token_id {
+            UserToken::deactivate(conn, token_id)?;
+            AuditEvent::SessionRevoked.record(
+                conn,
+                Some(self.id),
+                Some(ip),
+                Some(&token_id.to_string()),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Fail if `password` is not the password of this user
+    fn verify_password(
+        &self,
+        conn: &MindmapDB,
+        hasher: &PasswordHasher,
+        password: &str,
+    ) -> Result<(), failure::Error> {
+        let user = DatabaseUser::load_by_id(conn, self.id)?
+            .ok_or_else(|| format_err!("User not found"))?;
+        if !hasher.verify(password, &user.password)?.valid {
+            bail!("Your current password is incorrect");
+        }
+        Ok(())
+    }
+
+    /// Change the password of this user, after checking the old one.
+    ///
+    /// All sessions except the one this user is logged in with are ended.
+    pub fn change_password(
+        &self,
+        conn: &MindmapDB,
+        hasher: &PasswordHasher,
+        old_password: &str,
+        new_password: &str,
+        ip: &str,
+    ) -> Result<(), failure::Error> {
+        self.verify_password(conn, hasher, old_password)?;
+        let password = hasher.hash(new_password)?;
+        transaction(conn, || {
+            DatabaseUser::set_password(conn, self.id, &password)?;
+            let revoked = UserToken::deactivate_all_by_user(conn, self.id, self.token_id)?;
+            let details = format!("{} other sessions ended", revoked);
+            AuditEvent::PasswordChanged.record(conn, Some(self.id), Some(ip), Some(&details))
+        })
+    }
+
+    /// Delete this user, after checking their password.
+    ///
+    /// This deletes the notes the user created and the workspaces nobody else is a member of, with everything in them.
+    /// The revisions the user made to notes of others are kept, but are no longer attributed to them.
+    /// Fails if the user is the only owner of a workspace that has other members.
+    pub fn delete(
+        self,
+        conn: &MindmapDB,
+        hasher: &PasswordHasher,
+        attachments: &Attachments,
+        password: &str,
+        ip: &str,
+    ) -> Result<(), failure::Error> {
+        self.verify_password(conn, hasher, password)?;
+        let deleted = transaction(conn, || {
+            let mut abandoned = Vec::new();
+            for workspace in Workspace::load_all_by_member(conn, self.id)? {
+                let members = workspace.load_members(conn)?;
+                if members.len() == 1 {
+                    abandoned.push(workspace.id);
+                } else if workspace.role == Role::Owner
+                    && !members
+                        .iter()
+                        .any(|m| m.user_id != self.id && m.role == Role::Owner)
+                {
+                    bail!(
+                        "Make someone else an owner of {:?} before deleting your account",
+                        workspace.name
+                    );
+                }
+            }
+
+            let deleted = Note::delete_by_user_or_workspaces(conn, self.id, &abandoned)?;
+            Walk::delete_by_user_or_workspaces(conn, self.id, &abandoned)?;
+            JournalEntry::delete_by_user(conn, self.id)?;
+            NoteShare::delete_by_user(conn, self.id)?;
+            NoteAcl::delete_by_user(conn, self.id)?;
+            NoteHistory::unattribute_user(conn, self.id)?;
+            WorkspaceMember::delete_by_user(conn, self.id)?;
+            DatabaseWorkspace::delete_all(conn, &abandoned)?;
+            UserPreference::delete_by_user(conn, self.id)?;
+            RecoveryCode::delete_by_user(conn, self.id)?;
+            UserToken::delete_by_user(conn, self.id)?;
+            AuditLog::detach_user(conn, self.id)?;
+            DatabaseUser::delete(conn, self.id)?;
+
+            let details = format!("User {:?}", self.name);
+            AuditEvent::AccountDeleted.record(conn, None, Some(ip), Some(&details))?;
+            Ok(deleted)
+        })?;
+        attachments.remove_files(&deleted);
+        Ok(())
+    }
+
+    /// Enable two-factor authentication with the secret the user added to their authenticator app.
+    ///
+    /// The code that app shows has to be entered, to make sure it was set up correctly, as well as the password of the user.
+    /// Returns the new recovery codes.
+    #[allow(clippy::too_many_arguments)]
+    pub fn enable_two_factor(
+        &self,
+        conn: &MindmapDB,
+        hasher: &PasswordHasher,
+        clock: &Clock,
+        secret: &str,
+        password: &str,
+        code: &str,
+        ip: &str,
+    ) -> Result<Vec<String>, failure::Error> {
+        self.verify_password(conn, hasher, password)?;
+        let step = match totp::verify(secret, code, clock.now())? {
+            Some(step) => step,
+            None => bail!("The code is invalid, make sure the time on your device is correct"),
+        };
+        let codes = totp::generate_recovery_codes();
+        let hashes: Vec<String> = codes.iter().map(|c| totp::hash_recovery_code(c)).collect();
+        transaction(conn, || {
+            DatabaseUser::set_totp(conn, self.id, Some(secret), Some(step))?;
+            RecoveryCode::replace_all(conn, self.id, &hashes)?;
+            AuditEvent::TwoFactorEnabled.record(conn, Some(self.id), Some(ip), None)
+        })?;
+        Ok(codes)
+    }
+
+    pub fn disable_two_factor(
+        &self,
+        conn: &MindmapDB,
+        hasher: &PasswordHasher,
+        password: &str,
+        ip: &str,
+    ) -> Result<(), failure::Error> {
+        self.verify_password(conn, hasher, password)?;
+        transaction(conn, || {
+            DatabaseUser::set_totp(conn, self.id, None, None)?;
+            RecoveryCode::delete_by_user(conn, self.id)?;
+            AuditEvent::TwoFactorDisabled.record(conn, Some(self.id), Some(ip), None)
+        })
+    }
+
+    pub fn count_recovery_codes(&self, conn: &MindmapDB) -> Result<i64, failure::Error> {
+        RecoveryCode::count_by_user(conn, self.id)
+    }
 
-        Ok((user.into(), token))
+    pub fn load_by_name(conn: &MindmapDB, name: &str) -> Result<Option<User>, failure::Error> {
+        Ok(DatabaseUser::load_by_name(conn, name)?.map(Into::into))
     }
 
     pub fn load_by_id(conn: &MindmapDB, id: Uuid) -> Result<User, failure::Error> {
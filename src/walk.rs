@@ -0,0 +1,121 @@
+use crate::models::note::Note as DatabaseNote;
+use crate::models::note_acl::Accessor;
+use crate::models::note_link::NoteLink as DatabaseNoteLink;
+use crate::models::walk::Walk as DatabaseWalk;
+use crate::note::Note;
+use chrono::{DateTime, Utc};
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// A random walk over the links between notes, which can be replayed step by step
+#[derive(Serialize)]
+pub struct Walk {
+    pub id: Uuid,
+    pub created: DateTime<Utc>,
+    pub steps: Vec<Note>,
+}
+
+impl Walk {
+    /// Start a new walk at a random note of the workspace, which counts as a view of that note.
+    /// Returns `None` if the workspace has no notes.
+    pub fn start(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<Option<Walk>, failure::Error> {
+        let note = match DatabaseNote::load_random(conn, accessor)? {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+        let walk = DatabaseWalk::create(conn, accessor.user_id, accessor.workspace_id)?;
+        DatabaseWalk::add_step(conn, walk.id, note.id)?;
+        let mut note: Note = note.into();
+        note.increase_view_count(conn)?;
+        Ok(Some(Walk {
+            id: walk.id,
+            created: walk.created,
+            steps: vec![note],
+        }))
+    }
+
+    pub fn load(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        accessor: &Accessor,
+    ) -> Result<Option<Walk>, failure::Error> {
+        match DatabaseWalk::load_by_id(conn, id, accessor.user_id, accessor.workspace_id)? {
+            Some(walk) => Ok(Some(Walk::load_steps(conn, walk, accessor)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn load_recent(
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+        count: u64,
+    ) -> Result<Vec<Walk>, failure::Error> {
+        DatabaseWalk::load_recent(conn, accessor.user_id, accessor.workspace_id, count as i64)?
+            .into_iter()
+            .map(|walk| Walk::load_steps(conn, walk, accessor))
+            .collect()
+    }
+
+    fn load_steps(
+        conn: &diesel::PgConnection,
+        walk: DatabaseWalk,
+        accessor: &Accessor,
+    ) -> Result<Walk, failure::Error> {
+        let steps = DatabaseWalk::load_notes(conn, walk.id, accessor)?;
+        Ok(Walk {
+            id: walk.id,
+            created: walk.created,
+            steps: steps.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    /// Follow a random link from the last note of this walk.
+    ///
+    /// Links that are clicked often are more likely to be followed, as are notes that are rarely viewed.
+    /// Notes that were already visited in this walk are only revisited if there is no other option.
+    /// Private notes that the accessor cannot read are never visited.
+    /// The next note is viewed once, when it is stepped to; replaying the walk does not count as a view.
+    /// Returns `false` if the last note has no links.
+    pub fn step(
+        &mut self,
+        conn: &diesel::PgConnection,
+        accessor: &Accessor,
+    ) -> Result<bool, failure::Error> {
+        let current = match self.steps.last() {
+            Some(n) => n.id,
+            None => return Ok(false),
+        };
+        let seen: HashSet<Uuid> = self.steps.iter().map(|n| n.id).collect();
+        let links = DatabaseNoteLink::load_by_note(conn, current, accessor)?
+            .into_iter()
+            .filter_map(|l| {
+                let click_count = l.click_count;
+                l.other.map(|other| (other, click_count))
+            });
+        let (unvisited, visited): (Vec<_>, Vec<_>) =
+            links.partition(|(other, _)| !seen.contains(&other.id));
+        let candidates = if unvisited.is_empty() {
+            visited
+        } else {
+            unvisited
+        };
+
+        let next = match candidates.choose_weighted(&mut rand::thread_rng(), |(other, clicks)| {
+            f64::from(clicks + 1) / f64::from(other.view_count + 1)
+        }) {
+            Ok((other, _)) => other.id,
+            Err(_) => return Ok(false),
+        };
+        DatabaseWalk::add_step(conn, self.id, next)?;
+        if let Some((other, _)) = candidates.into_iter().find(|(other, _)| other.id == next) {
+            let mut note: Note = other.into();
+            note.increase_view_count(conn)?;
+            self.steps.push(note);
+        }
+        Ok(true)
+    }
+}
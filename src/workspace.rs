@@ -0,0 +1,338 @@
+use crate::journal;
+use crate::models::note::transaction;
+use crate::models::note_acl::Accessor;
+use crate::models::user::DatabaseUser;
+use crate::models::workspace::{
+    Workspace as DatabaseWorkspace, WorkspaceMember, WorkspaceMemberName, WorkspaceMembership,
+};
+use crate::note::Note;
+use crate::user::User;
+use crate::MindmapDB;
+use failure::{bail, format_err};
+use rocket::http::Status;
+use rocket::request::FromRequest;
+use rocket::{request, Outcome, Request};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// The private cookie that holds the id of the workspace the user switched to
+pub const WORKSPACE_COOKIE: &str = "WID";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can edit notes and manage the members of the workspace
+    Owner,
+    /// Can create, edit and delete notes
+    Editor,
+    /// Can only read notes
+    Viewer,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn can_edit(self) -> bool {
+        self != Role::Viewer
+    }
+}
+
+impl FromStr for Role {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Role, failure::Error> {
+        Ok(match s {
+            "owner" => Role::Owner,
+            "editor" => Role::Editor,
+            "viewer" => Role::Viewer,
+            _ => bail!("Unknown role {:?}", s),
+        })
+    }
+}
+
+/// A workspace owns notes, and is shared by its members.
+///
+/// As a request guard, this is the workspace the logged in user is currently working in.
+#[derive(Serialize)]
+pub struct Workspace {
+    pub id: Uuid,
+    pub name: String,
+    /// The user that this workspace was loaded for
+    pub user_id: Uuid,
+    /// The role of that user in this workspace
+    pub role: Role,
+    /// The template that new notes start from, unless another one is chosen
+    pub default_template_id: Option<Uuid>,
+    /// The strftime format that the titles of daily notes are made with, like "%Y-%m-%d"
+    pub journal_title_format: String,
+    /// The template that daily notes start from
+    pub journal_template_id: Option<Uuid>,
+}
+
+impl Workspace {
+    fn from_membership(m: WorkspaceMembership, user_id: Uuid) -> Result<Workspace, failure::Error> {
+        Ok(Workspace {
+            id: m.workspace.id,
+            name: m.workspace.name,
+            user_id,
+            role: m.role.parse()?,
+            default_template_id: m.workspace.default_template_id,
+            journal_title_format: m.workspace.journal_title_format,
+            journal_template_id: m.workspace.journal_template_id,
+        })
+    }
+
+    /// The member of this workspace that notes are loaded for
+    pub fn accessor(&self) -> Accessor {
+        Accessor {
+            workspace_id: self.id,
+            user_id: self.user_id,
+            role: self.role.as_str().to_owned(),
+        }
+    }
+
+    /// Create a workspace, with the given user as its owner
+    pub fn create(
+        conn: &diesel::PgConnection,
+        name: &str,
+        user_id: Uuid,
+    ) -> Result<Workspace, failure::Error> {
+        transaction(conn, || {
+            let workspace = DatabaseWorkspace::create(conn, name)?;
+            WorkspaceMember {
+                workspace_id: workspace.id,
+                user_id,
+                role: Role::Owner.as_str().to_owned(),
+            }
+            .save(conn)?;
+            Ok(Workspace {
+                id: workspace.id,
+                name: workspace.name,
+                user_id,
+                role: Role::Owner,
+                default_template_id: None,
+                journal_title_format: workspace.journal_title_format,
+                journal_template_id: None,
+            })
+        })
+    }
+
+    pub fn load_all_by_member(
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<Vec<Workspace>, failure::Error> {
+        DatabaseWorkspace::load_all_by_member(conn, user_id)?
+            .into_iter()
+            .map(|m| Workspace::from_membership(m, user_id))
+            .collect()
+    }
+
+    pub fn load_by_member(
+        conn: &diesel::PgConnection,
+        id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<Workspace>, failure::Error> {
+        DatabaseWorkspace::load_by_member(conn, id, user_id)?
+            .map(|m| Workspace::from_membership(m, user_id))
+            .transpose()
+    }
+
+    /// Load the workspace the user selected, falling back to the first workspace they are a member of.
+    /// If the user is not a member of any workspace, a personal workspace is created.
+    pub fn load_current(
+        conn: &diesel::PgConnection,
+        user: &User,
+        selected: Option<Uuid>,
+    ) -> Result<Workspace, failure::Error> {
+        if let Some(id) = selected {
+            if let Some(workspace) = Workspace::load_by_member(conn, id, user.id)? {
+                return Ok(workspace);
+            }
+        }
+        match Workspace::load_all_by_member(conn, user.id)?
+            .into_iter()
+            .next()
+        {
+            Some(workspace) => Ok(workspace),
+            None => Workspace::create(conn, &user.name, user.id),
+        }
+    }
+
+    pub fn load_members(&self, conn: &diesel::PgConnection) -> Result<Vec<Member>, failure::Error> {
+        DatabaseWorkspace::load_members(conn, self.id)?
+            .into_iter()
+            .map(Member::from_database)
+            .collect()
+    }
+
+    /// Add the user with the given name to this workspace, or change their role if they are already a member
+    pub fn save_member(
+        &self,
+        conn: &diesel::PgConnection,
+        user_name: &str,
+        role: Role,
+    ) -> Result<(), failure::Error> {
+        let user = DatabaseUser::load_by_name(conn, user_name)?
+            .ok_or_else(|| format_err!("User {:?} not found", user_name))?;
+        transaction(conn, || {
+            WorkspaceMember {
+                workspace_id: self.id,
+                user_id: user.id,
+                role: role.as_str().to_owned(),
+            }
+            .save(conn)?;
+            self.ensure_owner(conn)
+        })
+    }
+
+    pub fn remove_member(
+        &self,
+        conn: &diesel::PgConnection,
+        user_id: Uuid,
+    ) -> Result<(), failure::Error> {
+        transaction(conn, || {
+            WorkspaceMember::delete(conn, self.id, user_id)?;
+            self.ensure_owner(conn)
+        })
+    }
+
+    /// Choose the template that new notes in this workspace start from, or `None` to start them empty
+    pub fn set_default_template(
+        &self,
+        conn: &diesel::PgConnection,
+        template_id: Option<Uuid>,
+    ) -> Result<(), failure::Error> {
+        if let Some(id) = template_id {
+            if Note::load_template(conn, id, &self.accessor())?.is_none() {
+                bail!("Only a template of this workspace can be its default template");
+            }
+        }
+        DatabaseWorkspace::set_default_template(conn, self.id, template_id)
+    }
+
+    /// Change how the daily notes of the journal are titled, and the template they start from
+    pub fn set_journal(
+        &self,
+        conn: &diesel::PgConnection,
+        title_format: &str,
+        template_id: Option<Uuid>,
+    ) -> Result<(), failure::Error> {
+        if !journal::is_valid_title_format(title_format) {
+            bail!("{:?} is not a valid date format", title_format);
+        }
+        if let Some(id) = template_id {
+            if Note::load_template(conn, id, &self.accessor())?.is_none() {
+                bail!("Only a template of this workspace can be its journal template");
+            }
+        }
+        DatabaseWorkspace::set_journal(conn, self.id, title_format, template_id)
+    }
+
+    /// Fail if the workspace has no owner left, so the surrounding transaction is rolled back
+    fn ensure_owner(&self, conn: &diesel::PgConnection) -> Result<(), failure::Error> {
+        if WorkspaceMember::count_by_role(conn, self.id, Role::Owner.as_str())? == 0 {
+            bail!("A workspace needs at least one owner");
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> FromRequest<'a, 'b> for Workspace {
+    type Error = failure::Error;
+
+    fn from_request(req: &'a Request<'b>) -> request::Outcome<Self, Self::Error> {
+        let user = match req.guard::<User>() {
+            Outcome::Success(user) => user,
+            Outcome::Forward(()) => return Outcome::Forward(()),
+            Outcome::Failure(f) => return Outcome::Failure(f),
+        };
+        let selected = req
+            .cookies()
+            .get_private(WORKSPACE_COOKIE)
+            .and_then(|c| Uuid::from_str(c.value()).ok());
+        let connection = MindmapDB::from_request(req).unwrap();
+        match Workspace::load_current(&connection, &user, selected) {
+            Ok(workspace) => Outcome::Success(workspace),
+            Err(e) => Outcome::Failure((Status::InternalServerError, e)),
+        }
+    }
+}
+
+/// The current workspace, if the user is allowed to edit the notes in it
+pub struct WorkspaceEditor(pub Workspace);
+
+impl std::ops::Deref for WorkspaceEditor {
+    type Target = Workspace;
+    fn deref(&self) -> &Workspace {
+        &self.0
+    }
+}
+
+impl<'a, 'b> FromRequest<'a, 'b> for WorkspaceEditor {
+    type Error = failure::Error;
+
+    fn from_request(req: &'a Request<'b>) -> request::Outcome<Self, Self::Error> {
+        match req.guard::<Workspace>() {
+            Outcome::Success(workspace) if workspace.role.can_edit() => {
+                Outcome::Success(WorkspaceEditor(workspace))
+            }
+            Outcome::Success(_) => Outcome::Failure((
+                Status::Forbidden,
+                format_err!("You are not allowed to edit notes in this workspace"),
+            )),
+            Outcome::Forward(()) => Outcome::Forward(()),
+            Outcome::Failure(f) => Outcome::Failure(f),
+        }
+    }
+}
+
+/// The current workspace, if the user is allowed to manage its members
+pub struct WorkspaceOwner(pub Workspace);
+
+impl std::ops::Deref for WorkspaceOwner {
+    type Target = Workspace;
+    fn deref(&self) -> &Workspace {
+        &self.0
+    }
+}
+
+impl<'a, 'b> FromRequest<'a, 'b> for WorkspaceOwner {
+    type Error = failure::Error;
+
+    fn from_request(req: &'a Request<'b>) -> request::Outcome<Self, Self::Error> {
+        match req.guard::<Workspace>() {
+            Outcome::Success(workspace) if workspace.role == Role::Owner => {
+                Outcome::Success(WorkspaceOwner(workspace))
+            }
+            Outcome::Success(_) => Outcome::Failure((
+                Status::Forbidden,
+                format_err!("You are not allowed to manage this workspace"),
+            )),
+            Outcome::Forward(()) => Outcome::Forward(()),
+            Outcome::Failure(f) => Outcome::Failure(f),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Member {
+    pub user_id: Uuid,
+    pub name: String,
+    pub role: Role,
+}
+
+impl Member {
+    fn from_database(m: WorkspaceMemberName) -> Result<Member, failure::Error> {
+        Ok(Member {
+            user_id: m.user_id,
+            name: m.name,
+            role: m.role.parse()?,
+        })
+    }
+}